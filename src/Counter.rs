@@ -1,9 +1,26 @@
 use iced::{
-    button, Align, Application, Button, Color, Column, Command, Container, Element,
-    HorizontalAlignment, Image, Row, Settings, Text, VerticalAlignment,
+    button, text_input, Align, Application, Button, Color, Column, Command, Container, Element,
+    HorizontalAlignment, Image, Length, Row, Settings, Subscription, Text, TextInput,
+    VerticalAlignment,
 };
+use std::collections::VecDeque;
+use std::future::Future;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
 
-mod data;
+use calculator::data::bandwidth::{Limits, Stats, Traffic};
+use calculator::data::catalog::{
+    format_age, format_size, unshare, Catalog, CatalogError, Entry, LoadError, SaveError,
+};
+use calculator::data::control::{self, Client, ControlError, Lookup, PeerInfo, Snapshot, Status};
+use calculator::data::downloads::{assemble, Download, Downloads, State};
+use calculator::data::link::{LinkError, ShareLink};
+use calculator::data::node::{self, Node, NodeEvent};
+use calculator::data::session::Session;
+use calculator::data::sharing::{self, Recipient, SharingKey};
+use calculator::data::store::{self, is_cid, DirEntry, GcStats, StoreError, Usage};
+use calculator::data::styles;
+use calculator::data::time;
 
 //use data::ipfs;
 
@@ -15,17 +32,188 @@ pub fn main() {
 
 #[derive(Default)]
 struct Counter {
-    value: i32,
+    session: Session,
+    selected: Option<usize>,
+    path_value: String,
+    status: String,
+    path_input: text_input::State,
     primary_button: button::State,
     secondary_button: button::State,
-    increment_button: button::State,
-    decrement_button: button::State,
+    disconnect_buttons: Vec<button::State>,
+    address_value: String,
+    address_input: text_input::State,
+    connect_button: button::State,
+    lookup_value: String,
+    lookup_input: text_input::State,
+    lookup_button: button::State,
+    download_value: String,
+    download_input: text_input::State,
+    download_button: button::State,
+    share_value: String,
+    share_input: text_input::State,
+    share_button: button::State,
+    link_value: String,
+    link_input: text_input::State,
+    link_button: button::State,
+    sharing_value: String,
+    sharing_input: text_input::State,
+    recipients_value: String,
+    recipients_input: text_input::State,
+    usage: Usage,
+    quota_value: String,
+    quota_input: text_input::State,
+    quota_button: button::State,
+    clear_button: button::State,
+    browsing: Vec<(String, String)>,
+    listing: Vec<Listing>,
+    up_button: button::State,
+    bandwidth: Bandwidth,
+    // set when a daemon runs the node, the window then only drives it
+    remote: Option<Client>,
+    daemon: Option<Status>,
+    // a snapshot was asked and hasn't come back yet
+    refreshing: bool,
 }
 
+// Seconds of traffic shown in the graphs
+const HISTORY: usize = 60;
+
+// Stats are written out at most this often, in seconds
+const STATS_INTERVAL: u64 = 10;
+
 #[derive(Debug, Clone, Copy)]
+enum Limit {
+    Upload,
+    Download,
+    PeerUpload,
+    PeerDownload,
+}
+
+#[derive(Debug, Default)]
+struct Bandwidth {
+    limits: Limits,
+    // bytes sent and received every second, the latest last
+    history: VecDeque<(u64, u64)>,
+    // the session totals at the previous sample, none before the first
+    totals: Option<(u64, u64)>,
+    ticks: u64,
+    upload_value: String,
+    upload_input: text_input::State,
+    download_value: String,
+    download_input: text_input::State,
+    peer_upload_value: String,
+    peer_upload_input: text_input::State,
+    peer_download_value: String,
+    peer_download_input: text_input::State,
+    limits_button: button::State,
+}
+
+impl Bandwidth {
+    // A window attached to a daemon starts from what the daemon already exchanged
+    fn sample(&mut self, traffic: &Traffic) {
+        let totals = (traffic.sent, traffic.received);
+        let previous = self.totals.unwrap_or(totals);
+
+        self.history.push_back((
+            totals.0.saturating_sub(previous.0),
+            totals.1.saturating_sub(previous.1),
+        ));
+        if self.history.len() > HISTORY {
+            self.history.pop_front();
+        }
+
+        self.totals = Some(totals);
+        self.ticks += 1;
+    }
+
+    fn value(&mut self, limit: Limit) -> &mut String {
+        match limit {
+            Limit::Upload => &mut self.upload_value,
+            Limit::Download => &mut self.download_value,
+            Limit::PeerUpload => &mut self.peer_upload_value,
+            Limit::PeerDownload => &mut self.peer_download_value,
+        }
+    }
+
+    // Values are in KB/s, 0 lifts the limit and an empty value keeps it
+    fn parse_limits(&self) -> Result<Limits, ()> {
+        let parse = |value: &str, current: u64| {
+            let value = value.trim();
+
+            if value.is_empty() {
+                Ok(current)
+            } else {
+                value
+                    .parse::<u64>()
+                    .map(|kilobytes| kilobytes * 1024)
+                    .map_err(|_| ())
+            }
+        };
+
+        Ok(Limits {
+            upload: parse(&self.upload_value, self.limits.upload)?,
+            download: parse(&self.download_value, self.limits.download)?,
+            peer_upload: parse(&self.peer_upload_value, self.limits.peer_upload)?,
+            peer_download: parse(&self.peer_download_value, self.limits.peer_download)?,
+        })
+    }
+}
+
+// An entry of the directory being browsed
+#[derive(Debug)]
+struct Listing {
+    entry: DirEntry,
+    open_button: button::State,
+}
+
+#[derive(Debug, Clone)]
 enum Message {
-    IncrementPressed,
-    DecrementPressed,
+    Loaded(Result<Catalog, LoadError>),
+    Saved(Result<(), SaveError>),
+    PathChanged(String),
+    AddPressed,
+    FileAdded(Result<Entry, CatalogError>),
+    DeletePressed,
+    EntrySelected(usize),
+    Unshared(Result<u64, CatalogError>),
+    NodeEvent(NodeEvent),
+    AddressChanged(String),
+    ConnectPressed,
+    DisconnectPressed(String),
+    LookupChanged(String),
+    LookupPressed,
+    DownloadsLoaded(Result<Downloads, LoadError>),
+    DownloadsSaved(Result<(), SaveError>),
+    DownloadChanged(String),
+    DownloadPressed,
+    PausePressed(String),
+    ResumePressed(String),
+    CancelPressed(String),
+    Assembled(String, Result<PathBuf, StoreError>),
+    Tick(Instant),
+    SharePressed,
+    ShareLinkEdited(String),
+    LinkChanged(String),
+    OpenLinkPressed,
+    RecipientsChanged(String),
+    SharingKeyEdited(String),
+    PinPressed(String),
+    Measured(Usage),
+    Evicted(Result<GcStats, StoreError>),
+    QuotaChanged(String),
+    SetQuotaPressed,
+    ClearCachePressed,
+    OpenPressed(String, String),
+    UpPressed,
+    LimitsLoaded(Result<Limits, LoadError>),
+    LimitsSaved(Result<(), SaveError>),
+    StatsLoaded(Result<Stats, LoadError>),
+    StatsSaved(Result<(), SaveError>),
+    LimitChanged(Limit, String),
+    SetLimitsPressed,
+    Attached(Result<Status, ControlError>),
+    Refreshed(Result<Snapshot, ControlError>),
+    Remote(Result<String, ControlError>),
 }
 
 #[derive(Debug)]
@@ -39,7 +227,13 @@ impl Application for Counter {
 
     // required
     fn new() -> (Self, Command<Message>) {
-        (Self::default(), Command::none())
+        let mut counter = Self::default();
+        counter.status = String::from("Looking for the Fuzzy Net daemon...");
+
+        // a running daemon owns the node and the catalog, no second node is started
+        let status = Client::new(control::DEFAULT_ADDRESS).status();
+
+        (counter, Command::perform(status, Message::Attached))
     }
     // required
     fn title(&self) -> String {
@@ -47,19 +241,129 @@ impl Application for Counter {
     }
     // required
     fn update(&mut self, message: Message) -> Command<Message> {
-        match message {
-            Message::IncrementPressed => {
-                self.value += 1;
-            }
-            Message::DecrementPressed => {
-                self.value -= 1;
-            }
-        }
+        let command = self.handle(message);
 
-        Command::none()
+        Command::batch(vec![command, self.effects()])
+    }
+
+    fn subscription(&self) -> Subscription<Message> {
+        let events = match &self.session.node {
+            Some(node) => node.events().map(Message::NodeEvent),
+            None => Subscription::none(),
+        };
+
+        // the bandwidth graphs move for as long as the node runs, an attached
+        // window refreshes what the daemon reports at the same pace
+        if self.session.node.is_some() || self.remote.is_some() {
+            Subscription::batch(vec![
+                events,
+                time::every(Duration::from_secs(1)).map(Message::Tick),
+            ])
+        } else {
+            events
+        }
     }
     // required
     fn view(&mut self) -> Element<Message> {
+        let selected = self.selected;
+        let identity = self.identity_view();
+        let peers = peers_view(
+            &self.session.peers,
+            &mut self.disconnect_buttons,
+            &mut self.address_input,
+            &self.address_value,
+            &mut self.connect_button,
+        );
+        let lookups = lookups_view(
+            &self.session.lookups,
+            &mut self.lookup_input,
+            &self.lookup_value,
+            &mut self.lookup_button,
+        );
+        let downloads = downloads_view(
+            &mut self.session.downloads.downloads,
+            &mut self.download_input,
+            &self.download_value,
+            &mut self.download_button,
+            &mut self.link_input,
+            &self.link_value,
+            &mut self.link_button,
+        );
+        let storage = storage_view(
+            &self.usage,
+            &mut self.quota_input,
+            &self.quota_value,
+            &mut self.quota_button,
+            &mut self.clear_button,
+        );
+        let bandwidth = bandwidth_view(&mut self.bandwidth, &self.session.traffic.stats);
+        let share: Element<_> = if self.share_value.is_empty() {
+            Column::new().into()
+        } else {
+            TextInput::new(
+                &mut self.share_input,
+                "",
+                &self.share_value,
+                Message::ShareLinkEdited,
+            )
+            .padding(10)
+            .into()
+        };
+        let summary = format!(
+            "{} files shared, {}",
+            self.session.catalog.entries.len(),
+            format_size(self.session.catalog.total_size())
+        );
+        let is_empty = self.session.catalog.entries.is_empty();
+
+        let files = self.session.catalog.entries.iter_mut().enumerate().fold(
+            Column::new().spacing(10).padding(20),
+            |column, (i, entry)| {
+                let row = Row::new().spacing(10).align_items(Align::Center).push(
+                    Button::new(
+                        &mut entry.select_button,
+                        Text::new(format!(
+                            "{}{}  -  {}  -  {}  -  added {}  -  {}",
+                            entry.name,
+                            if entry.directory { "/" } else { "" },
+                            format_size(entry.size),
+                            short(&entry.cid),
+                            format_age(entry.added),
+                            access(&entry.access)
+                        ))
+                        .size(20),
+                    )
+                    .on_press(Message::EntrySelected(i))
+                    .padding(8)
+                    .style(styles::Button::Filter {
+                        selected: selected == Some(i),
+                    }),
+                );
+
+                let row = if entry.directory {
+                    row.push(
+                        Button::new(&mut entry.open_button, Text::new("Open").size(16))
+                            .on_press(Message::OpenPressed(entry.name.clone(), entry.cid.clone()))
+                            .padding(8),
+                    )
+                } else {
+                    row
+                };
+
+                column.push(row)
+            },
+        );
+
+        let files: Element<_> = if !self.browsing.is_empty() {
+            browser_view(&self.browsing, &mut self.listing, &mut self.up_button).into()
+        } else if is_empty {
+            Text::new("No shared files yet, add one above.")
+                .color(Color::BLACK)
+                .into()
+        } else {
+            files.into()
+        };
+
         Column::new()
             .push(
                 Container::new(
@@ -68,6 +372,32 @@ impl Application for Counter {
                         .spacing(100)
                         //.push(Text::new(title).size(50))
                         .push(Image::new("resources/fuzzynet.png"))
+                        .push(
+                            Column::new()
+                                .spacing(10)
+                                .push(
+                                    TextInput::new(
+                                        &mut self.path_input,
+                                        "Path of the file to share",
+                                        &self.path_value,
+                                        Message::PathChanged,
+                                    )
+                                    .on_submit(Message::AddPressed)
+                                    .padding(15)
+                                    .width(Length::Units(400)),
+                                )
+                                .push(
+                                    TextInput::new(
+                                        &mut self.recipients_input,
+                                        "Sharing keys to encrypt for, empty to share publicly",
+                                        &self.recipients_value,
+                                        Message::RecipientsChanged,
+                                    )
+                                    .on_submit(Message::AddPressed)
+                                    .padding(15)
+                                    .width(Length::Units(400)),
+                                ),
+                        )
                         .push(
                             Button::new(
                                 &mut self.primary_button,
@@ -76,11 +406,10 @@ impl Application for Counter {
                                     .horizontal_alignment(HorizontalAlignment::Center)
                                     .vertical_alignment(VerticalAlignment::Top),
                             )
-                            .on_press(Message::IncrementPressed)
+                            .on_press(Message::AddPressed)
                             .padding(30)
                             .min_width(100),
                         )
-                        .push(Text::new(self.value.to_string()).size(50))
                         .push(
                             Button::new(
                                 &mut self.secondary_button,
@@ -88,45 +417,58 @@ impl Application for Counter {
                                     .color(Color::WHITE)
                                     .horizontal_alignment(HorizontalAlignment::Center),
                             )
-                            .on_press(Message::DecrementPressed)
-                            .padding(30)
-                            .min_width(100),
-                        ),
-                )
-                .style(style_nav::Container),
-            )
-            .push(
-                Container::new(
-                    Row::new()
-                        .align_items(Align::Start)
-                        .spacing(100)
-                        //.push(Text::new(title).size(50))
-                        .push(
-                            Button::new(
-                                &mut self.increment_button,
-                                Text::new("Add File")
-                                    .color(Color::WHITE)
-                                    .horizontal_alignment(HorizontalAlignment::Center)
-                                    .vertical_alignment(VerticalAlignment::Top),
-                            )
-                            .on_press(Message::IncrementPressed)
+                            .on_press(Message::DeletePressed)
                             .padding(30)
                             .min_width(100),
                         )
-                        .push(Text::new(self.value.to_string()).size(50))
                         .push(
                             Button::new(
-                                &mut self.decrement_button,
-                                Text::new("Delete File")
+                                &mut self.share_button,
+                                Text::new("Share Link")
                                     .color(Color::WHITE)
                                     .horizontal_alignment(HorizontalAlignment::Center),
                             )
-                            //.style(style_action_button::Button)
-                            .on_press(Message::DecrementPressed)
+                            .on_press(Message::SharePressed)
                             .padding(30)
-                            //.background
                             .min_width(100),
-                        ),
+                        )
+                        .push(identity),
+                )
+                .style(style_nav::Container),
+            )
+            .push(
+                Container::new(
+                    Column::new()
+                        .padding(10)
+                        .spacing(10)
+                        .push(
+                            Row::new()
+                                .align_items(Align::Start)
+                                .spacing(100)
+                                .push(Text::new(summary).size(30))
+                                .push(Text::new(&self.status).size(30)),
+                        )
+                        .push(share)
+                        .push(
+                            Row::new()
+                                .spacing(10)
+                                .align_items(Align::Center)
+                                .push(Text::new("Your sharing key").size(16))
+                                .push(
+                                    TextInput::new(
+                                        &mut self.sharing_input,
+                                        "",
+                                        &self.sharing_value,
+                                        Message::SharingKeyEdited,
+                                    )
+                                    .padding(10),
+                                ),
+                        )
+                        .push(peers)
+                        .push(lookups)
+                        .push(downloads)
+                        .push(bandwidth)
+                        .push(storage),
                 )
                 .style(style_action_nav::Container),
             )
@@ -137,21 +479,9 @@ impl Application for Counter {
                         .spacing(150)
                         //.background(Color::BLACK)
                         //.push(Text::new(title).size(50))
-                        .push(
-                            Text::new(
-                                "It was originally born as part of Coffee, an opinionated \
-                         2D game engine for Rust.",
-                            )
-                            .color(Color::BLACK),
-                        )
-                        .push(
-                            Text::new("some middle content."), // .horizontal_alignment(HorizontalAlignment::Center),
-                        )
+                        .push(files)
                         //Image::new("resources/fuzzynet_large.png")
-                        .push(Image::new("resources/fuzzynet_large.png"))
-                        .push(
-                            Text::new("LSTE HAVE SOME MAIN CONTENT."), //.horizontal_alignment(HorizontalAlignment::Center),
-                        ),
+                        .push(Image::new("resources/fuzzynet_large.png")),
                 )
                 .style(style_main::Container),
             )
@@ -159,6 +489,1328 @@ impl Application for Counter {
     }
 }
 
+impl Counter {
+    fn handle(&mut self, message: Message) -> Command<Message> {
+        match message {
+            Message::Loaded(Ok(mut catalog)) => {
+                let migrated = catalog.migrate(&self.session.store);
+                self.session.catalog = catalog;
+                self.session.announce();
+
+                if migrated > 0 {
+                    return Command::perform(self.session.catalog.clone().save(), Message::Saved);
+                }
+            }
+            Message::Loaded(Err(LoadError::FileError)) => {}
+            Message::Loaded(Err(LoadError::FormatError)) => {
+                self.status = String::from("The catalog could not be read");
+            }
+            Message::Saved(Err(_)) => {
+                self.status = String::from("The catalog could not be saved");
+            }
+            Message::Saved(Ok(())) => {}
+            Message::PathChanged(value) => {
+                self.path_value = value;
+            }
+            Message::AddPressed => {
+                if !self.path_value.is_empty() {
+                    let recipients = match self.recipients() {
+                        Ok(recipients) => recipients,
+                        Err(key) => {
+                            self.status = format!("{} is not a sharing key", key);
+                            return Command::none();
+                        }
+                    };
+
+                    self.status = format!("Hashing {}...", self.path_value);
+
+                    if let Some(client) = &self.remote {
+                        // the daemon may well run from another directory
+                        let path = match std::fs::canonicalize(self.path_value.trim()) {
+                            Ok(path) => path,
+                            Err(_) => {
+                                self.status =
+                                    String::from("This path is neither a file nor a directory");
+                                return Command::none();
+                            }
+                        };
+                        let keys = recipients.iter().map(Recipient::export).collect();
+                        self.path_value.clear();
+                        self.recipients_value.clear();
+
+                        return Command::perform(client.clone().add(path, keys), |result| {
+                            Message::Remote(result.map(|entry| format!("{} added", entry.name)))
+                        });
+                    }
+
+                    return Command::perform(
+                        Entry::from_path(
+                            PathBuf::from(self.path_value.trim()),
+                            self.session.store.clone(),
+                            recipients,
+                        ),
+                        Message::FileAdded,
+                    );
+                }
+            }
+            Message::FileAdded(Ok(entry)) => {
+                let name = entry.name.clone();
+
+                match self.session.add(entry) {
+                    Ok(()) => {
+                        self.status = format!("{} added", name);
+                        self.path_value.clear();
+                        self.recipients_value.clear();
+
+                        return Command::batch(vec![
+                            Command::perform(self.session.catalog.clone().save(), Message::Saved),
+                            self.measure(),
+                        ]);
+                    }
+                    Err(_) => {
+                        self.status = format!("{} is already shared", name);
+                    }
+                }
+            }
+            Message::FileAdded(Err(error)) => {
+                self.status = match error {
+                    CatalogError::NotAFile => {
+                        String::from("This path is neither a file nor a directory")
+                    }
+                    CatalogError::ReadError => String::from("The file could not be read"),
+                    CatalogError::AlreadyShared => String::from("This file is already shared"),
+                    CatalogError::StoreError => String::from("The file could not be stored"),
+                };
+            }
+            Message::DeletePressed => {
+                if let Some(entry) = self
+                    .selected
+                    .take()
+                    .and_then(|i| self.session.catalog.remove(i))
+                {
+                    if let Some(client) = &self.remote {
+                        let name = entry.name;
+
+                        return Command::perform(client.clone().remove(entry.cid), move |result| {
+                            Message::Remote(result.map(|()| format!("{} removed", name)))
+                        });
+                    }
+
+                    self.status = format!("{} removed", entry.name);
+                    self.session.announce();
+
+                    return Command::batch(vec![
+                        Command::perform(self.session.catalog.clone().save(), Message::Saved),
+                        Command::perform(
+                            unshare(self.session.store.clone(), entry.cid),
+                            Message::Unshared,
+                        ),
+                    ]);
+                }
+            }
+            Message::EntrySelected(i) => {
+                self.selected = Some(i);
+            }
+            Message::Unshared(Ok(freed)) => {
+                if freed > 0 {
+                    self.status = format!("{}, {} freed", self.status, format_size(freed));
+                }
+
+                return self.measure();
+            }
+            Message::Unshared(Err(_)) => {
+                self.status = String::from("The file blocks could not be removed");
+            }
+            Message::NodeEvent(event) => self.node_event(event),
+            Message::AddressChanged(value) => {
+                self.address_value = value;
+            }
+            Message::ConnectPressed => {
+                if let Some(client) = &self.remote {
+                    let address = std::mem::replace(&mut self.address_value, String::new());
+                    let done = format!("Connecting to {}...", address);
+
+                    return remote(client.clone().connect(address), done);
+                }
+
+                if let Some(node) = &self.session.node {
+                    match node.dial(&self.address_value) {
+                        Ok(()) => {
+                            self.status = format!("Connecting to {}...", self.address_value);
+                            self.address_value.clear();
+                        }
+                        Err(_) => self.status = String::from("This is not a valid address"),
+                    }
+                }
+            }
+            Message::DisconnectPressed(peer) => {
+                if let Some(client) = &self.remote {
+                    let done = format!("Disconnected from {}", peer);
+
+                    return remote(client.clone().disconnect(peer), done);
+                }
+
+                if let Some(node) = &self.session.node {
+                    let _ = node.disconnect(&peer);
+                }
+            }
+            Message::LookupChanged(value) => {
+                self.lookup_value = value;
+            }
+            Message::LookupPressed => {
+                let cid = self.lookup_value.trim().to_owned();
+
+                if let (Some(client), false) = (&self.remote, cid.is_empty()) {
+                    self.lookup_value.clear();
+                    let done = format!("Looking up {}...", cid);
+
+                    return remote(client.clone().lookup(cid), done);
+                }
+
+                if !cid.is_empty() {
+                    match self.session.lookup(cid) {
+                        Ok(()) => self.lookup_value.clear(),
+                        Err(error) => self.status = error.to_string(),
+                    }
+                }
+            }
+            Message::DownloadsLoaded(Ok(downloads)) => {
+                self.session.downloads = downloads;
+                self.session.resume_downloads();
+            }
+            Message::DownloadsLoaded(Err(LoadError::FileError)) => {}
+            Message::DownloadsLoaded(Err(LoadError::FormatError)) => {
+                self.status = String::from("The downloads could not be read");
+            }
+            Message::DownloadsSaved(Err(_)) => {
+                self.status = String::from("The downloads could not be saved");
+            }
+            Message::DownloadsSaved(Ok(())) => {}
+            Message::DownloadChanged(value) => {
+                self.download_value = value;
+            }
+            Message::DownloadPressed => {
+                let root = self.download_value.trim().to_owned();
+
+                if !is_cid(&root) {
+                    self.status = String::from("This is not a valid content ID");
+                } else {
+                    self.download_value.clear();
+
+                    return self.start_download(ShareLink::new(root));
+                }
+            }
+            Message::PausePressed(root) => {
+                if let Some(client) = &self.remote {
+                    return remote(client.clone().pause(root), String::from("Paused"));
+                }
+
+                if let Err(error) = self.session.pause(&root) {
+                    self.status = error.to_string();
+                }
+            }
+            Message::ResumePressed(root) => {
+                if let Some(client) = &self.remote {
+                    return remote(client.clone().resume(root), String::from("Resumed"));
+                }
+
+                if let Err(error) = self.session.resume(&root) {
+                    self.status = error.to_string();
+                }
+            }
+            Message::CancelPressed(root) => {
+                if let Some(client) = &self.remote {
+                    return remote(client.clone().cancel(root), String::from("Cancelled"));
+                }
+
+                match self.session.cancel(&root) {
+                    Ok(download) => {
+                        self.status = format!("{} cancelled", download.name);
+
+                        return Command::perform(
+                            store::evict(self.session.store.clone()),
+                            Message::Evicted,
+                        );
+                    }
+                    Err(error) => self.status = error.to_string(),
+                }
+            }
+            Message::Assembled(root, result) => {
+                if let Some(saved) = self.session.assembled(&root, result) {
+                    self.status = saved;
+                }
+
+                return self.measure();
+            }
+            Message::Tick(now) => {
+                for download in &mut self.session.downloads.downloads {
+                    download.tick(now);
+                }
+
+                // an attached window samples what the daemon reports instead, and
+                // leaves the stats to it
+                if self.session.node.is_none() {
+                    return self.refresh();
+                }
+
+                self.bandwidth.sample(&self.session.traffic);
+
+                let mut commands = Vec::new();
+
+                if self.bandwidth.ticks % STATS_INTERVAL == 0 && self.session.traffic.take_changed()
+                {
+                    commands.push(Command::perform(
+                        self.session.traffic.stats.clone().save(),
+                        Message::StatsSaved,
+                    ));
+                }
+
+                return Command::batch(commands);
+            }
+            Message::Attached(Ok(status)) => {
+                self.status = String::from("Attached to the Fuzzy Net daemon");
+                self.remote = Some(Client::new(control::DEFAULT_ADDRESS));
+                self.load_sharing();
+                self.daemon = Some(status);
+
+                return self.refresh();
+            }
+            Message::Attached(Err(_)) => {
+                self.status.clear();
+
+                match Node::start(self.session.store.clone()) {
+                    Ok(node) => self.session.node = Some(node),
+                    Err(_) => self.status = String::from("The network node could not start"),
+                }
+                self.load_sharing();
+
+                let evict = store::evict(self.session.store.clone());
+
+                return Command::batch(vec![
+                    Command::perform(Catalog::load(), Message::Loaded),
+                    Command::perform(Downloads::load(), Message::DownloadsLoaded),
+                    Command::perform(Limits::load(), Message::LimitsLoaded),
+                    Command::perform(Stats::load(), Message::StatsLoaded),
+                    Command::perform(evict, Message::Evicted),
+                ]);
+            }
+            Message::Refreshed(Ok(snapshot)) => {
+                self.refreshing = false;
+                let mut files = snapshot.files;
+
+                // a press in progress isn't lost when the entries are replaced
+                for entry in &mut files.entries {
+                    if let Some(known) = self
+                        .session
+                        .catalog
+                        .entries
+                        .iter_mut()
+                        .find(|known| known.cid == entry.cid)
+                    {
+                        entry.select_button =
+                            std::mem::replace(&mut known.select_button, button::State::new());
+                        entry.open_button =
+                            std::mem::replace(&mut known.open_button, button::State::new());
+                    }
+                }
+
+                if self.selected.map_or(false, |i| i >= files.entries.len()) {
+                    self.selected = None;
+                }
+                self.session.catalog = files;
+
+                // the store is shared with the daemon, progress is read from it
+                self.session.downloads = snapshot.downloads;
+                for download in &mut self.session.downloads.downloads {
+                    download.refresh(&self.session.store);
+                }
+
+                self.session.peers = snapshot.peers;
+                self.session.lookups = snapshot.lookups;
+                self.session.listening = snapshot.status.listening.clone();
+                self.session.traffic = snapshot.traffic;
+                self.bandwidth.sample(&self.session.traffic);
+                self.daemon = Some(snapshot.status);
+            }
+            Message::Refreshed(Err(error)) => {
+                self.refreshing = false;
+                self.status = error.to_string();
+            }
+            Message::Remote(Ok(status)) => {
+                self.status = status;
+
+                return self.refresh();
+            }
+            Message::Remote(Err(error)) => {
+                self.status = error.to_string();
+            }
+            Message::LimitsLoaded(Ok(limits)) => {
+                self.bandwidth.limits = limits;
+
+                if let Some(node) = &self.session.node {
+                    let _ = node.set_limits(limits);
+                }
+            }
+            Message::LimitsLoaded(Err(LoadError::FileError)) => {}
+            Message::LimitsLoaded(Err(LoadError::FormatError)) => {
+                self.status = String::from("The bandwidth limits could not be read");
+            }
+            Message::LimitsSaved(Err(_)) => {
+                self.status = String::from("The bandwidth limits could not be saved");
+            }
+            Message::LimitsSaved(Ok(())) => {}
+            Message::StatsLoaded(Ok(stats)) => {
+                // what was recorded before the file was read is kept
+                let recorded = std::mem::replace(&mut self.session.traffic.stats, stats);
+                self.session.traffic.stats.merge(recorded);
+            }
+            Message::StatsLoaded(Err(LoadError::FileError)) => {}
+            Message::StatsLoaded(Err(LoadError::FormatError)) => {
+                self.status = String::from("The bandwidth stats could not be read");
+            }
+            Message::StatsSaved(Err(_)) => {
+                self.status = String::from("The bandwidth stats could not be saved");
+            }
+            Message::StatsSaved(Ok(())) => {}
+            Message::LimitChanged(limit, value) => {
+                *self.bandwidth.value(limit) = value;
+            }
+            Message::SetLimitsPressed => match self.bandwidth.parse_limits() {
+                Ok(limits) => {
+                    self.bandwidth.limits = limits;
+                    for limit in &[
+                        Limit::Upload,
+                        Limit::Download,
+                        Limit::PeerUpload,
+                        Limit::PeerDownload,
+                    ] {
+                        self.bandwidth.value(*limit).clear();
+                    }
+
+                    // the daemon saves them itself
+                    if let Some(client) = &self.remote {
+                        let done = String::from("Bandwidth limits set");
+
+                        return remote(client.clone().set_limits(limits), done);
+                    }
+
+                    if let Some(node) = &self.session.node {
+                        let _ = node.set_limits(limits);
+                    }
+
+                    return Command::perform(limits.save(), Message::LimitsSaved);
+                }
+                Err(()) => {
+                    self.status = String::from("Limits are in KB/s, 0 for no limit");
+                }
+            },
+            Message::SharePressed => {
+                let catalog = &self.session.catalog;
+
+                match self.selected.and_then(|i| catalog.entries.get(i)) {
+                    Some(entry) => {
+                        let mut link = ShareLink::new(entry.cid.clone());
+                        link.name = Some(entry.name.clone());
+
+                        if let Some(peer_id) = self.peer_id() {
+                            // a loopback address is of no use to somebody else
+                            link.via = self
+                                .session
+                                .listening
+                                .iter()
+                                .filter(|address| {
+                                    !address.starts_with("/ip4/127.")
+                                        && !address.starts_with("/ip6/::1/")
+                                })
+                                .map(|address| format!("{}/p2p/{}", address, peer_id))
+                                .collect();
+                        }
+
+                        self.share_value = link.to_string();
+                        self.status = format!("Share link for {} ready below", entry.name);
+                    }
+                    None => self.status = String::from("Select a file to share first"),
+                }
+            }
+            // the link is shown in a text input only so that it can be selected and copied
+            Message::ShareLinkEdited(_) => {}
+            Message::RecipientsChanged(value) => {
+                self.recipients_value = value;
+            }
+            // shown in a text input so that it can be copied and given to others
+            Message::SharingKeyEdited(_) => {}
+            Message::PinPressed(root) => {
+                if let Some(download) = self.session.downloads.find(&root) {
+                    let pinned = !download.pinned;
+
+                    if let Some(client) = &self.remote {
+                        let done = String::from(if pinned { "Pinned" } else { "Unpinned" });
+
+                        return remote(client.clone().pin(root, pinned), done);
+                    }
+                    if let Err(error) = self.session.pin(&root, pinned) {
+                        self.status = error.to_string();
+                    }
+
+                    return Command::perform(
+                        store::evict(self.session.store.clone()),
+                        Message::Evicted,
+                    );
+                }
+            }
+            Message::Measured(usage) => {
+                self.usage = usage;
+            }
+            Message::Evicted(Ok(stats)) => {
+                if stats.removed > 0 {
+                    self.status = evicted(&stats);
+                }
+
+                return self.measure();
+            }
+            Message::Evicted(Err(_)) => {
+                self.status = String::from("The cache could not be cleaned up");
+            }
+            Message::QuotaChanged(value) => {
+                self.quota_value = value;
+            }
+            Message::SetQuotaPressed => match self.quota_value.trim().parse::<u64>() {
+                Ok(megabytes) if megabytes > 0 => {
+                    // the daemon evicts from the store it is using
+                    if let Some(client) = &self.remote {
+                        self.quota_value.clear();
+
+                        return Command::perform(
+                            client.clone().set_quota(megabytes * 1024 * 1024),
+                            |result| Message::Remote(result.map(|stats| evicted(&stats))),
+                        );
+                    }
+
+                    if self
+                        .session
+                        .store
+                        .set_quota(megabytes * 1024 * 1024)
+                        .is_err()
+                    {
+                        self.status = String::from("The quota could not be saved");
+                    }
+                    self.quota_value.clear();
+
+                    return Command::perform(
+                        store::evict(self.session.store.clone()),
+                        Message::Evicted,
+                    );
+                }
+                _ => self.status = String::from("The quota is a number of megabytes"),
+            },
+            Message::ClearCachePressed => {
+                if let Some(client) = &self.remote {
+                    return Command::perform(client.clone().clear_cache(), |result| {
+                        Message::Remote(result.map(|stats| evicted(&stats)))
+                    });
+                }
+
+                return Command::perform(
+                    store::clear_cache(self.session.store.clone()),
+                    Message::Evicted,
+                );
+            }
+            Message::OpenPressed(name, cid) => match self.session.store.directory(&cid) {
+                Ok(directory) => {
+                    self.browsing.push((name, cid));
+                    self.list(directory.entries);
+                }
+                Err(_) => self.status = String::from("This directory is not in the store"),
+            },
+            Message::UpPressed => {
+                self.browsing.pop();
+
+                let entries = match self.browsing.last() {
+                    Some((_, cid)) => self
+                        .session
+                        .store
+                        .directory(cid)
+                        .map(|directory| directory.entries)
+                        .unwrap_or_default(),
+                    None => Vec::new(),
+                };
+                self.list(entries);
+            }
+            Message::LinkChanged(value) => {
+                self.link_value = value;
+            }
+            Message::OpenLinkPressed => match ShareLink::parse(&self.link_value) {
+                Ok(link) => {
+                    self.link_value.clear();
+
+                    return self.start_download(link);
+                }
+                Err(error) => {
+                    self.status = match error {
+                        LinkError::NotALink => String::from("This is not a Fuzzy Net link"),
+                        LinkError::UnsupportedVersion(version) => {
+                            format!("Links of version {} are not supported", version)
+                        }
+                        LinkError::InvalidCid => String::from("The link has an invalid content ID"),
+                        LinkError::InvalidQuery => String::from("The link is malformed"),
+                    };
+                }
+            },
+        }
+
+        Command::none()
+    }
+
+    fn node_event(&mut self, event: NodeEvent) {
+        if let Some(notice) = self.session.node_event(event) {
+            self.status = notice;
+        }
+    }
+
+    // Signed with the node identity, which the daemon shares with the window
+    fn load_sharing(&mut self) {
+        let sharing = node::load_identity()
+            .ok()
+            .and_then(|identity| SharingKey::load_or_generate(&identity).ok());
+
+        match sharing {
+            Some(sharing) => {
+                self.sharing_value = sharing.recipient().export();
+                self.session.sharing = Some(sharing);
+            }
+            None => self.status = String::from("The sharing key could not be loaded"),
+        }
+    }
+
+    // The id of the node sharing the files, whether it runs here or in the daemon
+    fn peer_id(&self) -> Option<String> {
+        match (&self.session.node, &self.daemon) {
+            (Some(node), _) => Some(node.peer_id.clone()),
+            (None, Some(daemon)) => Some(daemon.peer_id.clone()),
+            (None, None) => None,
+        }
+    }
+
+    // Asks the daemon what changed, unless the previous snapshot is still on its way
+    fn refresh(&mut self) -> Command<Message> {
+        match (&self.remote, self.refreshing) {
+            (Some(client), false) => {
+                self.refreshing = true;
+                Command::perform(client.clone().snapshot(), Message::Refreshed)
+            }
+            _ => Command::none(),
+        }
+    }
+
+    // Peers given by a link are asked directly, otherwise the providers are found on the DHT
+    fn start_download(&mut self, link: ShareLink) -> Command<Message> {
+        if let Some(client) = &self.remote {
+            return Command::perform(client.clone().fetch(link.to_string()), |result| {
+                Message::Remote(
+                    result.map(|download| format!("{} added to the downloads", download.name)),
+                )
+            });
+        }
+
+        if let Err(error) = self.session.start_download(link) {
+            self.status = error.to_string();
+        }
+
+        Command::none()
+    }
+
+    // Saves the downloads the session changed and writes out those with every block
+    fn effects(&mut self) -> Command<Message> {
+        let store = self.session.store.clone();
+        let sharing = self.session.sharing.clone();
+
+        let mut commands: Vec<Command<Message>> = self
+            .session
+            .take_complete()
+            .into_iter()
+            .map(|(root, destination)| {
+                Command::perform(
+                    assemble(store.clone(), root.clone(), destination, sharing.clone()),
+                    move |result| Message::Assembled(root.clone(), result),
+                )
+            })
+            .collect();
+
+        if self.session.take_changed() {
+            commands.push(self.save_downloads());
+        }
+
+        Command::batch(commands)
+    }
+
+    // The sharing keys typed in for the next file
+    fn recipients(&self) -> Result<Vec<Recipient>, String> {
+        sharing::recipients(
+            std::iter::once(self.recipients_value.as_str()),
+            self.session.sharing.as_ref(),
+        )
+    }
+
+    fn list(&mut self, entries: Vec<DirEntry>) {
+        self.listing = entries
+            .into_iter()
+            .map(|entry| Listing {
+                entry,
+                open_button: button::State::new(),
+            })
+            .collect();
+    }
+
+    fn measure(&self) -> Command<Message> {
+        Command::perform(store::usage(self.session.store.clone()), Message::Measured)
+    }
+
+    fn save_downloads(&self) -> Command<Message> {
+        Command::perform(
+            self.session.downloads.clone().save(),
+            Message::DownloadsSaved,
+        )
+    }
+
+    // Local PeerId and the full addresses other peers can dial
+    fn identity_view(&self) -> Column<'static, Message> {
+        let (peer_id, routing) = match (&self.session.node, &self.daemon) {
+            (Some(node), _) => (
+                &node.peer_id,
+                format!("DHT: {} peers in routing table", self.session.routing),
+            ),
+            (None, Some(daemon)) => (&daemon.peer_id, String::from("Run by the daemon")),
+            (None, None) => return Column::new().push(Text::new("Offline").size(16)),
+        };
+
+        self.session.listening.iter().fold(
+            Column::new()
+                .spacing(5)
+                .push(Text::new(format!("Peer ID: {}", peer_id)).size(16))
+                .push(Text::new(routing).size(14)),
+            |column, address| {
+                column.push(Text::new(format!("{}/p2p/{}", address, peer_id)).size(14))
+            },
+        )
+    }
+}
+
+// An action done by the daemon, the window refreshes once it answered
+fn remote(
+    request: impl Future<Output = Result<(), ControlError>> + Send + 'static,
+    done: String,
+) -> Command<Message> {
+    Command::perform(request, move |result| {
+        Message::Remote(result.map(|()| done.clone()))
+    })
+}
+
+fn evicted(stats: &GcStats) -> String {
+    format!(
+        "{} cached blocks evicted, {} freed",
+        stats.removed,
+        format_size(stats.freed)
+    )
+}
+
+// One directory of a shared tree, entered from the catalog
+fn browser_view<'a>(
+    browsing: &[(String, String)],
+    listing: &'a mut [Listing],
+    up_button: &'a mut button::State,
+) -> Column<'a, Message> {
+    let path: Vec<&str> = browsing.iter().map(|(name, _)| name.as_str()).collect();
+    let header = Row::new()
+        .spacing(20)
+        .align_items(Align::Center)
+        .push(
+            Button::new(up_button, Text::new("Up").size(16))
+                .on_press(Message::UpPressed)
+                .padding(8),
+        )
+        .push(Text::new(path.join(" / ")).size(20).color(Color::BLACK));
+
+    listing.iter_mut().fold(
+        Column::new().spacing(10).padding(20).push(header),
+        |column, listing| {
+            let entry = &listing.entry;
+            let row = Row::new().spacing(10).align_items(Align::Center).push(
+                Text::new(format!(
+                    "{}{}  -  {}  -  {}",
+                    entry.name,
+                    if entry.directory { "/" } else { "" },
+                    format_size(entry.size),
+                    short(&entry.cid)
+                ))
+                .size(20)
+                .color(Color::BLACK),
+            );
+
+            let row = if entry.directory {
+                let message = Message::OpenPressed(entry.name.clone(), entry.cid.clone());
+
+                row.push(
+                    Button::new(&mut listing.open_button, Text::new("Open").size(16))
+                        .on_press(message)
+                        .padding(8),
+                )
+            } else {
+                row
+            };
+
+            column.push(row)
+        },
+    )
+}
+
+fn lookups_view<'a>(
+    lookups: &[Lookup],
+    lookup_input: &'a mut text_input::State,
+    lookup_value: &str,
+    lookup_button: &'a mut button::State,
+) -> Column<'a, Message> {
+    let search = Row::new()
+        .spacing(10)
+        .push(
+            TextInput::new(
+                lookup_input,
+                "Content ID to look up",
+                lookup_value,
+                Message::LookupChanged,
+            )
+            .on_submit(Message::LookupPressed)
+            .padding(10)
+            .width(Length::Units(500)),
+        )
+        .push(
+            Button::new(
+                lookup_button,
+                Text::new("Find providers").color(Color::WHITE),
+            )
+            .on_press(Message::LookupPressed)
+            .padding(10),
+        );
+
+    lookups
+        .iter()
+        .fold(Column::new().spacing(5).push(search), |column, lookup| {
+            let state = match &lookup.providers {
+                None => String::from("searching..."),
+                Some(providers) if providers.is_empty() => String::from("no provider found"),
+                Some(providers) => {
+                    format!("{} providers: {}", providers.len(), providers.join(", "))
+                }
+            };
+
+            column.push(Text::new(format!("{}  -  {}", lookup.cid, state)).size(16))
+        })
+}
+
+fn downloads_view<'a>(
+    downloads: &'a mut [Download],
+    download_input: &'a mut text_input::State,
+    download_value: &str,
+    download_button: &'a mut button::State,
+    link_input: &'a mut text_input::State,
+    link_value: &str,
+    link_button: &'a mut button::State,
+) -> Column<'a, Message> {
+    let fetch = Row::new()
+        .spacing(10)
+        .push(
+            TextInput::new(
+                download_input,
+                "Content ID to download",
+                download_value,
+                Message::DownloadChanged,
+            )
+            .on_submit(Message::DownloadPressed)
+            .padding(10)
+            .width(Length::Units(500)),
+        )
+        .push(
+            Button::new(download_button, Text::new("Download").color(Color::WHITE))
+                .on_press(Message::DownloadPressed)
+                .padding(10),
+        );
+    let open = Row::new()
+        .spacing(10)
+        .push(
+            TextInput::new(
+                link_input,
+                "fuzzynet://v1/...",
+                link_value,
+                Message::LinkChanged,
+            )
+            .on_submit(Message::OpenLinkPressed)
+            .padding(10)
+            .width(Length::Units(500)),
+        )
+        .push(
+            Button::new(link_button, Text::new("Open link").color(Color::WHITE))
+                .on_press(Message::OpenLinkPressed)
+                .padding(10),
+        );
+
+    downloads.iter_mut().fold(
+        Column::new()
+            .spacing(5)
+            .push(Text::new("Downloads").size(20))
+            .push(fetch)
+            .push(open),
+        |column, download| {
+            let state = match &download.state {
+                State::Locating => String::from("looking for providers..."),
+                State::Fetching => format!(
+                    "{}/s from {} peers",
+                    format_size(download.rate),
+                    download.peers.len()
+                ),
+                State::Paused => String::from("paused"),
+                State::Complete(path) => format!("saved to {}", path.display()),
+                State::Failed(reason) => format!("failed, {}", reason),
+            };
+            let progress = download.progress();
+
+            let (label, message) = match download.state {
+                State::Locating | State::Fetching => {
+                    ("Pause", Message::PausePressed(download.root.clone()))
+                }
+                _ => ("Resume", Message::ResumePressed(download.root.clone())),
+            };
+
+            let pin = if download.pinned { "Unpin" } else { "Pin" };
+            let actions = Row::new().spacing(10).push(
+                Button::new(&mut download.pin_button, Text::new(pin).size(16))
+                    .on_press(Message::PinPressed(download.root.clone()))
+                    .padding(5)
+                    .style(styles::Button::Filter {
+                        selected: download.pinned,
+                    }),
+            );
+            let actions = match download.state {
+                State::Complete(_) => actions,
+                _ => actions.push(
+                    Button::new(&mut download.pause_button, Text::new(label).size(16))
+                        .on_press(message)
+                        .padding(5),
+                ),
+            };
+            let actions = actions.push(
+                Button::new(&mut download.cancel_button, Text::new("Cancel").size(16))
+                    .on_press(Message::CancelPressed(download.root.clone()))
+                    .padding(5)
+                    .style(styles::Button::Destructive),
+            );
+
+            column.push(
+                Row::new()
+                    .spacing(20)
+                    .align_items(Align::Center)
+                    .push(
+                        Text::new(format!(
+                            "{}{}  -  {}",
+                            download.name,
+                            if download.directory { "/" } else { "" },
+                            format_size(download.size)
+                        ))
+                        .size(16)
+                        .width(Length::Units(300)),
+                    )
+                    .push(progress_bar(progress))
+                    .push(
+                        Text::new(format!(
+                            "{} / {} chunks  -  {}",
+                            download.present,
+                            download.chunks.len(),
+                            state
+                        ))
+                        .size(16),
+                    )
+                    .push(actions),
+            )
+        },
+    )
+}
+
+fn progress_bar<'a>(progress: f32) -> Row<'a, Message> {
+    let filled = (progress * 100.0).round() as u64;
+
+    bar(
+        200,
+        &[
+            (filled, style_bar::Bar::Filled),
+            (100 - filled.min(100), style_bar::Bar::Remaining),
+        ],
+    )
+}
+
+// Segments sized by their share of the total, the empty ones are left out
+fn bar<'a>(width: u16, segments: &[(u64, style_bar::Bar)]) -> Row<'a, Message> {
+    let total: u64 = segments.iter().map(|(value, _)| value).sum();
+
+    segments.iter().fold(
+        Row::new()
+            .width(Length::Units(width))
+            .height(Length::Units(12)),
+        |row, (value, style)| {
+            let portion = if total == 0 { 0 } else { value * 1000 / total };
+
+            if portion == 0 {
+                row
+            } else {
+                row.push(
+                    Container::new(Column::new())
+                        .width(Length::FillPortion(portion as u16))
+                        .height(Length::Fill)
+                        .style(*style),
+                )
+            }
+        },
+    )
+}
+
+// One column per sample, scaled to the largest one
+fn graph<'a>(samples: &[u64], style: style_bar::Bar) -> Row<'a, Message> {
+    let max = samples.iter().cloned().max().unwrap_or(0).max(1);
+
+    samples.iter().fold(
+        Row::new()
+            .spacing(1)
+            .height(Length::Units(40))
+            .align_items(Align::End),
+        |row, value| {
+            let portion = (value * 1000 / max) as u16;
+            // the empty part is on top, left out like the bar itself when it has no height
+            let column = [(1000 - portion, None), (portion, Some(style))]
+                .iter()
+                .fold(
+                    Column::new().width(Length::Units(5)).height(Length::Fill),
+                    |column, (portion, style)| match (portion, style) {
+                        (0, _) => column,
+                        (portion, None) => {
+                            column.push(Column::new().height(Length::FillPortion(*portion)))
+                        }
+                        (portion, Some(style)) => column.push(
+                            Container::new(Column::new())
+                                .width(Length::Fill)
+                                .height(Length::FillPortion(*portion))
+                                .style(*style),
+                        ),
+                    },
+                );
+
+            row.push(column)
+        },
+    )
+}
+
+fn format_rate(bytes: u64) -> String {
+    format!("{}/s", format_size(bytes))
+}
+
+fn format_limit(bytes: u64) -> String {
+    if bytes == 0 {
+        String::from("none")
+    } else {
+        format_rate(bytes)
+    }
+}
+
+fn bandwidth_view<'a>(bandwidth: &'a mut Bandwidth, stats: &Stats) -> Column<'a, Message> {
+    let sent: Vec<u64> = bandwidth.history.iter().map(|(sent, _)| *sent).collect();
+    let received: Vec<u64> = bandwidth
+        .history
+        .iter()
+        .map(|(_, received)| *received)
+        .collect();
+    let limits = bandwidth.limits;
+
+    let graphs = Row::new()
+        .spacing(20)
+        .push(
+            Column::new()
+                .spacing(5)
+                .push(
+                    Text::new(format!(
+                        "Upload {}",
+                        format_rate(sent.last().cloned().unwrap_or(0))
+                    ))
+                    .size(16),
+                )
+                .push(graph(&sent, style_bar::Bar::Upload)),
+        )
+        .push(
+            Column::new()
+                .spacing(5)
+                .push(
+                    Text::new(format!(
+                        "Download {}",
+                        format_rate(received.last().cloned().unwrap_or(0))
+                    ))
+                    .size(16),
+                )
+                .push(graph(&received, style_bar::Bar::Download)),
+        );
+
+    let inputs = Row::new()
+        .spacing(10)
+        .push(
+            TextInput::new(
+                &mut bandwidth.upload_input,
+                "Upload KB/s",
+                &bandwidth.upload_value,
+                |value| Message::LimitChanged(Limit::Upload, value),
+            )
+            .on_submit(Message::SetLimitsPressed)
+            .padding(10)
+            .width(Length::Units(120)),
+        )
+        .push(
+            TextInput::new(
+                &mut bandwidth.download_input,
+                "Download KB/s",
+                &bandwidth.download_value,
+                |value| Message::LimitChanged(Limit::Download, value),
+            )
+            .on_submit(Message::SetLimitsPressed)
+            .padding(10)
+            .width(Length::Units(120)),
+        )
+        .push(
+            TextInput::new(
+                &mut bandwidth.peer_upload_input,
+                "Per peer up",
+                &bandwidth.peer_upload_value,
+                |value| Message::LimitChanged(Limit::PeerUpload, value),
+            )
+            .on_submit(Message::SetLimitsPressed)
+            .padding(10)
+            .width(Length::Units(120)),
+        )
+        .push(
+            TextInput::new(
+                &mut bandwidth.peer_download_input,
+                "Per peer down",
+                &bandwidth.peer_download_value,
+                |value| Message::LimitChanged(Limit::PeerDownload, value),
+            )
+            .on_submit(Message::SetLimitsPressed)
+            .padding(10)
+            .width(Length::Units(120)),
+        )
+        .push(
+            Button::new(
+                &mut bandwidth.limits_button,
+                Text::new("Set limits").color(Color::WHITE),
+            )
+            .on_press(Message::SetLimitsPressed)
+            .padding(10),
+        );
+
+    let peers = stats.ranked().into_iter().take(10).fold(
+        Column::new().spacing(5),
+        |column, (peer, stats)| {
+            column.push(
+                Text::new(format!(
+                    "{}  -  in {}  -  out {}  -  {} blocks served",
+                    short(peer),
+                    format_size(stats.bytes_in),
+                    format_size(stats.bytes_out),
+                    stats.blocks_served
+                ))
+                .size(16),
+            )
+        },
+    );
+
+    Column::new()
+        .spacing(5)
+        .push(Text::new("Bandwidth").size(20))
+        .push(graphs)
+        .push(
+            Text::new(format!(
+                "limits: upload {}  -  download {}  -  per peer upload {}  -  per peer download {}",
+                format_limit(limits.upload),
+                format_limit(limits.download),
+                format_limit(limits.peer_upload),
+                format_limit(limits.peer_download)
+            ))
+            .size(16),
+        )
+        .push(inputs)
+        .push(peers)
+}
+
+fn storage_view<'a>(
+    usage: &Usage,
+    quota_input: &'a mut text_input::State,
+    quota_value: &str,
+    quota_button: &'a mut button::State,
+    clear_button: &'a mut button::State,
+) -> Column<'a, Message> {
+    let free = usage.quota.saturating_sub(usage.total());
+
+    Column::new()
+        .spacing(5)
+        .push(Text::new("Storage").size(20))
+        .push(bar(
+            500,
+            &[
+                (usage.own, style_bar::Bar::Own),
+                (usage.pinned, style_bar::Bar::Pinned),
+                (usage.cached, style_bar::Bar::Cached),
+                (free, style_bar::Bar::Remaining),
+            ],
+        ))
+        .push(
+            Text::new(format!(
+                "own {}  -  pinned {}  -  cached {}  -  {} of {} quota used",
+                format_size(usage.own),
+                format_size(usage.pinned),
+                format_size(usage.cached),
+                format_size(usage.total()),
+                format_size(usage.quota)
+            ))
+            .size(16),
+        )
+        .push(
+            Row::new()
+                .spacing(10)
+                .push(
+                    TextInput::new(
+                        quota_input,
+                        "Quota in MB",
+                        quota_value,
+                        Message::QuotaChanged,
+                    )
+                    .on_submit(Message::SetQuotaPressed)
+                    .padding(10)
+                    .width(Length::Units(200)),
+                )
+                .push(
+                    Button::new(quota_button, Text::new("Set quota").color(Color::WHITE))
+                        .on_press(Message::SetQuotaPressed)
+                        .padding(10),
+                )
+                .push(
+                    Button::new(clear_button, Text::new("Clear cache"))
+                        .on_press(Message::ClearCachePressed)
+                        .padding(10)
+                        .style(styles::Button::Destructive),
+                ),
+        )
+}
+
+fn peers_view<'a>(
+    peers: &[PeerInfo],
+    disconnect_buttons: &'a mut Vec<button::State>,
+    address_input: &'a mut text_input::State,
+    address_value: &str,
+    connect_button: &'a mut button::State,
+) -> Column<'a, Message> {
+    let connect = Row::new()
+        .spacing(10)
+        .push(
+            TextInput::new(
+                address_input,
+                "/ip4/127.0.0.1/tcp/4001/p2p/Qm...",
+                address_value,
+                Message::AddressChanged,
+            )
+            .on_submit(Message::ConnectPressed)
+            .padding(10)
+            .width(Length::Units(500)),
+        )
+        .push(
+            Button::new(connect_button, Text::new("Connect").color(Color::WHITE))
+                .on_press(Message::ConnectPressed)
+                .padding(10),
+        );
+
+    let nearby =
+        peers
+            .iter()
+            .filter(|peer| peer.nearby)
+            .fold(Column::new().spacing(5), |column, peer| {
+                column.push(
+                    Text::new(format!(
+                        "{}  -  {}  -  {}",
+                        peer.id,
+                        if peer.connected {
+                            "connected"
+                        } else {
+                            "connecting..."
+                        },
+                        latency(peer.rtt)
+                    ))
+                    .size(16),
+                )
+            });
+
+    disconnect_buttons.resize_with(peers.len(), Default::default);
+
+    let connected = peers
+        .iter()
+        .zip(disconnect_buttons.iter_mut())
+        .filter(|(peer, _)| peer.connected)
+        .fold(
+            Column::new().spacing(5).push(connect),
+            |column, (peer, state)| {
+                column.push(
+                    Row::new()
+                        .spacing(20)
+                        .align_items(Align::Center)
+                        .push(
+                            Text::new(format!(
+                                "{} via {} ({})  -  sent {}, received {}",
+                                peer.id,
+                                peer.address,
+                                latency(peer.rtt),
+                                format_size(peer.ledger.bytes_sent),
+                                format_size(peer.ledger.bytes_received)
+                            ))
+                            .size(16),
+                        )
+                        .push(
+                            Button::new(state, Text::new("Disconnect").size(16))
+                                .on_press(Message::DisconnectPressed(peer.id.clone()))
+                                .padding(5)
+                                .style(styles::Button::Destructive),
+                        ),
+                )
+            },
+        );
+
+    Column::new()
+        .spacing(10)
+        .push(connected)
+        .push(Text::new("Peers nearby").size(20))
+        .push(nearby)
+}
+
+// The first characters of a cid or a peer ID, enough to tell them apart
+fn short(id: &str) -> String {
+    id.chars().take(12).collect()
+}
+
+fn access(peers: &[String]) -> String {
+    if peers.is_empty() {
+        String::from("public")
+    } else {
+        let peers: Vec<String> = peers.iter().map(|peer| short(peer)).collect();
+
+        format!("private, shared with {}", peers.join(", "))
+    }
+}
+
+fn latency(rtt: Option<u64>) -> String {
+    match rtt {
+        Some(rtt) => format!("{} ms", rtt),
+        None => String::from("-"),
+    }
+}
+
 // fn container(title: &str) -> Column<'a, StepMessage> {
 //     Column::new().spacing(20).push(Text::new(title).size(50))
 // }
@@ -239,3 +1891,36 @@ mod style_main {
         }
     }
 }
+mod style_bar {
+    use iced::{container, Background, Color};
+
+    #[derive(Debug, Clone, Copy)]
+    pub enum Bar {
+        Filled,
+        Remaining,
+        Own,
+        Pinned,
+        Cached,
+        Upload,
+        Download,
+    }
+
+    impl container::StyleSheet for Bar {
+        fn style(&self) -> container::Style {
+            let color = match self {
+                Bar::Filled | Bar::Own => Color::from_rgb(0.76, 0.20, 0.98),
+                Bar::Remaining => Color::from_rgb(0.85, 0.85, 0.85),
+                Bar::Pinned => Color::from_rgb(0.2, 0.2, 0.7),
+                Bar::Cached => Color::from_rgb(0.5, 0.5, 0.5),
+                Bar::Upload => Color::from_rgb(0.98, 0.55, 0.20),
+                Bar::Download => Color::from_rgb(0.20, 0.65, 0.35),
+            };
+
+            container::Style {
+                background: Some(Background::Color(color)),
+                border_radius: 2,
+                ..container::Style::default()
+            }
+        }
+    }
+}
@@ -1,17 +1,59 @@
+use chrono::{Datelike, Local, NaiveDate, NaiveDateTime, NaiveTime, Utc, Weekday};
 use iced::{
     button, scrollable, text_input, Align, Application, Button, Checkbox, Column, Command,
-    Container, Element, Font, HorizontalAlignment, Length, Row, Scrollable, Settings, Text,
+    Container, Element, HorizontalAlignment, Length, Row, Scrollable, Settings, Subscription, Text,
     TextInput,
 };
 use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use calculator::data::time;
+
+const DUE_FORMAT: &str = "%Y-%m-%d %H:%M";
+
+const DATE_FORMAT: &str = "%Y-%m-%d";
+
+const TAGS_PER_ROW: usize = 6;
+
+// Rotated on the first save of every session
+const BACKUPS: usize = 3;
+
+// Undo steps kept, the history is not saved
+const HISTORY: usize = 100;
+
+// How long deletions are remembered, in milliseconds; what a device that stays away for
+// longer still has comes back
+const DELETIONS_KEPT: i64 = 90 * 24 * 60 * 60 * 1000;
 
 pub fn main() {
-    Todos::run(Settings::default())
+    let mut args = std::env::args().skip(1);
+
+    match (args.next(), args.next()) {
+        (None, _) => Todos::run(Settings::default()),
+        (Some(flag), Some(path)) if flag == "--import" || flag == "--export" => {
+            match async_std::task::block_on(run_command(&flag, path.into())) {
+                Ok(message) => println!("{}", message),
+                Err(error) => {
+                    eprintln!("{}", error);
+                    std::process::exit(1);
+                }
+            }
+        }
+        _ => {
+            eprintln!("usage: todos [--import <file> | --export <file>]");
+            std::process::exit(2);
+        }
+    }
 }
 
 #[derive(Debug)]
 enum Todos {
-    Loading,
+    Loading(Location),
+    Recovering(Recovery),
     Loaded(State),
 }
 
@@ -21,35 +63,207 @@ struct State {
     input: text_input::State,
     input_value: String,
     filter: Filter,
-    tasks: Vec<Task>,
+    sort: Sort,
+    tag: Option<String>,
+    search_value: String,
+    // never empty once loaded
+    lists: Vec<List>,
+    current: usize,
+    list_value: String,
+    show_archived: bool,
+    sidebar: Sidebar,
+    file_value: String,
+    transfer_status: Option<String>,
+    transfer: Transfer,
+    folder_value: String,
+    sync_status: Option<String>,
+    sync_folder: SyncFolder,
     controls: Controls,
+    reminders: Vec<String>,
+    dismiss_button: button::State,
+    history: History,
+    location: Location,
+    // lists and tasks removed, by ID, with when they were
+    deleted: BTreeMap<String, i64>,
+    // what every list and task looked like when last saved, by ID
+    seen: HashMap<String, String>,
     dirty: bool,
     saving: bool,
+    // taken by the first save
+    keep: Option<Keep>,
+}
+
+// The saved file could not be read, it is kept until the user chose what to start from
+#[derive(Debug)]
+struct Recovery {
+    error: LoadError,
+    location: Location,
+    // readable backups by number, once looked for
+    backups: Option<Vec<(usize, SavedState)>>,
+    backup_buttons: Vec<button::State>,
+    empty_button: button::State,
 }
 
 #[derive(Debug, Clone)]
 enum Message {
     Loaded(Result<SavedState, LoadError>),
+    BackupsFound(Vec<(usize, SavedState)>),
+    Recover(Option<usize>),
     Saved(Result<(), SaveError>),
     InputChanged(String),
     CreateTask,
     FilterChanged(Filter),
+    SortChanged(Sort),
+    TagSelected(String),
+    SearchChanged(String),
+    ListSelected(usize),
+    ListNameChanged(String),
+    CreateList,
+    ArchiveList,
+    ToggleArchived,
+    FileChanged(String),
+    Import,
+    Imported(Result<List, LoadError>),
+    Export,
+    Exported(Result<(), SaveError>),
+    FolderChanged(String),
+    SetFolder,
+    Synced(Location, Result<Option<SavedState>, LoadError>),
     TaskMessage(usize, TaskMessage),
+    Undo,
+    Redo,
+    EventOccurred(iced_native::Event),
+    Tick(Instant),
+    DismissReminders,
+}
+
+impl Message {
+    fn is_undoable(&self) -> bool {
+        match self {
+            Message::CreateTask
+            | Message::CreateList
+            | Message::ArchiveList
+            | Message::Imported(Ok(_)) => true,
+            Message::TaskMessage(_, message) => message.is_undoable(),
+            _ => false,
+        }
+    }
+}
+
+// The tag and the search narrow down what the filter lets through, `search` is in lowercase
+fn is_shown(task: &Task, filter: Filter, tag: Option<&str>, search: &str) -> bool {
+    filter.matches(task)
+        && tag.map_or(true, |tag| task.has_tag(tag))
+        && (search.is_empty() || task.contains(search))
+}
+
+// Ctrl+Z undoes, Ctrl+Shift+Z redoes and Alt+Up or Alt+Down moves the task being edited
+fn shortcut(event: iced_native::Event, tasks: &[Task]) -> Option<Message> {
+    use iced_native::input::{keyboard, ButtonState};
+
+    let (key_code, modifiers) = match event {
+        iced_native::Event::Keyboard(keyboard::Event::Input {
+            state: ButtonState::Pressed,
+            key_code,
+            modifiers,
+        }) => (key_code, modifiers),
+        _ => return None,
+    };
+
+    match key_code {
+        keyboard::KeyCode::Z if modifiers.control => Some(if modifiers.shift {
+            Message::Redo
+        } else {
+            Message::Undo
+        }),
+        keyboard::KeyCode::Up if modifiers.alt => {
+            let (i, message) = editing(tasks, TaskMessage::MoveUp)?;
+
+            Some(Message::TaskMessage(i, message))
+        }
+        keyboard::KeyCode::Down if modifiers.alt => {
+            let (i, message) = editing(tasks, TaskMessage::MoveDown)?;
+
+            Some(Message::TaskMessage(i, message))
+        }
+        _ => None,
+    }
+}
+
+// Addresses `message` to the task being edited, subtasks included
+fn editing(tasks: &[Task], message: TaskMessage) -> Option<(usize, TaskMessage)> {
+    tasks.iter().enumerate().find_map(|(i, task)| {
+        if task.is_editing() {
+            Some((i, message.clone()))
+        } else {
+            editing(&task.children, message.clone())
+                .map(|(j, message)| (i, TaskMessage::Child(j, Box::new(message))))
+        }
+    })
+}
+
+#[derive(Debug, Clone)]
+struct Snapshot {
+    lists: Vec<List>,
+    current: usize,
+}
+
+impl Snapshot {
+    // Compared as saved, what is being edited or confirmed aside
+    fn is_of(&self, lists: &[List], current: usize) -> bool {
+        self.current == current
+            && serde_json::to_value(&self.lists).ok() == serde_json::to_value(lists).ok()
+    }
+}
+
+#[derive(Debug, Default)]
+struct History {
+    undo: Vec<Snapshot>,
+    redo: Vec<Snapshot>,
+    // taken before an undoable message, recorded once the tasks change
+    pending: Option<Snapshot>,
+}
+
+impl History {
+    fn record(&mut self, snapshot: Snapshot) {
+        self.undo.push(snapshot);
+        self.redo.clear();
+
+        if self.undo.len() > HISTORY {
+            self.undo.remove(0);
+        }
+    }
+
+    fn undo(&mut self, present: Snapshot) -> Option<Snapshot> {
+        let previous = self.undo.pop()?;
+        self.redo.push(present);
+
+        Some(previous)
+    }
+
+    fn redo(&mut self, present: Snapshot) -> Option<Snapshot> {
+        let next = self.redo.pop()?;
+        self.undo.push(present);
+
+        Some(next)
+    }
 }
 
 impl Application for Todos {
     type Message = Message;
 
     fn new() -> (Todos, Command<Message>) {
+        let location = Location::load();
+
         (
-            Todos::Loading,
-            Command::perform(SavedState::load(), Message::Loaded),
+            Todos::Loading(location.clone()),
+            Command::perform(SavedState::load(location), Message::Loaded),
         )
     }
 
     fn title(&self) -> String {
         let dirty = match self {
-            Todos::Loading => false,
+            Todos::Loading(_) | Todos::Recovering(_) => false,
             Todos::Loaded(state) => state.dirty,
         };
 
@@ -58,55 +272,317 @@ impl Application for Todos {
 
     fn update(&mut self, message: Message) -> Command<Message> {
         match self {
-            Todos::Loading => {
+            Todos::Loading(location) => {
+                let location = location.clone();
+
                 match message {
                     Message::Loaded(Ok(state)) => {
-                        *self = Todos::Loaded(State {
-                            input_value: state.input_value,
-                            filter: state.filter,
-                            tasks: state.tasks,
-                            ..State::default()
-                        });
+                        *self = Todos::Loaded(State::from_saved(state, location, Keep::Backup));
+                    }
+                    // nothing was saved yet
+                    Message::Loaded(Err(LoadError::FileError)) => {
+                        *self = Todos::Loaded(State::from_saved(
+                            SavedState::default(),
+                            location,
+                            Keep::Backup,
+                        ));
                     }
-                    Message::Loaded(Err(_)) => {
-                        *self = Todos::Loaded(State::default());
+                    Message::Loaded(Err(error)) => {
+                        *self = Todos::Recovering(Recovery {
+                            error,
+                            location: location.clone(),
+                            backups: None,
+                            backup_buttons: Vec::new(),
+                            empty_button: button::State::new(),
+                        });
+
+                        return Command::perform(
+                            SavedState::backups(location),
+                            Message::BackupsFound,
+                        );
                     }
                     _ => {}
                 }
 
                 Command::none()
             }
+            Todos::Recovering(recovery) => match message {
+                Message::BackupsFound(backups) => {
+                    recovery.backups = Some(backups);
+
+                    Command::none()
+                }
+                Message::Recover(backup) => {
+                    let saved = backup
+                        .and_then(|i| recovery.backups.as_ref()?.get(i))
+                        .map(|(_, saved)| saved.clone())
+                        .unwrap_or_default();
+
+                    // saved right away, so the unreadable file is set aside
+                    let mut state =
+                        State::from_saved(saved, recovery.location.clone(), Keep::Broken);
+                    state.saving = true;
+
+                    let save = state
+                        .saved()
+                        .save(state.location.clone(), state.keep.take());
+                    *self = Todos::Loaded(state);
+
+                    Command::perform(save, Message::Saved)
+                }
+                _ => Command::none(),
+            },
             Todos::Loaded(state) => {
                 let mut saved = false;
 
+                let message = match message {
+                    Message::EventOccurred(event) => match shortcut(event, state.tasks()) {
+                        Some(message) => message,
+                        None => return Command::none(),
+                    },
+                    message => message,
+                };
+
+                // a parent waiting for its completion to be confirmed, or an edition that has
+                // just started, changes nothing yet
+                if message.is_undoable() && state.history.pending.is_none() {
+                    state.history.pending = Some(state.snapshot());
+                }
+                let edit = match message {
+                    Message::TaskMessage(..) => true,
+                    _ => message.is_undoable(),
+                };
+
                 match message {
                     Message::InputChanged(value) => {
                         state.input_value = value;
                     }
                     Message::CreateTask => {
                         if !state.input_value.is_empty() {
-                            state.tasks.push(Task::new(state.input_value.clone()));
+                            let task = Task::new(state.input_value.clone());
+
+                            state.tasks().push(task);
                             state.input_value.clear();
                         }
                     }
                     Message::FilterChanged(filter) => {
                         state.filter = filter;
                     }
-                    Message::TaskMessage(i, TaskMessage::Delete) => {
-                        state.tasks.remove(i);
+                    Message::SortChanged(sort) => {
+                        state.sort = sort;
                     }
-                    Message::TaskMessage(i, task_message) => {
-                        if let Some(task) = state.tasks.get_mut(i) {
-                            task.update(task_message);
+                    Message::TagSelected(tag) => {
+                        // selecting the current tag again clears it
+                        if state.tag.as_ref() == Some(&tag) {
+                            state.tag = None;
+                        } else {
+                            state.tag = Some(tag);
+                        }
+                    }
+                    Message::SearchChanged(value) => {
+                        state.search_value = value;
+                    }
+                    Message::ListSelected(i) => {
+                        if i < state.lists.len() {
+                            state.current = i;
+                        }
+                    }
+                    Message::ListNameChanged(value) => {
+                        state.list_value = value;
+                    }
+                    Message::CreateList => {
+                        let name = state.list_value.trim();
+
+                        if !name.is_empty() {
+                            state.lists.push(List::new(name.to_owned()));
+                            state.current = state.lists.len() - 1;
+                            state.list_value.clear();
+                        }
+                    }
+                    Message::ArchiveList => {
+                        let list = &mut state.lists[state.current];
+                        list.archived = !list.archived;
+
+                        // an archived list is put away unless the archive is shown
+                        if list.archived && !state.show_archived {
+                            if let Some(i) = state.lists.iter().position(|list| !list.archived) {
+                                state.current = i;
+                            }
+                        }
+                    }
+                    Message::ToggleArchived => {
+                        state.show_archived = !state.show_archived;
+                    }
+                    Message::FileChanged(value) => {
+                        state.file_value = value;
+
+                        return Command::none();
+                    }
+                    Message::Import => {
+                        let path = state.file_value.trim();
+
+                        if path.is_empty() {
+                            return Command::none();
+                        }
+
+                        return Command::perform(import(path.into()), Message::Imported);
+                    }
+                    Message::Imported(Ok(list)) => {
+                        state.transfer_status = Some(format!(
+                            "Imported {} tasks into {}",
+                            list.tasks.len(),
+                            list.name
+                        ));
+                        state.lists.push(list);
+                        state.current = state.lists.len() - 1;
+                    }
+                    Message::Imported(Err(_)) => {
+                        state.transfer_status = Some(String::from("The file could not be read"));
+
+                        return Command::none();
+                    }
+                    Message::Export => {
+                        let path = state.file_value.trim();
+
+                        if path.is_empty() {
+                            return Command::none();
+                        }
+
+                        let list = state.lists[state.current].clone();
+
+                        return Command::perform(export(path.into(), list), Message::Exported);
+                    }
+                    Message::FolderChanged(value) => {
+                        state.folder_value = value;
+
+                        return Command::none();
+                    }
+                    Message::SetFolder => {
+                        let folder = state.folder_value.trim();
+                        let location = Location {
+                            folder: if folder.is_empty() {
+                                None
+                            } else {
+                                Some(folder.into())
+                            },
+                            device: state.location.device.clone(),
+                        };
+
+                        return Command::perform(open_folder(location.clone()), move |result| {
+                            Message::Synced(location.clone(), result)
+                        });
+                    }
+                    Message::Synced(location, Ok(found)) => {
+                        if location.store().is_err() {
+                            state.sync_status =
+                                Some(String::from("The folder could not be remembered"));
+
+                            return Command::none();
+                        }
+
+                        // what the folder holds is merged with what is open
+                        if let Some(found) = found {
+                            let open = state.saved();
+                            let merged = merge(
+                                vec![(location.device.clone(), open), (String::new(), found)],
+                                &location.device,
+                            );
+
+                            state.current = merged.current.min(merged.lists.len() - 1);
+                            state.lists = merged.lists;
+                            state.deleted = merged.deleted;
+                            state.history = History::default();
+                            state.stamp(None);
+                        }
+
+                        state.sync_status = None;
+                        state.folder_value = location.folder_value();
+                        state.location = location;
+                        state.keep = Some(Keep::Backup);
+                    }
+                    Message::Synced(_, Err(_)) => {
+                        state.sync_status =
+                            Some(String::from("The tasks in the folder could not be read"));
+
+                        return Command::none();
+                    }
+                    Message::Exported(result) => {
+                        state.transfer_status = Some(String::from(match result {
+                            Ok(()) => "Exported",
+                            Err(_) => "The file could not be written",
+                        }));
+
+                        return Command::none();
+                    }
+                    Message::TaskMessage(i, TaskMessage::MoveTo(list)) => {
+                        if list < state.lists.len()
+                            && list != state.current
+                            && i < state.tasks().len()
+                        {
+                            let mut task = state.tasks().remove(i);
+                            task.state = TaskState::default();
+
+                            state.lists[list].tasks.push(task);
                         }
                     }
+                    Message::TaskMessage(i, TaskMessage::MoveUp) => {
+                        state.reorder(i, true);
+                    }
+                    Message::TaskMessage(i, TaskMessage::MoveDown) => {
+                        state.reorder(i, false);
+                    }
+                    Message::TaskMessage(i, task_message) => {
+                        update_tasks(state.tasks(), i, task_message);
+                    }
                     Message::Saved(_) => {
                         state.saving = false;
                         saved = true;
                     }
+                    Message::Tick(_) => {
+                        let now = Local::now().naive_local();
+                        let mut due = Vec::new();
+
+                        for list in state.lists.iter_mut().filter(|list| !list.archived) {
+                            for task in &mut list.tasks {
+                                task.remind(now, &mut due);
+                            }
+                        }
+
+                        // nothing to save until a task comes due
+                        if due.is_empty() {
+                            return Command::none();
+                        }
+                        state.reminders.extend(due);
+                    }
+                    Message::DismissReminders => {
+                        state.reminders.clear();
+
+                        return Command::none();
+                    }
+                    Message::Undo => {
+                        let present = state.snapshot();
+
+                        match state.history.undo(present) {
+                            Some(snapshot) => state.restore(snapshot),
+                            None => return Command::none(),
+                        }
+                    }
+                    Message::Redo => {
+                        let present = state.snapshot();
+
+                        match state.history.redo(present) {
+                            Some(snapshot) => state.restore(snapshot),
+                            None => return Command::none(),
+                        }
+                    }
                     _ => {}
                 }
 
+                if edit {
+                    state.sync();
+                }
+                state.settle(edit);
+
                 if !saved {
                     state.dirty = true;
                 }
@@ -115,13 +591,10 @@ impl Application for Todos {
                     state.dirty = false;
                     state.saving = true;
 
+                    let saved = state.saved();
+
                     Command::perform(
-                        SavedState {
-                            input_value: state.input_value.clone(),
-                            filter: state.filter,
-                            tasks: state.tasks.clone(),
-                        }
-                        .save(),
+                        saved.save(state.location.clone(), state.keep.take()),
                         Message::Saved,
                     )
                 } else {
@@ -131,18 +604,63 @@ impl Application for Todos {
         }
     }
 
+    fn subscription(&self) -> Subscription<Message> {
+        match self {
+            Todos::Loading(_) | Todos::Recovering(_) => Subscription::none(),
+            // due tasks are looked for twice a minute
+            Todos::Loaded(_) => Subscription::batch(vec![
+                time::every(Duration::from_secs(30)).map(Message::Tick),
+                iced_native::subscription::events().map(Message::EventOccurred),
+            ]),
+        }
+    }
+
     fn view(&mut self) -> Element<Message> {
         match self {
-            Todos::Loading => loading_message(),
+            Todos::Loading(_) => loading_message(),
+            Todos::Recovering(recovery) => recovery.view(),
             Todos::Loaded(State {
                 scroll,
                 input,
                 input_value,
                 filter,
-                tasks,
+                sort,
+                tag,
+                search_value,
+                lists,
+                current,
+                list_value,
+                show_archived,
+                sidebar,
+                file_value,
+                transfer_status,
+                transfer,
+                folder_value,
+                sync_status,
+                sync_folder,
+                location,
                 controls,
+                history,
+                reminders,
+                dismiss_button,
                 ..
             }) => {
+                let sidebar = sidebar
+                    .view(lists, *current, list_value, *show_archived)
+                    .push(transfer.view(file_value, transfer_status.as_ref()))
+                    .push(sync_folder.view(folder_value, location, sync_status.as_ref()));
+
+                // tasks can be moved to any other list still in use
+                let targets: Vec<(usize, String)> = lists
+                    .iter()
+                    .enumerate()
+                    .filter(|(i, list)| *i != *current && !list.archived)
+                    .map(|(i, list)| (i, list.name.clone()))
+                    .collect();
+
+                let name = Text::new(&lists[*current].name).size(30);
+                let tasks = &mut lists[*current].tasks;
+
                 let title = Text::new("todos")
                     .width(Length::Fill)
                     .size(100)
@@ -159,26 +677,70 @@ impl Application for Todos {
                 .size(30)
                 .on_submit(Message::CreateTask);
 
-                let controls = controls.view(&tasks, *filter);
-                let filtered_tasks = tasks.iter().filter(|task| filter.matches(task));
+                let reminders: Element<_> = if reminders.is_empty() {
+                    Column::new().into()
+                } else {
+                    Container::new(
+                        Row::new()
+                            .spacing(20)
+                            .align_items(Align::Center)
+                            .push(
+                                Text::new(format!("Due now: {}", reminders.join(", ")))
+                                    .width(Length::Fill)
+                                    .size(20),
+                            )
+                            .push(
+                                Button::new(dismiss_button, Text::new("Dismiss").size(16))
+                                    .on_press(Message::DismissReminders)
+                                    .padding(8)
+                                    .style(style::Button::Filter { selected: true }),
+                            ),
+                    )
+                    .width(Length::Fill)
+                    .padding(15)
+                    .style(style::Reminder)
+                    .into()
+                };
+
+                let controls = controls.view(
+                    &tasks,
+                    *filter,
+                    *sort,
+                    tag.as_ref().map(String::as_str),
+                    search_value,
+                    history,
+                );
+
+                let search = search_value.trim().to_lowercase();
+                let tag = tag.as_ref().map(String::as_str);
+                let matches = |task: &Task| is_shown(task, *filter, tag, &search);
+                let filtered_tasks = tasks.iter().filter(|task| matches(task));
 
                 let tasks: Element<_> = if filtered_tasks.count() > 0 {
-                    tasks
+                    let mut visible: Vec<(usize, &mut Task)> = tasks
                         .iter_mut()
                         .enumerate()
-                        .filter(|(_, task)| filter.matches(task))
+                        .filter(|(_, task)| matches(task))
+                        .collect();
+                    visible.sort_by(|(_, a), (_, b)| sort.compare(a, b));
+
+                    visible
+                        .into_iter()
                         .fold(Column::new().spacing(20), |column, (i, task)| {
                             column.push(
-                                task.view()
+                                task.view(&targets, *sort == Sort::Manual)
                                     .map(move |message| Message::TaskMessage(i, message)),
                             )
                         })
                         .into()
+                } else if tag.is_some() || !search.is_empty() {
+                    empty_message("No task matches your search.")
                 } else {
                     empty_message(match filter {
                         Filter::All => "You have not created a task yet...",
                         Filter::Active => "All your tasks are done! :D",
                         Filter::Completed => "You have not completed a task yet...",
+                        Filter::Overdue => "Nothing is overdue.",
                     })
                 };
 
@@ -186,162 +748,1445 @@ impl Application for Todos {
                     .max_width(800)
                     .spacing(20)
                     .push(title)
+                    .push(reminders)
+                    .push(name)
                     .push(input)
                     .push(controls)
                     .push(tasks);
 
                 Scrollable::new(scroll)
                     .padding(40)
-                    .push(Container::new(content).width(Length::Fill).center_x())
+                    .push(
+                        Container::new(Row::new().spacing(40).push(sidebar).push(content))
+                            .width(Length::Fill)
+                            .center_x(),
+                    )
                     .into()
             }
         }
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-struct Task {
-    description: String,
-    completed: bool,
+impl State {
+    fn from_saved(saved: SavedState, location: Location, keep: Keep) -> State {
+        let mut lists = saved.lists;
 
-    #[serde(skip)]
-    state: TaskState,
-}
+        if lists.is_empty() {
+            lists.push(List::default());
+        }
 
-#[derive(Debug, Clone)]
-pub enum TaskState {
-    Idle {
-        edit_button: button::State,
-    },
-    Editing {
-        text_input: text_input::State,
-        delete_button: button::State,
-    },
-}
+        let mut state = State {
+            input_value: saved.input_value,
+            filter: saved.filter,
+            sort: saved.sort,
+            tag: saved.tag,
+            search_value: saved.search,
+            current: saved.current.min(lists.len() - 1),
+            lists,
+            folder_value: location.folder_value(),
+            location,
+            deleted: saved.deleted,
+            keep: Some(keep),
+            ..State::default()
+        };
+        state.stamp(None);
 
-impl Default for TaskState {
-    fn default() -> Self {
-        TaskState::Idle {
-            edit_button: button::State::new(),
-        }
+        state
     }
-}
 
-#[derive(Debug, Clone)]
-pub enum TaskMessage {
-    Completed(bool),
-    Edit,
-    DescriptionEdited(String),
-    FinishEdition,
-    Delete,
-}
+    fn saved(&mut self) -> SavedState {
+        self.stamp(Some(Utc::now().timestamp_millis()));
 
-impl Task {
-    fn new(description: String) -> Self {
-        Task {
-            description,
-            completed: false,
-            state: TaskState::Idle {
-                edit_button: button::State::new(),
-            },
+        SavedState {
+            version: VERSION,
+            input_value: self.input_value.clone(),
+            filter: self.filter,
+            sort: self.sort,
+            tag: self.tag.clone(),
+            search: self.search_value.clone(),
+            lists: self.lists.clone(),
+            current: self.current,
+            deleted: self.deleted.clone(),
         }
     }
 
-    fn update(&mut self, message: TaskMessage) {
-        match message {
-            TaskMessage::Completed(completed) => {
-                self.completed = completed;
-            }
-            TaskMessage::Edit => {
-                self.state = TaskState::Editing {
-                    text_input: text_input::State::focused(),
-                    delete_button: button::State::new(),
-                };
-            }
-            TaskMessage::DescriptionEdited(new_description) => {
-                self.description = new_description;
-            }
-            TaskMessage::FinishEdition => {
-                if !self.description.is_empty() {
-                    self.state = TaskState::Idle {
-                        edit_button: button::State::new(),
-                    }
+    // Marks what changed since it was last seen as modified `now`, and what is gone as
+    // deleted; without `now` it only remembers how everything looks
+    fn stamp(&mut self, now: Option<i64>) {
+        let mut seen = HashMap::new();
+
+        for (index, list) in self.lists.iter_mut().enumerate() {
+            let look = json!([list.name, list.archived, index]).to_string();
+
+            if let Some(now) = now {
+                if self.seen.get(&list.id) != Some(&look) {
+                    list.modified = now;
                 }
             }
-            TaskMessage::Delete => {}
-        }
-    }
+            seen.insert(list.id.clone(), look);
 
-    fn view(&mut self) -> Element<TaskMessage> {
-        match &mut self.state {
-            TaskState::Idle { edit_button } => {
-                let checkbox =
-                    Checkbox::new(self.completed, &self.description, TaskMessage::Completed)
-                        .width(Length::Fill);
+            stamp_tasks(&mut list.tasks, &list.id, None, now, &self.seen, &mut seen);
+        }
 
-                Row::new()
-                    .spacing(20)
-                    .align_items(Align::Center)
-                    .push(checkbox)
-                    .push(
-                        Button::new(edit_button, edit_icon())
-                            .on_press(TaskMessage::Edit)
-                            .padding(10)
-                            .style(style::Button::Icon),
-                    )
-                    .into()
+        if let Some(now) = now {
+            for id in self.seen.keys() {
+                if !seen.contains_key(id) {
+                    self.deleted.insert(id.clone(), now);
+                }
             }
-            TaskState::Editing {
-                text_input,
-                delete_button,
-            } => {
-                let text_input = TextInput::new(
-                    text_input,
-                    "Describe your task...",
-                    &self.description,
-                    TaskMessage::DescriptionEdited,
-                )
-                .on_submit(TaskMessage::FinishEdition)
-                .padding(10);
 
-                Row::new()
-                    .spacing(20)
-                    .align_items(Align::Center)
-                    .push(text_input)
-                    .push(
-                        Button::new(
-                            delete_button,
-                            Row::new()
-                                .spacing(10)
-                                .push(delete_icon())
-                                .push(Text::new("Delete")),
-                        )
-                        .on_press(TaskMessage::Delete)
-                        .padding(10)
-                        .style(style::Button::Destructive),
-                    )
-                    .into()
-            }
+            self.deleted.retain(|_, time| now - *time < DELETIONS_KEPT);
         }
+
+        self.seen = seen;
     }
-}
+
+    fn tasks(&mut self) -> &mut Vec<Task> {
+        &mut self.lists[self.current].tasks
+    }
+
+    // The task swaps places with the shown one next to it, the hidden tasks in between
+    // keep their place
+    fn reorder(&mut self, i: usize, up: bool) {
+        if self.sort != Sort::Manual {
+            return;
+        }
+
+        let search = self.search_value.trim().to_lowercase();
+        let (filter, tag) = (self.filter, self.tag.clone());
+        let tasks = self.tasks();
+
+        let shown: Vec<usize> = tasks
+            .iter()
+            .enumerate()
+            .filter(|(_, task)| is_shown(task, filter, tag.as_ref().map(String::as_str), &search))
+            .map(|(i, _)| i)
+            .collect();
+
+        let target = shown
+            .iter()
+            .position(|shown| *shown == i)
+            .and_then(|position| {
+                if up {
+                    position.checked_sub(1).map(|position| shown[position])
+                } else {
+                    shown.get(position + 1).cloned()
+                }
+            });
+
+        if let Some(target) = target {
+            tasks.swap(i, target);
+        }
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            lists: self.lists.clone(),
+            current: self.current,
+        }
+    }
+
+    // Completion is derived again after every edit, whatever level it was made at
+    fn sync(&mut self) {
+        for list in &mut self.lists {
+            for task in &mut list.tasks {
+                task.sync();
+            }
+        }
+    }
+
+    // The pending snapshot is recorded by the first edit that changes the tasks; other changes,
+    // such as reminders going off, are not undone and move it along
+    fn settle(&mut self, edit: bool) {
+        if let Some(pending) = self.history.pending.take() {
+            if pending.is_of(&self.lists, self.current) {
+                self.history.pending = Some(pending);
+            } else if edit {
+                self.history.record(pending);
+            } else {
+                self.history.pending = Some(self.snapshot());
+            }
+        }
+    }
+
+    // Tasks come back out of edition
+    fn restore(&mut self, snapshot: Snapshot) {
+        self.lists = snapshot.lists;
+        self.current = snapshot.current.min(self.lists.len() - 1);
+
+        for list in &mut self.lists {
+            for task in &mut list.tasks {
+                task.reset();
+            }
+        }
+    }
+}
+
+// The place of a task is part of how it looks, moving it is a change
+fn stamp_tasks(
+    tasks: &mut [Task],
+    list: &str,
+    parent: Option<&str>,
+    now: Option<i64>,
+    before: &HashMap<String, String>,
+    seen: &mut HashMap<String, String>,
+) {
+    for (index, task) in tasks.iter_mut().enumerate() {
+        let look = json!([
+            task.description,
+            task.completed,
+            task.due,
+            task.priority,
+            task.notes,
+            task.recurrence,
+            task.collapsed,
+            list,
+            parent,
+            index
+        ])
+        .to_string();
+
+        if let Some(now) = now {
+            if before.get(&task.id) != Some(&look) {
+                task.modified = now;
+            }
+        }
+        seen.insert(task.id.clone(), look);
+
+        stamp_tasks(&mut task.children, list, Some(&task.id), now, before, seen);
+    }
+}
+
+impl Recovery {
+    fn view(&mut self) -> Element<Message> {
+        let Recovery {
+            error,
+            location,
+            backups,
+            backup_buttons,
+            empty_button,
+        } = self;
+
+        let reason = match error {
+            LoadError::VersionError => "It was saved by a newer version of Todos.",
+            _ => "The file is damaged.",
+        };
+
+        let content = Column::new()
+            .max_width(800)
+            .spacing(20)
+            .push(Text::new("Your tasks could not be read").size(40))
+            .push(Text::new(reason).size(20))
+            .push(
+                Text::new(format!(
+                    "Whatever you choose, the file is kept next to it as {}.broken.",
+                    location.file()
+                ))
+                .size(16)
+                .color([0.5, 0.5, 0.5]),
+            );
+
+        let content = match backups {
+            None => content.push(Text::new("Looking for backups...").size(16)),
+            Some(backups) => {
+                backup_buttons.resize_with(backups.len(), button::State::new);
+
+                backups
+                    .iter()
+                    .zip(backup_buttons.iter_mut())
+                    .enumerate()
+                    .fold(content, |column, (i, ((number, saved), state))| {
+                        let tasks: usize = saved
+                            .lists
+                            .iter()
+                            .flat_map(|list| list.tasks.iter())
+                            .map(Task::leaves)
+                            .sum();
+
+                        column.push(
+                            Button::new(
+                                state,
+                                Text::new(format!(
+                                    "Restore backup {} ({} lists, {} tasks)",
+                                    number,
+                                    saved.lists.len(),
+                                    tasks
+                                )),
+                            )
+                            .on_press(Message::Recover(Some(i)))
+                            .padding(10)
+                            .style(style::Button::Filter { selected: false }),
+                        )
+                    })
+            }
+        };
+
+        let content = content.push(
+            Button::new(empty_button, Text::new("Start with no tasks"))
+                .on_press(Message::Recover(None))
+                .padding(10)
+                .style(style::Button::Destructive),
+        );
+
+        Container::new(content)
+            .width(Length::Fill)
+            .height(Length::Fill)
+            .padding(40)
+            .center_x()
+            .center_y()
+            .into()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct List {
+    #[serde(default = "new_id")]
+    id: String,
+    #[serde(default)]
+    modified: i64,
+    name: String,
+    tasks: Vec<Task>,
+    #[serde(default)]
+    archived: bool,
+}
+
+impl Default for List {
+    fn default() -> Self {
+        List::new(String::from("Tasks"))
+    }
+}
+
+impl List {
+    fn new(name: String) -> Self {
+        List {
+            id: new_id(),
+            modified: 0,
+            name,
+            tasks: Vec::new(),
+            archived: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Task {
+    // the same on every device, for merging
+    #[serde(default = "new_id")]
+    id: String,
+    // milliseconds since the epoch, set when a change is saved
+    #[serde(default)]
+    modified: i64,
+    description: String,
+    completed: bool,
+    // local time, as it was entered
+    #[serde(default)]
+    due: Option<NaiveDateTime>,
+    #[serde(default)]
+    priority: Priority,
+    #[serde(default)]
+    notes: String,
+    // the reminder for the current due date went off
+    #[serde(default)]
+    reminded: bool,
+    #[serde(default)]
+    recurrence: Option<Recurrence>,
+    // a task with children is done when all of them are
+    #[serde(default)]
+    children: Vec<Task>,
+    #[serde(default)]
+    collapsed: bool,
+
+    #[serde(skip)]
+    state: TaskState,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Priority {
+    Low,
+    Normal,
+    High,
+}
+
+impl Default for Priority {
+    fn default() -> Self {
+        Priority::Normal
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum TaskState {
+    Idle {
+        edit_button: button::State,
+        toggle_button: button::State,
+        // completion asked for over the subtasks, until it is confirmed
+        confirm: Option<bool>,
+        confirm_button: button::State,
+        cancel_button: button::State,
+    },
+    Editing {
+        text_input: text_input::State,
+        due_input: text_input::State,
+        due_value: String,
+        recurrence_input: text_input::State,
+        recurrence_value: String,
+        notes_input: text_input::State,
+        low_button: button::State,
+        normal_button: button::State,
+        high_button: button::State,
+        indent_button: button::State,
+        outdent_button: button::State,
+        up_button: button::State,
+        down_button: button::State,
+        subtask_button: button::State,
+        move_buttons: Vec<button::State>,
+        delete_button: button::State,
+    },
+}
+
+impl Default for TaskState {
+    fn default() -> Self {
+        TaskState::Idle {
+            edit_button: button::State::new(),
+            toggle_button: button::State::new(),
+            confirm: None,
+            confirm_button: button::State::new(),
+            cancel_button: button::State::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum TaskMessage {
+    Completed(bool),
+    Edit,
+    DescriptionEdited(String),
+    DueEdited(String),
+    RecurrenceEdited(String),
+    PriorityChanged(Priority),
+    NotesEdited(String),
+    FinishEdition,
+    ConfirmCompletion,
+    CancelCompletion,
+    ToggleCollapsed,
+    AddSubtask,
+    Indent,
+    Outdent,
+    MoveUp,
+    MoveDown,
+    Child(usize, Box<TaskMessage>),
+    MoveTo(usize),
+    Delete,
+}
+
+impl TaskMessage {
+    // Edition is undone as a whole, from when it started
+    fn is_undoable(&self) -> bool {
+        match self {
+            TaskMessage::Completed(_)
+            | TaskMessage::Edit
+            | TaskMessage::ConfirmCompletion
+            | TaskMessage::AddSubtask
+            | TaskMessage::Indent
+            | TaskMessage::Outdent
+            | TaskMessage::MoveUp
+            | TaskMessage::MoveDown
+            | TaskMessage::MoveTo(_)
+            | TaskMessage::Delete => true,
+            TaskMessage::Child(_, message) => message.is_undoable(),
+            _ => false,
+        }
+    }
+}
+
+impl Task {
+    fn new(description: String) -> Self {
+        Task {
+            id: new_id(),
+            modified: 0,
+            description,
+            completed: false,
+            due: None,
+            priority: Priority::default(),
+            notes: String::new(),
+            reminded: false,
+            recurrence: None,
+            children: Vec::new(),
+            collapsed: false,
+            state: TaskState::default(),
+        }
+    }
+
+    fn update(&mut self, message: TaskMessage) {
+        match message {
+            TaskMessage::Completed(completed) => {
+                if self.children.is_empty() {
+                    self.completed = completed;
+                } else if let TaskState::Idle { confirm, .. } = &mut self.state {
+                    *confirm = Some(completed);
+                }
+            }
+            TaskMessage::Edit => {
+                self.state = TaskState::Editing {
+                    text_input: text_input::State::focused(),
+                    due_input: text_input::State::new(),
+                    due_value: self
+                        .due
+                        .map(|due| due.format(DUE_FORMAT).to_string())
+                        .unwrap_or_default(),
+                    recurrence_input: text_input::State::new(),
+                    recurrence_value: self
+                        .recurrence
+                        .as_ref()
+                        .map(Recurrence::to_string)
+                        .unwrap_or_default(),
+                    notes_input: text_input::State::new(),
+                    low_button: button::State::new(),
+                    normal_button: button::State::new(),
+                    high_button: button::State::new(),
+                    indent_button: button::State::new(),
+                    outdent_button: button::State::new(),
+                    up_button: button::State::new(),
+                    down_button: button::State::new(),
+                    subtask_button: button::State::new(),
+                    move_buttons: Vec::new(),
+                    delete_button: button::State::new(),
+                };
+            }
+            TaskMessage::DescriptionEdited(new_description) => {
+                self.description = new_description;
+            }
+            TaskMessage::DueEdited(new_due) => {
+                if let TaskState::Editing { due_value, .. } = &mut self.state {
+                    *due_value = new_due;
+                }
+            }
+            TaskMessage::RecurrenceEdited(new_recurrence) => {
+                if let TaskState::Editing {
+                    recurrence_value, ..
+                } = &mut self.state
+                {
+                    *recurrence_value = new_recurrence;
+                }
+            }
+            TaskMessage::PriorityChanged(priority) => {
+                self.priority = priority;
+            }
+            TaskMessage::NotesEdited(new_notes) => {
+                self.notes = new_notes;
+            }
+            TaskMessage::FinishEdition => {
+                let (due, recurrence) = match &self.state {
+                    TaskState::Editing {
+                        due_value,
+                        recurrence_value,
+                        ..
+                    } => (
+                        parse_due(due_value).ok(),
+                        Recurrence::parse(recurrence_value),
+                    ),
+                    TaskState::Idle { .. } => (Some(self.due), Some(self.recurrence.clone())),
+                };
+
+                // the task stays in edition until its due date and recurrence can be read
+                if let (false, Some(due), Some(recurrence)) =
+                    (self.description.is_empty(), due, recurrence)
+                {
+                    if due != self.due {
+                        self.due = due;
+                        self.reminded = false;
+                    }
+                    self.recurrence = recurrence;
+
+                    self.state = TaskState::default();
+                }
+            }
+            TaskMessage::ConfirmCompletion => {
+                if let TaskState::Idle { confirm, .. } = &mut self.state {
+                    if let Some(completed) = confirm.take() {
+                        self.set_completed(completed);
+                    }
+                }
+            }
+            TaskMessage::CancelCompletion => {
+                if let TaskState::Idle { confirm, .. } = &mut self.state {
+                    *confirm = None;
+                }
+            }
+            TaskMessage::ToggleCollapsed => {
+                self.collapsed = !self.collapsed;
+            }
+            TaskMessage::AddSubtask => {
+                // the subtask stays in edition until it is described
+                let mut task = Task::new(String::new());
+                task.update(TaskMessage::Edit);
+
+                self.children.push(task);
+                self.collapsed = false;
+            }
+            TaskMessage::Child(i, message) => {
+                update_tasks(&mut self.children, i, *message);
+            }
+            TaskMessage::Indent
+            | TaskMessage::Outdent
+            | TaskMessage::MoveUp
+            | TaskMessage::MoveDown
+            | TaskMessage::MoveTo(_)
+            | TaskMessage::Delete => {}
+        }
+    }
+
+    // The task to do next once this one is completed, only tasks without subtasks recur
+    fn next_occurrence(&self, now: NaiveDateTime) -> Option<Task> {
+        if self.completed || !self.children.is_empty() {
+            return None;
+        }
+
+        let recurrence = self.recurrence.as_ref()?;
+
+        Some(Task {
+            due: Some(recurrence.next(self.due, now)),
+            recurrence: Some(recurrence.clone()),
+            notes: self.notes.clone(),
+            priority: self.priority,
+            ..Task::new(self.description.clone())
+        })
+    }
+
+    fn is_editing(&self) -> bool {
+        match self.state {
+            TaskState::Editing { .. } => true,
+            TaskState::Idle { .. } => false,
+        }
+    }
+
+    fn reset(&mut self) {
+        self.state = TaskState::default();
+
+        for child in &mut self.children {
+            child.reset();
+        }
+    }
+
+    fn set_completed(&mut self, completed: bool) {
+        self.completed = completed;
+
+        for child in &mut self.children {
+            child.set_completed(completed);
+        }
+    }
+
+    // A task with subtasks is done when all of them are, derived from the deepest ones up
+    fn sync(&mut self) {
+        for child in &mut self.children {
+            child.sync();
+        }
+
+        if !self.children.is_empty() {
+            self.completed = self.children.iter().all(|child| child.completed);
+        }
+    }
+
+    // Only the tasks without subtasks are counted
+    fn leaves_left(&self) -> usize {
+        if self.children.is_empty() {
+            if self.completed {
+                0
+            } else {
+                1
+            }
+        } else {
+            self.children.iter().map(Task::leaves_left).sum()
+        }
+    }
+
+    fn leaves(&self) -> usize {
+        if self.children.is_empty() {
+            1
+        } else {
+            self.children.iter().map(Task::leaves).sum()
+        }
+    }
+
+    // A task has the tags of its subtasks too
+    fn has_tag(&self, tag: &str) -> bool {
+        parse_tags(&self.description).any(|own| own == tag)
+            || self.children.iter().any(|child| child.has_tag(tag))
+    }
+
+    // `text` is expected in lowercase
+    fn contains(&self, text: &str) -> bool {
+        self.description.to_lowercase().contains(text)
+            || self.notes.to_lowercase().contains(text)
+            || self.children.iter().any(|child| child.contains(text))
+    }
+
+    fn remind(&mut self, now: NaiveDateTime, due: &mut Vec<String>) {
+        if self.is_due(now) && !self.reminded {
+            self.reminded = true;
+            due.push(self.description.clone());
+        }
+
+        for child in &mut self.children {
+            child.remind(now, due);
+        }
+    }
+
+    fn is_due(&self, now: NaiveDateTime) -> bool {
+        !self.completed && self.due.map_or(false, |due| due <= now)
+    }
+
+    // `targets` are the other lists, by index, the task can be moved to
+    fn view(&mut self, targets: &[(usize, String)], reorderable: bool) -> Element<TaskMessage> {
+        let overdue = self.is_due(Local::now().naive_local());
+        let has_children = !self.children.is_empty();
+        let (leaves, leaves_left) = (self.leaves(), self.leaves_left());
+
+        let task: Element<_> = match &mut self.state {
+            TaskState::Idle {
+                edit_button,
+                toggle_button,
+                confirm,
+                confirm_button,
+                cancel_button,
+            } => {
+                let checkbox =
+                    Checkbox::new(self.completed, &self.description, TaskMessage::Completed)
+                        .width(Length::Fill);
+
+                let row = Row::new().spacing(20).align_items(Align::Center);
+                let row = if has_children {
+                    row.push(
+                        Button::new(
+                            toggle_button,
+                            Text::new(if self.collapsed { "+" } else { "-" }).size(20),
+                        )
+                        .on_press(TaskMessage::ToggleCollapsed)
+                        .padding(10)
+                        .style(style::Button::Icon),
+                    )
+                } else {
+                    row
+                };
+
+                let row = row.push(checkbox).push(
+                    Button::new(edit_button, edit_icon())
+                        .on_press(TaskMessage::Edit)
+                        .padding(10)
+                        .style(style::Button::Icon),
+                );
+
+                let mut details = Vec::new();
+                if let Some(due) = self.due {
+                    details.push(format!("due {}", due.format(DUE_FORMAT)));
+                }
+                match self.priority {
+                    Priority::Low => details.push(String::from("low priority")),
+                    Priority::Normal => {}
+                    Priority::High => details.push(String::from("high priority")),
+                }
+                if let Some(recurrence) = &self.recurrence {
+                    details.push(format!("repeats {}", recurrence));
+                }
+                if has_children {
+                    details.push(format!(
+                        "{} of {} subtasks done",
+                        leaves - leaves_left,
+                        leaves
+                    ));
+                }
+
+                let column = Column::new().spacing(5).push(row);
+                let column = match confirm {
+                    Some(completed) => column.push(
+                        Row::new()
+                            .spacing(10)
+                            .align_items(Align::Center)
+                            .push(
+                                Text::new(if *completed {
+                                    "Mark every subtask as done?"
+                                } else {
+                                    "Mark every subtask as not done?"
+                                })
+                                .width(Length::Fill)
+                                .size(16),
+                            )
+                            .push(
+                                Button::new(confirm_button, Text::new("Confirm").size(16))
+                                    .on_press(TaskMessage::ConfirmCompletion)
+                                    .padding(8)
+                                    .style(style::Button::Filter { selected: true }),
+                            )
+                            .push(
+                                Button::new(cancel_button, Text::new("Cancel").size(16))
+                                    .on_press(TaskMessage::CancelCompletion)
+                                    .padding(8)
+                                    .style(style::Button::Filter { selected: false }),
+                            ),
+                    ),
+                    None => column,
+                };
+                let column = if details.is_empty() {
+                    column
+                } else {
+                    column.push(Text::new(details.join("  -  ")).size(14).color(if overdue {
+                        [0.8, 0.2, 0.2]
+                    } else {
+                        [0.5, 0.5, 0.5]
+                    }))
+                };
+                let column = if self.notes.is_empty() {
+                    column
+                } else {
+                    column.push(Text::new(&self.notes).size(14).color([0.5, 0.5, 0.5]))
+                };
+
+                column.into()
+            }
+            TaskState::Editing {
+                text_input,
+                due_input,
+                due_value,
+                recurrence_input,
+                recurrence_value,
+                notes_input,
+                low_button,
+                normal_button,
+                high_button,
+                indent_button,
+                outdent_button,
+                up_button,
+                down_button,
+                subtask_button,
+                move_buttons,
+                delete_button,
+            } => {
+                let text_input = TextInput::new(
+                    text_input,
+                    "Describe your task...",
+                    &self.description,
+                    TaskMessage::DescriptionEdited,
+                )
+                .on_submit(TaskMessage::FinishEdition)
+                .padding(10);
+
+                let current = self.priority;
+                let priority_button = |state, label, priority| {
+                    Button::new(state, Text::new(label).size(16))
+                        .style(style::Button::Filter {
+                            selected: priority == current,
+                        })
+                        .on_press(TaskMessage::PriorityChanged(priority))
+                        .padding(8)
+                };
+
+                let row = Row::new()
+                    .spacing(20)
+                    .align_items(Align::Center)
+                    .push(text_input)
+                    .push(
+                        Button::new(delete_button, Text::new("Delete"))
+                            .on_press(TaskMessage::Delete)
+                            .padding(10)
+                            .style(style::Button::Destructive),
+                    );
+
+                let due = Row::new()
+                    .spacing(10)
+                    .align_items(Align::Center)
+                    .push(
+                        TextInput::new(
+                            due_input,
+                            "Due as YYYY-MM-DD or YYYY-MM-DD HH:MM",
+                            due_value,
+                            TaskMessage::DueEdited,
+                        )
+                        .on_submit(TaskMessage::FinishEdition)
+                        .padding(10),
+                    )
+                    .push(priority_button(low_button, "Low", Priority::Low))
+                    .push(priority_button(normal_button, "Normal", Priority::Normal))
+                    .push(priority_button(high_button, "High", Priority::High));
+
+                let recurrence = TextInput::new(
+                    recurrence_input,
+                    "Repeat: daily, weekly mon fri, monthly 31 or every 3 days",
+                    recurrence_value,
+                    TaskMessage::RecurrenceEdited,
+                )
+                .on_submit(TaskMessage::FinishEdition)
+                .padding(10);
+
+                let notes =
+                    TextInput::new(notes_input, "Notes", &self.notes, TaskMessage::NotesEdited)
+                        .on_submit(TaskMessage::FinishEdition)
+                        .padding(10);
+
+                let structure_button = |state, label, message| {
+                    Button::new(state, Text::new(label).size(16))
+                        .style(style::Button::Filter { selected: false })
+                        .on_press(message)
+                        .padding(8)
+                };
+
+                // a task is indented under the one before it, and outdented after its parent
+                let structure = Row::new()
+                    .spacing(10)
+                    .push(structure_button(
+                        subtask_button,
+                        "Add subtask",
+                        TaskMessage::AddSubtask,
+                    ))
+                    .push(structure_button(
+                        indent_button,
+                        "Indent",
+                        TaskMessage::Indent,
+                    ))
+                    .push(structure_button(
+                        outdent_button,
+                        "Outdent",
+                        TaskMessage::Outdent,
+                    ));
+
+                // a sorted list has no order of its own to change
+                let structure = if reorderable {
+                    structure
+                        .push(structure_button(up_button, "Move up", TaskMessage::MoveUp))
+                        .push(structure_button(
+                            down_button,
+                            "Move down",
+                            TaskMessage::MoveDown,
+                        ))
+                } else {
+                    structure
+                };
+
+                move_buttons.resize_with(targets.len(), button::State::new);
+
+                let moves = if targets.is_empty() {
+                    Row::new()
+                } else {
+                    targets.iter().zip(move_buttons.iter_mut()).fold(
+                        Row::new()
+                            .spacing(10)
+                            .align_items(Align::Center)
+                            .push(Text::new("Move to").size(16)),
+                        |row, ((list, name), state)| {
+                            row.push(
+                                Button::new(state, Text::new(name).size(16))
+                                    .on_press(TaskMessage::MoveTo(*list))
+                                    .padding(8)
+                                    .style(style::Button::Filter { selected: false }),
+                            )
+                        },
+                    )
+                };
+
+                Column::new()
+                    .spacing(10)
+                    .push(row)
+                    .push(due)
+                    .push(recurrence)
+                    .push(notes)
+                    .push(structure)
+                    .push(moves)
+                    .into()
+            }
+        };
+
+        if !has_children || self.collapsed {
+            return task;
+        }
+
+        // subtasks stay in the list of their parent
+        let children = self.children.iter_mut().enumerate().fold(
+            Column::new().spacing(20).width(Length::Fill),
+            |column, (i, child)| {
+                column.push(
+                    child
+                        .view(&[], true)
+                        .map(move |message| TaskMessage::Child(i, Box::new(message))),
+                )
+            },
+        );
+
+        Column::new()
+            .spacing(20)
+            .push(task)
+            .push(
+                Row::new()
+                    .push(Column::new().width(Length::Units(40)))
+                    .push(children),
+            )
+            .into()
+    }
+}
+
+// Messages that rearrange a task among its siblings are handled here
+fn update_tasks(tasks: &mut Vec<Task>, i: usize, message: TaskMessage) {
+    match message {
+        TaskMessage::Completed(true) => {
+            if let Some(task) = tasks.get_mut(i) {
+                let next = task.next_occurrence(Local::now().naive_local());
+                task.update(TaskMessage::Completed(true));
+
+                // the completed occurrence does not recur again
+                if let Some(next) = next {
+                    task.recurrence = None;
+                    tasks.insert(i + 1, next);
+                }
+            }
+        }
+        TaskMessage::Delete => {
+            if i < tasks.len() {
+                tasks.remove(i);
+            }
+        }
+        // subtasks are always shown in their order
+        TaskMessage::MoveUp => {
+            if i > 0 && i < tasks.len() {
+                tasks.swap(i - 1, i);
+            }
+        }
+        TaskMessage::MoveDown => {
+            if i + 1 < tasks.len() {
+                tasks.swap(i, i + 1);
+            }
+        }
+        TaskMessage::Indent => {
+            if i > 0 && i < tasks.len() {
+                let task = tasks.remove(i);
+                let parent = &mut tasks[i - 1];
+
+                parent.children.push(task);
+                parent.collapsed = false;
+            }
+        }
+        TaskMessage::Child(j, message) => match *message {
+            TaskMessage::Outdent => {
+                if let Some(parent) = tasks.get_mut(i) {
+                    if j < parent.children.len() {
+                        let task = parent.children.remove(j);
+
+                        tasks.insert(i + 1, task);
+                    }
+                }
+            }
+            message => {
+                if let Some(task) = tasks.get_mut(i) {
+                    task.update(TaskMessage::Child(j, Box::new(message)));
+                }
+            }
+        },
+        message => {
+            if let Some(task) = tasks.get_mut(i) {
+                task.update(message);
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Recurrence {
+    Daily,
+    // on the same weekday as the task when no day is given
+    Weekly(Vec<Weekday>),
+    // clamped to the last day of shorter months
+    Monthly(u32),
+    // counted from the day the task was completed
+    AfterCompletion(u32),
+}
+
+impl Recurrence {
+    // An empty value removes the recurrence, `None` when it can not be read
+    fn parse(value: &str) -> Option<Option<Recurrence>> {
+        let words: Vec<String> = value
+            .split(|c: char| c.is_whitespace() || c == ',')
+            .filter(|word| !word.is_empty())
+            .map(str::to_lowercase)
+            .collect();
+        let words: Vec<&str> = words.iter().map(String::as_str).collect();
+
+        let recurrence = match words.as_slice() {
+            [] => return Some(None),
+            ["daily"] => Recurrence::Daily,
+            ["weekly", ..] => Recurrence::Weekly(
+                words[1..]
+                    .iter()
+                    .map(|day| day.parse().ok())
+                    .collect::<Option<Vec<Weekday>>>()?,
+            ),
+            ["monthly", day] => match day.parse() {
+                Ok(day) if (1..=31).contains(&day) => Recurrence::Monthly(day),
+                _ => return None,
+            },
+            ["every", days, "days"] | ["every", days, "day"] => match days.parse() {
+                Ok(days) if days >= 1 => Recurrence::AfterCompletion(days),
+                _ => return None,
+            },
+            _ => return None,
+        };
+
+        Some(Some(recurrence))
+    }
+
+    // The due date of the next occurrence of a task due at `due` and completed at `now`;
+    // it keeps the time of day of the task, or 9:00
+    fn next(&self, due: Option<NaiveDateTime>, now: NaiveDateTime) -> NaiveDateTime {
+        let time = due.map_or_else(|| NaiveTime::from_hms(9, 0, 0), |due| due.time());
+        let from = due.unwrap_or(now).date();
+
+        let date = match self {
+            Recurrence::Daily => from + chrono::Duration::days(1),
+            Recurrence::Weekly(days) => (1..=7)
+                .map(|offset| from + chrono::Duration::days(offset))
+                .find(|date| {
+                    if days.is_empty() {
+                        date.weekday() == from.weekday()
+                    } else {
+                        days.contains(&date.weekday())
+                    }
+                })
+                .unwrap_or(from + chrono::Duration::days(7)),
+            Recurrence::Monthly(day) => {
+                let this_month = clamp_day(from.year(), from.month(), *day);
+
+                if from.day() < this_month.day() {
+                    this_month
+                } else if from.month() == 12 {
+                    clamp_day(from.year() + 1, 1, *day)
+                } else {
+                    clamp_day(from.year(), from.month() + 1, *day)
+                }
+            }
+            Recurrence::AfterCompletion(days) => {
+                now.date() + chrono::Duration::days(i64::from(*days))
+            }
+        };
+
+        date.and_time(time)
+    }
+}
+
+impl fmt::Display for Recurrence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Recurrence::Daily => write!(f, "daily"),
+            Recurrence::Weekly(days) => {
+                write!(f, "weekly")?;
+
+                for day in days {
+                    write!(f, " {}", format!("{:?}", day).to_lowercase())?;
+                }
+
+                Ok(())
+            }
+            Recurrence::Monthly(day) => write!(f, "monthly {}", day),
+            Recurrence::AfterCompletion(1) => write!(f, "every 1 day"),
+            Recurrence::AfterCompletion(days) => write!(f, "every {} days", days),
+        }
+    }
+}
+
+// The given day of a month, or its last day when the month is shorter
+fn clamp_day(year: i32, month: u32, day: u32) -> NaiveDate {
+    (1..=day)
+        .rev()
+        .filter_map(|day| NaiveDate::from_ymd_opt(year, month, day))
+        .next()
+        .unwrap_or_else(|| NaiveDate::from_ymd(year, month, 1))
+}
+
+// Tags are the words starting with `#`, compared without case; todo.txt `+projects` and
+// `@contexts` are tags too and keep their sign
+fn parse_tags<'a>(description: &'a str) -> impl Iterator<Item = String> + 'a {
+    description
+        .split_whitespace()
+        .filter(|word| word.starts_with('#') || word.starts_with('+') || word.starts_with('@'))
+        .filter_map(|word| {
+            let name = word[1..]
+                .trim_start_matches('#')
+                .trim_end_matches(|c: char| !c.is_alphanumeric() && c != '-' && c != '_')
+                .to_lowercase();
+
+            if name.is_empty() {
+                None
+            } else if word.starts_with('#') {
+                Some(name)
+            } else {
+                Some(format!("{}{}", &word[..1], name))
+            }
+        })
+}
+
+fn tag_label(tag: &str) -> String {
+    if tag.starts_with('+') || tag.starts_with('@') {
+        tag.to_owned()
+    } else {
+        format!("#{}", tag)
+    }
+}
+
+// How many tasks, subtasks included, carry every tag
+fn count_tags(tasks: &[Task], counts: &mut BTreeMap<String, usize>) {
+    for task in tasks {
+        let mut tags: Vec<String> = parse_tags(&task.description).collect();
+        tags.sort();
+        tags.dedup();
+
+        for tag in tags {
+            *counts.entry(tag).or_insert(0) += 1;
+        }
+
+        count_tags(&task.children, counts);
+    }
+}
+
+// A date alone is due at 9:00, an empty value removes the due date
+fn parse_due(value: &str) -> Result<Option<NaiveDateTime>, chrono::ParseError> {
+    let value = value.trim();
+
+    if value.is_empty() {
+        return Ok(None);
+    }
+
+    NaiveDateTime::parse_from_str(value, DUE_FORMAT)
+        .or_else(|_| {
+            NaiveDate::parse_from_str(value, DATE_FORMAT).map(|date| date.and_hms(9, 0, 0))
+        })
+        .map(Some)
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct Sidebar {
+    list_input: text_input::State,
+    archive_button: button::State,
+    show_archived_button: button::State,
+    buttons: Vec<button::State>,
+}
+
+impl Sidebar {
+    fn view(
+        &mut self,
+        lists: &[List],
+        current: usize,
+        list_value: &str,
+        show_archived: bool,
+    ) -> Column<Message> {
+        let Sidebar {
+            list_input,
+            archive_button,
+            show_archived_button,
+            buttons,
+        } = self;
+
+        buttons.resize_with(lists.len(), button::State::new);
+
+        let entries = lists
+            .iter()
+            .zip(buttons.iter_mut())
+            .enumerate()
+            .filter(|(i, (list, _))| show_archived || !list.archived || *i == current)
+            .fold(Column::new().spacing(10), |column, (i, (list, state))| {
+                let left: usize = list.tasks.iter().map(Task::leaves_left).sum();
+                let label = if list.archived {
+                    format!("{} ({} left, archived)", list.name, left)
+                } else {
+                    format!("{} ({} left)", list.name, left)
+                };
+
+                column.push(
+                    Button::new(state, Text::new(label).size(16))
+                        .width(Length::Fill)
+                        .on_press(Message::ListSelected(i))
+                        .padding(8)
+                        .style(style::Button::Filter {
+                            selected: i == current,
+                        }),
+                )
+            });
+
+        let input = TextInput::new(list_input, "New list", list_value, Message::ListNameChanged)
+            .padding(8)
+            .size(16)
+            .on_submit(Message::CreateList);
+
+        let archived = lists[current].archived;
+
+        Column::new()
+            .width(Length::Units(220))
+            .spacing(20)
+            .push(Text::new("Lists").size(30))
+            .push(entries)
+            .push(input)
+            .push(
+                Button::new(
+                    archive_button,
+                    Text::new(if archived { "Unarchive" } else { "Archive" }).size(16),
+                )
+                .on_press(Message::ArchiveList)
+                .padding(8)
+                .style(style::Button::Filter { selected: false }),
+            )
+            .push(
+                Button::new(
+                    show_archived_button,
+                    Text::new(if show_archived {
+                        "Hide archived"
+                    } else {
+                        "Show archived"
+                    })
+                    .size(16),
+                )
+                .on_press(Message::ToggleArchived)
+                .padding(8)
+                .style(style::Button::Filter {
+                    selected: show_archived,
+                }),
+            )
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct Transfer {
+    file_input: text_input::State,
+    import_button: button::State,
+    export_button: button::State,
+}
+
+impl Transfer {
+    fn view(&mut self, file_value: &str, status: Option<&String>) -> Column<Message> {
+        let Transfer {
+            file_input,
+            import_button,
+            export_button,
+        } = self;
+
+        let button = |state, label, message| {
+            Button::new(state, Text::new(label).size(16))
+                .on_press(message)
+                .padding(8)
+                .style(style::Button::Filter { selected: false })
+        };
+
+        let column = Column::new()
+            .spacing(10)
+            .push(Text::new("Import and export").size(20))
+            .push(
+                TextInput::new(file_input, "File path", file_value, Message::FileChanged)
+                    .padding(8)
+                    .size(16),
+            )
+            .push(
+                Text::new("todo.txt, or a Markdown checklist for .md files")
+                    .size(14)
+                    .color([0.5, 0.5, 0.5]),
+            )
+            .push(
+                Row::new()
+                    .spacing(10)
+                    .push(button(import_button, "Import", Message::Import))
+                    .push(button(export_button, "Export list", Message::Export)),
+            );
+
+        match status {
+            Some(status) => column.push(Text::new(status).size(14)),
+            None => column,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct SyncFolder {
+    folder_input: text_input::State,
+    folder_button: button::State,
+}
+
+impl SyncFolder {
+    fn view(
+        &mut self,
+        folder_value: &str,
+        location: &Location,
+        status: Option<&String>,
+    ) -> Column<Message> {
+        let SyncFolder {
+            folder_input,
+            folder_button,
+        } = self;
+
+        let current = match &location.folder {
+            Some(folder) => format!("Synced through {}", folder.display()),
+            None => String::from("Kept on this computer only"),
+        };
+
+        let column = Column::new()
+            .spacing(10)
+            .push(Text::new("Sync folder").size(20))
+            .push(Text::new(current).size(14).color([0.5, 0.5, 0.5]))
+            .push(
+                TextInput::new(
+                    folder_input,
+                    "Shared folder, empty for none",
+                    folder_value,
+                    Message::FolderChanged,
+                )
+                .padding(8)
+                .size(16)
+                .on_submit(Message::SetFolder),
+            )
+            .push(
+                Button::new(folder_button, Text::new("Use folder").size(16))
+                    .on_press(Message::SetFolder)
+                    .padding(8)
+                    .style(style::Button::Filter { selected: false }),
+            );
+
+        match status {
+            Some(status) => column.push(Text::new(status).size(14)),
+            None => column,
+        }
+    }
+}
 
 #[derive(Debug, Default, Clone)]
 pub struct Controls {
     all_button: button::State,
     active_button: button::State,
     completed_button: button::State,
+    overdue_button: button::State,
+    manual_button: button::State,
+    due_button: button::State,
+    priority_button: button::State,
+    search_input: text_input::State,
+    tag_buttons: Vec<button::State>,
+    undo_button: button::State,
+    redo_button: button::State,
 }
 
 impl Controls {
-    fn view(&mut self, tasks: &[Task], current_filter: Filter) -> Row<Message> {
+    fn view(
+        &mut self,
+        tasks: &[Task],
+        current_filter: Filter,
+        current_sort: Sort,
+        current_tag: Option<&str>,
+        search_value: &str,
+        history: &History,
+    ) -> Column<Message> {
         let Controls {
             all_button,
             active_button,
             completed_button,
+            overdue_button,
+            manual_button,
+            due_button,
+            priority_button,
+            search_input,
+            tag_buttons,
+            undo_button,
+            redo_button,
         } = self;
 
-        let tasks_left = tasks.iter().filter(|task| !task.completed).count();
+        let tasks_left: usize = tasks.iter().map(Task::leaves_left).sum();
 
         let filter_button = |state, label, filter, current_filter| {
             let label = Text::new(label).size(16);
@@ -352,7 +2197,16 @@ impl Controls {
             button.on_press(Message::FilterChanged(filter)).padding(8)
         };
 
-        Row::new()
+        let sort_button = |state, label, sort| {
+            let label = Text::new(label).size(16);
+            let button = Button::new(state, label).style(style::Button::Filter {
+                selected: sort == current_sort,
+            });
+
+            button.on_press(Message::SortChanged(sort)).padding(8)
+        };
+
+        let filters = Row::new()
             .spacing(20)
             .align_items(Align::Center)
             .push(
@@ -384,8 +2238,95 @@ impl Controls {
                         "Completed",
                         Filter::Completed,
                         current_filter,
+                    ))
+                    .push(filter_button(
+                        overdue_button,
+                        "Overdue",
+                        Filter::Overdue,
+                        current_filter,
                     )),
+            );
+
+        // nothing to undo or redo disables the button
+        let history_button = |state, label, message: Option<Message>| {
+            let button = Button::new(state, Text::new(label).size(16))
+                .style(style::Button::Filter { selected: false })
+                .padding(8);
+
+            match message {
+                Some(message) => button.on_press(message),
+                None => button,
+            }
+        };
+
+        let sorts = Row::new()
+            .spacing(10)
+            .align_items(Align::Center)
+            .push(history_button(
+                undo_button,
+                "Undo",
+                Some(Message::Undo).filter(|_| !history.undo.is_empty()),
+            ))
+            .push(history_button(
+                redo_button,
+                "Redo",
+                Some(Message::Redo).filter(|_| !history.redo.is_empty()),
+            ))
+            .push(
+                Text::new("Sort by")
+                    .width(Length::Fill)
+                    .horizontal_alignment(HorizontalAlignment::Right)
+                    .size(16),
             )
+            .push(sort_button(manual_button, "Manual", Sort::Manual))
+            .push(sort_button(due_button, "Due date", Sort::Due))
+            .push(sort_button(priority_button, "Priority", Sort::Priority));
+
+        let search = TextInput::new(
+            search_input,
+            "Search tasks...",
+            search_value,
+            Message::SearchChanged,
+        )
+        .padding(8)
+        .size(16);
+
+        let mut tags = BTreeMap::new();
+        count_tags(tasks, &mut tags);
+
+        // the selected tag stays in the cloud, to be cleared, even once no task carries it
+        if let Some(tag) = current_tag {
+            tags.entry(tag.to_owned()).or_insert(0);
+        }
+
+        tag_buttons.resize_with(tags.len(), button::State::new);
+
+        let mut cloud = Column::new().spacing(10);
+        let mut row = Row::new().spacing(10);
+
+        for (i, ((tag, count), state)) in tags.into_iter().zip(tag_buttons.iter_mut()).enumerate() {
+            if i > 0 && i % TAGS_PER_ROW == 0 {
+                cloud = cloud.push(row);
+                row = Row::new().spacing(10);
+            }
+
+            let selected = current_tag == Some(tag.as_str());
+            let label = Text::new(format!("{} ({})", tag_label(&tag), count)).size(14);
+
+            row = row.push(
+                Button::new(state, label)
+                    .style(style::Button::Filter { selected })
+                    .on_press(Message::TagSelected(tag))
+                    .padding(6),
+            );
+        }
+
+        Column::new()
+            .spacing(10)
+            .push(filters)
+            .push(sorts)
+            .push(search)
+            .push(cloud.push(row))
     }
 }
 
@@ -394,6 +2335,7 @@ pub enum Filter {
     All,
     Active,
     Completed,
+    Overdue,
 }
 
 impl Default for Filter {
@@ -408,6 +2350,38 @@ impl Filter {
             Filter::All => true,
             Filter::Active => !task.completed,
             Filter::Completed => task.completed,
+            Filter::Overdue => task.is_due(Local::now().naive_local()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Sort {
+    #[serde(alias = "Created")]
+    Manual,
+    Due,
+    Priority,
+}
+
+impl Default for Sort {
+    fn default() -> Self {
+        Sort::Manual
+    }
+}
+
+impl Sort {
+    fn compare(&self, a: &Task, b: &Task) -> Ordering {
+        match self {
+            // the sort is stable, the tasks keep the order they were put in
+            Sort::Manual => Ordering::Equal,
+            // tasks without a due date come last
+            Sort::Due => match (a.due, b.due) {
+                (Some(a), Some(b)) => a.cmp(&b),
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (None, None) => Ordering::Equal,
+            },
+            Sort::Priority => b.priority.cmp(&a.priority),
         }
     }
 }
@@ -437,40 +2411,792 @@ fn empty_message(message: &str) -> Element<'static, Message> {
     .into()
 }
 
-// Fonts
-const ICONS: Font = Font::External {
-    name: "Icons",
-    bytes: include_bytes!("../resources/icons.ttf"),
-};
-
-fn icon(unicode: char) -> Text {
-    Text::new(&unicode.to_string())
-        .font(ICONS)
-        .width(Length::Units(20))
+// The icon font of the iced example is not part of resources/, plain labels instead
+fn edit_icon() -> Text {
+    Text::new("Edit")
         .horizontal_alignment(HorizontalAlignment::Center)
-        .size(20)
+        .size(16)
 }
 
-fn edit_icon() -> Text {
-    icon('\u{F303}')
+// Import and export
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    TodoTxt,
+    Markdown,
+}
+
+impl Format {
+    fn from_path(path: &std::path::Path) -> Format {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("md") | Some("markdown") => Format::Markdown,
+            _ => Format::TodoTxt,
+        }
+    }
+
+    // The list is named after the first heading of a Markdown file, or after the file
+    fn parse(self, text: &str, name: String) -> List {
+        match self {
+            Format::TodoTxt => List {
+                tasks: nest_todo_txt(text),
+                ..List::new(name)
+            },
+            Format::Markdown => {
+                let name = text
+                    .lines()
+                    .find(|line| line.starts_with("# "))
+                    .map(|heading| heading[2..].trim().to_owned())
+                    .unwrap_or(name);
+
+                List {
+                    tasks: parse_markdown(text),
+                    ..List::new(name)
+                }
+            }
+        }
+    }
+
+    fn write(self, list: &List) -> String {
+        let mut text = String::new();
+
+        match self {
+            // subtasks follow their parent, which they name with `parent:`
+            Format::TodoTxt => write_todo_txt(&list.tasks, None, &mut text),
+            Format::Markdown => {
+                text.push_str(&format!("# {}\n\n", list.name));
+                write_markdown(&list.tasks, 0, &mut text);
+            }
+        }
+
+        text
+    }
+}
+
+// The priority, the description, the due date and the recurrence, as todo.txt writes them;
+// completed tasks keep their priority as `pri:A`
+fn inline(task: &Task) -> String {
+    let mut words = Vec::new();
+
+    let letter = match task.priority {
+        Priority::High => Some("A"),
+        Priority::Normal => None,
+        Priority::Low => Some("C"),
+    };
+
+    if let (Some(letter), false) = (letter, task.completed) {
+        words.push(format!("({})", letter));
+    }
+
+    words.push(task.description.clone());
+
+    // todo.txt due dates are days, the time is only written when it is not the default
+    if let Some(due) = task.due {
+        if due.time() == NaiveTime::from_hms(9, 0, 0) {
+            words.push(format!("due:{}", due.format(DATE_FORMAT)));
+        } else {
+            words.push(format!("due:{}", due.format("%Y-%m-%dT%H:%M")));
+        }
+    }
+
+    // a value without spaces, such as `rec:weekly,mon,fri`
+    if let Some(recurrence) = &task.recurrence {
+        words.push(format!("rec:{}", recurrence.to_string().replace(' ', ",")));
+    }
+
+    if let (Some(letter), true) = (letter, task.completed) {
+        words.push(format!("pri:{}", letter));
+    }
+
+    words.join(" ")
+}
+
+// A `(A)` priority, or `pri:A` on completed tasks
+fn parse_priority(word: &str) -> Option<Priority> {
+    let letter = if word.len() == 3 && word.starts_with('(') && word.ends_with(')') {
+        &word[1..2]
+    } else if word.starts_with("pri:") && word.len() == 5 {
+        &word[4..]
+    } else {
+        return None;
+    };
+
+    match letter {
+        "A" => Some(Priority::High),
+        "B" => Some(Priority::Normal),
+        letter if letter.chars().all(|c| c.is_ascii_uppercase()) => Some(Priority::Low),
+        _ => None,
+    }
+}
+
+fn parse_inline(words: &[&str]) -> Task {
+    let mut task = Task::new(String::new());
+    let mut words = words;
+
+    if let Some(priority) = words.first().and_then(|word| parse_priority(word)) {
+        task.priority = priority;
+        words = &words[1..];
+    }
+
+    // the creation date is not kept
+    if words.first().map_or(false, |word| {
+        NaiveDate::parse_from_str(word, DATE_FORMAT).is_ok()
+    }) {
+        words = &words[1..];
+    }
+
+    let mut description = Vec::new();
+
+    for word in words {
+        if word.starts_with("due:") {
+            let value = &word[4..];
+            let due = NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M")
+                .ok()
+                .or_else(|| parse_due(value).ok().and_then(|due| due));
+
+            if due.is_some() {
+                task.due = due;
+                continue;
+            }
+        }
+
+        if word.starts_with("rec:") {
+            if let Some(Some(recurrence)) = Recurrence::parse(&word[4..]) {
+                task.recurrence = Some(recurrence);
+                continue;
+            }
+        }
+
+        match parse_priority(word) {
+            Some(priority) if word.starts_with("pri:") => task.priority = priority,
+            _ => description.push(*word),
+        }
+    }
+
+    task.description = description.join(" ");
+    task
+}
+
+// The task of a line, with its `id:` and the `id:` of its `parent:`
+fn parse_todo_txt(line: &str) -> Option<(Task, Option<String>, Option<String>)> {
+    let mut id = None;
+    let mut parent = None;
+
+    let words: Vec<&str> = line
+        .split_whitespace()
+        .filter(|word| {
+            if word.starts_with("id:") && word.len() > 3 {
+                id = Some(word[3..].to_owned());
+            } else if word.starts_with("parent:") && word.len() > 7 {
+                parent = Some(word[7..].to_owned());
+            } else {
+                return true;
+            }
+
+            false
+        })
+        .collect();
+
+    let task = match words.as_slice() {
+        [] => return None,
+        ["x", ..] => {
+            // the completion date is not kept
+            let rest = match words[1..].first() {
+                Some(date) if NaiveDate::parse_from_str(date, DATE_FORMAT).is_ok() => &words[2..],
+                _ => &words[1..],
+            };
+
+            Task {
+                completed: true,
+                ..parse_inline(rest)
+            }
+        }
+        words => parse_inline(words),
+    };
+
+    Some((task, id, parent)).filter(|(task, _, _)| !task.description.is_empty())
+}
+
+// Subtasks follow their parent, as they are written; one whose `parent:` is not among the tasks
+// it follows is put at the top
+fn nest_todo_txt(text: &str) -> Vec<Task> {
+    let mut tasks = Vec::new();
+    // the ID of the last task at every depth, outermost first
+    let mut open: Vec<Option<String>> = Vec::new();
+
+    for (task, id, parent) in text.lines().filter_map(parse_todo_txt) {
+        let depth = parent
+            .and_then(|parent| open.iter().rposition(|id| id.as_ref() == Some(&parent)))
+            .map_or(0, |position| position + 1);
+
+        insert_at(&mut tasks, depth, task);
+        open.truncate(depth);
+        open.push(id);
+    }
+
+    tasks
+}
+
+// Imported, the tasks are given new IDs, these only link subtasks to their parent
+fn write_todo_txt(tasks: &[Task], parent: Option<&str>, text: &mut String) {
+    for task in tasks {
+        if task.completed {
+            text.push_str("x ");
+        }
+        text.push_str(&inline(task));
+
+        if !task.children.is_empty() {
+            text.push_str(&format!(" id:{}", task.id));
+        }
+        if let Some(parent) = parent {
+            text.push_str(&format!(" parent:{}", parent));
+        }
+        text.push('\n');
+
+        write_todo_txt(&task.children, Some(&task.id), text);
+    }
+}
+
+// Checklist items are `- [ ]` or `- [x]`, nested by indentation; the other lines indented
+// under an item are its notes
+fn parse_markdown(text: &str) -> Vec<Task> {
+    let mut tasks = Vec::new();
+    // the indentation of every open item, outermost first
+    let mut open: Vec<usize> = Vec::new();
+
+    for line in text.lines() {
+        let content = line.trim_start();
+        let indent = line.len() - content.len();
+
+        if content.is_empty() {
+            continue;
+        }
+
+        let completed = match content.get(..6) {
+            Some("- [ ] ") | Some("* [ ] ") => Some(false),
+            Some("- [x] ") | Some("- [X] ") | Some("* [x] ") | Some("* [X] ") => Some(true),
+            _ => None,
+        };
+
+        match completed {
+            Some(completed) => {
+                while open.last().map_or(false, |open| *open >= indent) {
+                    open.pop();
+                }
+
+                let words: Vec<&str> = content[6..].split_whitespace().collect();
+                let task = Task {
+                    completed,
+                    ..parse_inline(&words)
+                };
+
+                insert_at(&mut tasks, open.len(), task);
+                open.push(indent);
+            }
+            None => {
+                if let Some(depth) = open.iter().rposition(|open| *open < indent) {
+                    if let Some(task) = last_at(&mut tasks, depth) {
+                        if !task.notes.is_empty() {
+                            task.notes.push('\n');
+                        }
+                        task.notes.push_str(content);
+                    }
+                }
+            }
+        }
+    }
+
+    tasks
+}
+
+fn insert_at(tasks: &mut Vec<Task>, depth: usize, task: Task) {
+    if depth > 0 && !tasks.is_empty() {
+        let last = tasks.len() - 1;
+
+        insert_at(&mut tasks[last].children, depth - 1, task);
+    } else {
+        tasks.push(task);
+    }
+}
+
+fn last_at(tasks: &mut [Task], depth: usize) -> Option<&mut Task> {
+    let last = tasks.last_mut()?;
+
+    if depth == 0 || last.children.is_empty() {
+        Some(last)
+    } else {
+        last_at(&mut last.children, depth - 1)
+    }
+}
+
+fn write_markdown(tasks: &[Task], depth: usize, text: &mut String) {
+    let indent = "  ".repeat(depth);
+
+    for task in tasks {
+        text.push_str(&format!(
+            "{}- [{}] {}\n",
+            indent,
+            if task.completed { "x" } else { " " },
+            inline(task)
+        ));
+
+        for line in task.notes.lines() {
+            text.push_str(&format!("{}  {}\n", indent, line));
+        }
+
+        write_markdown(&task.children, depth + 1, text);
+    }
+}
+
+async fn import(path: std::path::PathBuf) -> Result<List, LoadError> {
+    let text = async_std::fs::read_to_string(&path)
+        .await
+        .map_err(|_| LoadError::FileError)?;
+
+    let name = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| String::from("Imported"));
+
+    Ok(Format::from_path(&path).parse(&text, name))
+}
+
+async fn export(path: std::path::PathBuf, list: List) -> Result<(), SaveError> {
+    let text = Format::from_path(&path).write(&list);
+
+    async_std::fs::write(path, text)
+        .await
+        .map_err(|_| SaveError::WriteError)
 }
 
-fn delete_icon() -> Text {
-    icon('\u{F1F8}')
+// Imports into, or exports the current list from, what was saved, without a window
+async fn run_command(flag: &str, path: std::path::PathBuf) -> Result<String, String> {
+    let location = Location::load();
+
+    let saved = match SavedState::load(location.clone()).await {
+        Ok(saved) => saved,
+        Err(LoadError::FileError) => SavedState::default(),
+        Err(_) => return Err(String::from("The saved tasks could not be read")),
+    };
+    let mut state = State::from_saved(saved, location, Keep::Backup);
+
+    if flag == "--import" {
+        let list = import(path)
+            .await
+            .map_err(|_| String::from("The file could not be read"))?;
+        let message = format!("Imported {} tasks into {}", list.tasks.len(), list.name);
+
+        state.lists.push(list);
+        state
+            .saved()
+            .save(state.location.clone(), state.keep.take())
+            .await
+            .map_err(|_| String::from("The tasks could not be saved"))?;
+
+        Ok(message)
+    } else {
+        let list = state.lists[state.current].clone();
+        let message = format!("Exported {}", list.name);
+
+        export(path, list)
+            .await
+            .map_err(|_| String::from("The file could not be written"))?;
+
+        Ok(message)
+    }
 }
 
 // Persistence
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 struct SavedState {
+    version: usize,
     input_value: String,
     filter: Filter,
-    tasks: Vec<Task>,
+    #[serde(default)]
+    sort: Sort,
+    #[serde(default)]
+    tag: Option<String>,
+    #[serde(default)]
+    search: String,
+    #[serde(default)]
+    lists: Vec<List>,
+    #[serde(default)]
+    current: usize,
+    // lists and tasks removed, by ID, with when they were, so that merging does not bring
+    // them back
+    #[serde(default)]
+    deleted: BTreeMap<String, i64>,
+}
+
+// Files without a version are version 0
+const VERSION: usize = 2;
+
+// Brings a file from every older version to the next one, in order
+const MIGRATIONS: [fn(&mut Value); VERSION] = [into_lists, with_ids];
+
+// Version 1 holds named lists instead of a single list of tasks
+fn into_lists(value: &mut Value) {
+    if let Some(object) = value.as_object_mut() {
+        let tasks = object.remove("tasks").unwrap_or_else(|| json!([]));
+
+        object
+            .entry("lists")
+            .or_insert_with(|| json!([{ "name": "Tasks", "tasks": tasks }]));
+    }
+}
+
+// Version 2 gives lists and tasks IDs; they are made from where they are and what they say,
+// so that every device migrating the same file agrees on them
+fn with_ids(value: &mut Value) {
+    if let Some(lists) = value["lists"].as_array_mut() {
+        for (i, list) in lists.iter_mut().enumerate() {
+            if let Some(list) = list.as_object_mut() {
+                let key = i.to_string();
+
+                list.entry("id").or_insert_with(|| json!(stable_id(&key)));
+
+                if let Some(tasks) = list.get_mut("tasks").and_then(Value::as_array_mut) {
+                    tasks_with_ids(tasks, &key);
+                }
+            }
+        }
+    }
+}
+
+fn tasks_with_ids(tasks: &mut Vec<Value>, parent: &str) {
+    for (i, task) in tasks.iter_mut().enumerate() {
+        if let Some(task) = task.as_object_mut() {
+            let key = format!("{}/{}", parent, i);
+            let description = task
+                .get("description")
+                .and_then(Value::as_str)
+                .unwrap_or_default()
+                .to_owned();
+
+            task.entry("id")
+                .or_insert_with(|| json!(stable_id(&format!("{} {}", key, description))));
+
+            if let Some(children) = task.get_mut("children").and_then(Value::as_array_mut) {
+                tasks_with_ids(children, &key);
+            }
+        }
+    }
+}
+
+// FNV-1a, the same on every device and build
+fn stable_id(key: &str) -> String {
+    let hash = key.bytes().fold(0xcbf2_9ce4_8422_2325u64, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    });
+
+    format!("{:016x}", hash)
+}
+
+fn new_id() -> String {
+    use ring::rand::{SecureRandom, SystemRandom};
+
+    let mut bytes = [0u8; 8];
+    let _ = SystemRandom::new().fill(&mut bytes);
+
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn data_dir() -> std::path::PathBuf {
+    if let Some(project_dirs) = directories::ProjectDirs::from("rs", "Iced", "Todos") {
+        project_dirs.data_dir().into()
+    } else {
+        std::env::current_dir().unwrap_or(std::path::PathBuf::new())
+    }
+}
+
+// Where the tasks are kept; in a synced folder every device writes a file of its own, and
+// reads all of them
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct Location {
+    #[serde(default)]
+    folder: Option<std::path::PathBuf>,
+    device: String,
+}
+
+impl Location {
+    // Kept on this computer, the device is named the first time
+    fn load() -> Location {
+        let location = std::fs::read_to_string(data_dir().join("location.json"))
+            .ok()
+            .and_then(|contents| serde_json::from_str::<Location>(&contents).ok())
+            .filter(|location| !location.device.is_empty());
+
+        match location {
+            Some(location) => location,
+            None => {
+                let location = Location {
+                    folder: None,
+                    device: new_id(),
+                };
+                let _ = location.store();
+
+                location
+            }
+        }
+    }
+
+    fn store(&self) -> Result<(), SaveError> {
+        let json = serde_json::to_string_pretty(self).map_err(|_| SaveError::FormatError)?;
+        let dir = data_dir();
+
+        std::fs::create_dir_all(&dir).map_err(|_| SaveError::DirectoryError)?;
+        std::fs::write(dir.join("location.json"), json).map_err(|_| SaveError::WriteError)
+    }
+
+    fn folder_value(&self) -> String {
+        self.folder
+            .as_ref()
+            .map(|folder| folder.display().to_string())
+            .unwrap_or_default()
+    }
+
+    fn dir(&self) -> std::path::PathBuf {
+        self.folder.clone().unwrap_or_else(data_dir)
+    }
+
+    fn file(&self) -> String {
+        match self.folder {
+            Some(_) => format!("todos-{}.json", self.device),
+            None => String::from("todos.json"),
+        }
+    }
+
+    // The file of this device, with `suffix` appended
+    fn path(&self, suffix: &str) -> std::path::PathBuf {
+        self.dir().join(format!("{}{}", self.file(), suffix))
+    }
+
+    // The files of every device, this one first
+    fn devices(&self) -> Vec<(String, std::path::PathBuf)> {
+        let mut devices = vec![(self.device.clone(), self.path(""))];
+
+        if let Some(Ok(entries)) = self.folder.as_ref().map(std::fs::read_dir) {
+            let mut others: Vec<_> = entries
+                .flatten()
+                .filter_map(|entry| {
+                    let name = entry.file_name().to_string_lossy().into_owned();
+
+                    if name.starts_with("todos-") && name.ends_with(".json") {
+                        let device = name["todos-".len()..name.len() - ".json".len()].to_owned();
+
+                        Some((device, entry.path()))
+                    } else {
+                        None
+                    }
+                })
+                .filter(|(device, _)| *device != self.device)
+                .collect();
+            others.sort();
+
+            devices.extend(others);
+        }
+
+        devices
+    }
+}
+
+// A task as a device saved it, taken out of its tree
+struct Placed {
+    task: Task,
+    list: String,
+    parent: Option<String>,
+    index: usize,
+}
+
+fn flatten(tasks: &[Task], list: &str, parent: Option<&str>, placed: &mut HashMap<String, Placed>) {
+    for (index, task) in tasks.iter().enumerate() {
+        // devices come in order, the later one wins a tie
+        if placed
+            .get(&task.id)
+            .map_or(true, |known| known.task.modified <= task.modified)
+        {
+            placed.insert(
+                task.id.clone(),
+                Placed {
+                    task: Task {
+                        children: Vec::new(),
+                        state: TaskState::default(),
+                        ..task.clone()
+                    },
+                    list: list.to_owned(),
+                    parent: parent.map(String::from),
+                    index,
+                },
+            );
+        }
+
+        flatten(&task.children, list, Some(&task.id), placed);
+    }
+}
+
+fn build(
+    id: &str,
+    placed: &mut HashMap<String, Placed>,
+    children: &HashMap<String, Vec<(usize, String)>>,
+) -> Option<Task> {
+    let mut task = placed.remove(id)?.task;
+
+    if let Some(ids) = children.get(id) {
+        task.children = ids
+            .iter()
+            .filter_map(|(_, child)| build(child, placed, children))
+            .collect();
+    }
+
+    Some(task)
+}
+
+// Every device saves all the tasks; the most recent version of every list and task is kept,
+// a tie going to the device named last, so that every device merges to the same tasks.
+// The settings are those of `device`
+fn merge(mut states: Vec<(String, SavedState)>, device: &str) -> SavedState {
+    states.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let mut deleted: BTreeMap<String, i64> = BTreeMap::new();
+    let mut lists: HashMap<String, (List, usize)> = HashMap::new();
+    let mut placed: HashMap<String, Placed> = HashMap::new();
+
+    for (_, saved) in &states {
+        for (id, time) in &saved.deleted {
+            let known = deleted.entry(id.clone()).or_insert(*time);
+            *known = (*known).max(*time);
+        }
+
+        for (index, list) in saved.lists.iter().enumerate() {
+            if lists
+                .get(&list.id)
+                .map_or(true, |(known, _)| known.modified <= list.modified)
+            {
+                let list = List {
+                    id: list.id.clone(),
+                    modified: list.modified,
+                    name: list.name.clone(),
+                    tasks: Vec::new(),
+                    archived: list.archived,
+                };
+
+                lists.insert(list.id.clone(), (list, index));
+            }
+
+            flatten(&list.tasks, &list.id, None, &mut placed);
+        }
+    }
+
+    // what was changed after it was deleted elsewhere stays
+    lists.retain(|id, (list, _)| deleted.get(id).map_or(true, |time| *time < list.modified));
+    placed.retain(|id, placed| {
+        deleted
+            .get(id)
+            .map_or(true, |time| *time < placed.task.modified)
+    });
+
+    let mut merged: Vec<(usize, List)> = lists
+        .drain()
+        .map(|(_, (list, index))| (index, list))
+        .collect();
+    merged.sort_by(|a, b| (a.0, &a.1.id).cmp(&(b.0, &b.1.id)));
+    let mut merged: Vec<List> = merged.into_iter().map(|(_, list)| list).collect();
+
+    if merged.is_empty() {
+        merged.push(List::default());
+    }
+
+    // tasks of a list or a parent that is gone go to the top of their list, or of the first
+    let list_of = |placed: &Placed| {
+        merged
+            .iter()
+            .position(|list| list.id == placed.list)
+            .unwrap_or(0)
+    };
+
+    let mut roots: Vec<Vec<(usize, String)>> = vec![Vec::new(); merged.len()];
+    let mut children: HashMap<String, Vec<(usize, String)>> = HashMap::new();
+
+    for (id, task) in &placed {
+        match &task.parent {
+            Some(parent) if placed.contains_key(parent) => children
+                .entry(parent.clone())
+                .or_insert_with(Vec::new)
+                .push((task.index, id.clone())),
+            _ => roots[list_of(task)].push((task.index, id.clone())),
+        }
+    }
+
+    for ids in roots.iter_mut().chain(children.values_mut()) {
+        ids.sort();
+    }
+
+    let mut tops: Vec<Vec<Task>> = roots
+        .iter()
+        .map(|ids| {
+            ids.iter()
+                .filter_map(|(_, id)| build(id, &mut placed, &children))
+                .collect()
+        })
+        .collect();
+
+    // what is left is a loop of parents, from concurrent indentation
+    let mut left: Vec<(String, usize)> = placed
+        .iter()
+        .map(|(id, task)| (id.clone(), list_of(task)))
+        .collect();
+    left.sort();
+
+    for (id, list) in left {
+        if let Some(task) = build(&id, &mut placed, &children) {
+            tops[list].push(task);
+        }
+    }
+
+    for (list, tasks) in merged.iter_mut().zip(tops) {
+        list.tasks = tasks;
+    }
+
+    let own = states
+        .iter()
+        .find(|(name, _)| name == device)
+        .or_else(|| states.first())
+        .map(|(_, saved)| saved.clone())
+        .unwrap_or_default();
+
+    let current = own
+        .lists
+        .get(own.current)
+        .and_then(|current| merged.iter().position(|list| list.id == current.id))
+        .unwrap_or(0);
+
+    SavedState {
+        version: VERSION,
+        lists: merged,
+        current,
+        deleted,
+        ..own
+    }
+}
+
+async fn open_folder(location: Location) -> Result<Option<SavedState>, LoadError> {
+    match SavedState::load(location).await {
+        Ok(saved) => Ok(Some(saved)),
+        Err(LoadError::FileError) => Ok(None),
+        Err(error) => Err(error),
+    }
+}
+
+// What becomes of the file being replaced
+#[derive(Debug, Clone, Copy)]
+enum Keep {
+    Backup,
+    Broken,
 }
 
 #[derive(Debug, Clone)]
 enum LoadError {
     FileError,
     FormatError,
+    VersionError,
 }
 
 #[derive(Debug, Clone)]
@@ -482,25 +3208,35 @@ enum SaveError {
 }
 
 impl SavedState {
-    fn path() -> std::path::PathBuf {
-        let mut path =
-            if let Some(project_dirs) = directories::ProjectDirs::from("rs", "Iced", "Todos") {
-                project_dirs.data_dir().into()
-            } else {
-                std::env::current_dir().unwrap_or(std::path::PathBuf::new())
-            };
+    fn parse(contents: &str) -> Result<SavedState, LoadError> {
+        let mut value: Value =
+            serde_json::from_str(contents).map_err(|_| LoadError::FormatError)?;
+
+        if !value.is_object() {
+            return Err(LoadError::FormatError);
+        }
+
+        let version = value["version"].as_u64().unwrap_or(0) as usize;
+
+        // never read, and then overwritten, by an older version
+        if version > VERSION {
+            return Err(LoadError::VersionError);
+        }
 
-        path.push("todos.json");
+        for migrate in &MIGRATIONS[version..] {
+            migrate(&mut value);
+        }
+        value["version"] = json!(VERSION);
 
-        path
+        serde_json::from_value(value).map_err(|_| LoadError::FormatError)
     }
 
-    async fn load() -> Result<SavedState, LoadError> {
+    async fn load_from(path: std::path::PathBuf) -> Result<SavedState, LoadError> {
         use async_std::prelude::*;
 
         let mut contents = String::new();
 
-        let mut file = async_std::fs::File::open(Self::path())
+        let mut file = async_std::fs::File::open(path)
             .await
             .map_err(|_| LoadError::FileError)?;
 
@@ -508,15 +3244,57 @@ impl SavedState {
             .await
             .map_err(|_| LoadError::FileError)?;
 
-        serde_json::from_str(&contents).map_err(|_| LoadError::FormatError)
+        Self::parse(&contents)
+    }
+
+    // Merges the files of every device; only the file of this one has to be readable, the
+    // others may be halfway synced
+    async fn load(location: Location) -> Result<SavedState, LoadError> {
+        let mut states = Vec::new();
+
+        for (device, path) in location.devices() {
+            match (Self::load_from(path).await, device == location.device) {
+                (Ok(saved), _) => states.push((device, saved)),
+                (Err(LoadError::FileError), _) | (Err(_), false) => {}
+                (Err(error), true) => return Err(error),
+            }
+        }
+
+        if states.is_empty() {
+            return Err(LoadError::FileError);
+        }
+
+        Ok(merge(states, &location.device))
+    }
+
+    // The backups that can be read, newest first
+    async fn backups(location: Location) -> Vec<(usize, SavedState)> {
+        let mut backups = Vec::new();
+
+        for number in 1..=BACKUPS {
+            if let Ok(saved) = Self::load_from(location.path(&format!(".{}", number))).await {
+                backups.push((number, saved));
+            }
+        }
+
+        backups
     }
 
-    async fn save(self) -> Result<(), SaveError> {
+    async fn save(self, location: Location, keep: Option<Keep>) -> Result<(), SaveError> {
+        self.write(&location, keep).await?;
+
+        // This is a simple way to save at most once every couple seconds
+        async_std::task::sleep(std::time::Duration::from_secs(2)).await;
+
+        Ok(())
+    }
+
+    async fn write(&self, location: &Location, keep: Option<Keep>) -> Result<(), SaveError> {
         use async_std::prelude::*;
 
-        let json = serde_json::to_string_pretty(&self).map_err(|_| SaveError::FormatError)?;
+        let json = serde_json::to_string_pretty(self).map_err(|_| SaveError::FormatError)?;
 
-        let path = Self::path();
+        let path = location.path("");
 
         if let Some(dir) = path.parent() {
             async_std::fs::create_dir_all(dir)
@@ -524,25 +3302,67 @@ impl SavedState {
                 .map_err(|_| SaveError::DirectoryError)?;
         }
 
+        if path.exists() {
+            match keep {
+                Some(Keep::Backup) => {
+                    for number in (1..BACKUPS).rev() {
+                        let _ = async_std::fs::rename(
+                            location.path(&format!(".{}", number)),
+                            location.path(&format!(".{}", number + 1)),
+                        )
+                        .await;
+                    }
+
+                    async_std::fs::copy(&path, location.path(".1"))
+                        .await
+                        .map_err(|_| SaveError::FileError)?;
+                }
+                Some(Keep::Broken) => {
+                    async_std::fs::copy(&path, location.path(".broken"))
+                        .await
+                        .map_err(|_| SaveError::FileError)?;
+                }
+                None => {}
+            }
+        }
+
+        // written aside and renamed over the file, a crash leaves either version whole
+        let temporary = location.path(".tmp");
+
         {
-            let mut file = async_std::fs::File::create(path)
+            let mut file = async_std::fs::File::create(&temporary)
                 .await
                 .map_err(|_| SaveError::FileError)?;
 
             file.write_all(json.as_bytes())
                 .await
                 .map_err(|_| SaveError::WriteError)?;
-        }
 
-        // This is a simple way to save at most once every couple seconds
-        async_std::task::sleep(std::time::Duration::from_secs(2)).await;
+            file.sync_all().await.map_err(|_| SaveError::WriteError)?;
+        }
 
-        Ok(())
+        async_std::fs::rename(&temporary, &path)
+            .await
+            .map_err(|_| SaveError::WriteError)
     }
 }
 
 mod style {
-    use iced::{button, Background, Color, Vector};
+    use iced::{button, container, Background, Color, Vector};
+
+    pub struct Reminder;
+
+    impl container::StyleSheet for Reminder {
+        fn style(&self) -> container::Style {
+            container::Style {
+                background: Some(Background::Color(Color::from_rgb(1.0, 0.95, 0.8))),
+                border_radius: 5,
+                border_width: 1,
+                border_color: Color::from_rgb(0.9, 0.7, 0.3),
+                ..container::Style::default()
+            }
+        }
+    }
 
     pub enum Button {
         Filter { selected: bool },
@@ -594,3 +3414,871 @@ mod style {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(year: i32, month: u32, day: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd(year, month, day).and_hms(18, 30, 0)
+    }
+
+    #[test]
+    fn monthly_recurrence_is_clamped_to_short_months() {
+        let recurrence = Recurrence::Monthly(31);
+        let now = at(2019, 1, 1);
+
+        let february = recurrence.next(Some(at(2019, 1, 31)), now);
+        assert_eq!(february, at(2019, 2, 28));
+        assert_eq!(recurrence.next(Some(february), now), at(2019, 3, 31));
+
+        let february = recurrence.next(Some(at(2020, 1, 31)), now);
+        assert_eq!(february, at(2020, 2, 29));
+        assert_eq!(recurrence.next(Some(february), now), at(2020, 3, 31));
+
+        assert_eq!(
+            recurrence.next(Some(at(2019, 12, 31)), now),
+            at(2020, 1, 31)
+        );
+    }
+
+    #[test]
+    fn weekly_recurrence_keeps_the_weekday_without_days() {
+        // a Wednesday
+        let due = at(2019, 11, 6);
+
+        assert_eq!(
+            Recurrence::Weekly(Vec::new()).next(Some(due), due),
+            at(2019, 11, 13)
+        );
+        assert_eq!(
+            Recurrence::Weekly(vec![Weekday::Mon, Weekday::Fri]).next(Some(due), due),
+            at(2019, 11, 8)
+        );
+        assert_eq!(
+            Recurrence::Weekly(vec![Weekday::Mon]).next(Some(due), due),
+            at(2019, 11, 11)
+        );
+        assert_eq!(
+            Recurrence::Weekly(vec![Weekday::Wed]).next(Some(due), due),
+            at(2019, 11, 13)
+        );
+    }
+
+    #[test]
+    fn after_completion_counts_from_now() {
+        let recurrence = Recurrence::AfterCompletion(3);
+        let now = at(2019, 11, 20);
+
+        assert_eq!(
+            recurrence.next(Some(at(2019, 11, 6)), now),
+            at(2019, 11, 23)
+        );
+        assert_eq!(
+            recurrence.next(None, now),
+            NaiveDate::from_ymd(2019, 11, 23).and_hms(9, 0, 0)
+        );
+    }
+
+    #[test]
+    fn recurrences_are_read_back_as_written() {
+        let recurrences = vec![
+            Recurrence::Daily,
+            Recurrence::Weekly(Vec::new()),
+            Recurrence::Weekly(vec![Weekday::Mon, Weekday::Thu]),
+            Recurrence::Monthly(31),
+            Recurrence::AfterCompletion(1),
+            Recurrence::AfterCompletion(10),
+        ];
+
+        for recurrence in recurrences {
+            assert_eq!(
+                Recurrence::parse(&recurrence.to_string()),
+                Some(Some(recurrence))
+            );
+        }
+
+        assert_eq!(
+            Recurrence::parse(" Weekly mon, fri "),
+            Some(Some(Recurrence::Weekly(vec![Weekday::Mon, Weekday::Fri])))
+        );
+        assert_eq!(Recurrence::parse(""), Some(None));
+        assert_eq!(Recurrence::parse("monthly 32"), None);
+        assert_eq!(Recurrence::parse("every 0 days"), None);
+        assert_eq!(Recurrence::parse("weekly someday"), None);
+        assert_eq!(Recurrence::parse("yearly"), None);
+    }
+
+    fn tasks() -> Vec<Task> {
+        let mut trip = Task {
+            id: String::from("trip"),
+            ..Task::new(String::from("Plan the trip +holidays"))
+        };
+        trip.children.push(Task {
+            notes: String::from("two nights\nnear the station"),
+            ..Task::new(String::from("Book a hotel @laptop"))
+        });
+
+        vec![
+            Task {
+                priority: Priority::High,
+                due: Some(NaiveDate::from_ymd(2019, 11, 6).and_hms(9, 0, 0)),
+                recurrence: Some(Recurrence::Weekly(vec![Weekday::Mon, Weekday::Thu])),
+                ..Task::new(String::from("Call mom @phone +family"))
+            },
+            Task {
+                priority: Priority::Low,
+                completed: true,
+                due: Some(NaiveDate::from_ymd(2019, 11, 7).and_hms(17, 45, 0)),
+                ..Task::new(String::from("Buy milk +groceries"))
+            },
+            trip,
+        ]
+    }
+
+    fn assert_same(read: &Task, written: &Task) {
+        assert_eq!(read.description, written.description);
+        assert_eq!(read.completed, written.completed);
+        assert_eq!(read.priority, written.priority);
+        assert_eq!(read.due, written.due);
+        assert_eq!(read.recurrence, written.recurrence);
+        assert_eq!(read.children.len(), written.children.len());
+    }
+
+    #[test]
+    fn todo_txt_is_read_back_as_written() {
+        let list = List {
+            tasks: tasks(),
+            ..List::new(String::from("Home"))
+        };
+
+        let text = Format::TodoTxt.write(&list);
+        assert_eq!(
+            text,
+            "(A) Call mom @phone +family due:2019-11-06 rec:weekly,mon,thu\n\
+             x Buy milk +groceries due:2019-11-07T17:45 pri:C\n\
+             Plan the trip +holidays id:trip\n\
+             Book a hotel @laptop parent:trip\n"
+        );
+
+        let read = Format::TodoTxt.parse(&text, String::from("home"));
+        assert_eq!(read.name, "home");
+        assert_eq!(read.tasks.len(), 3);
+
+        let written = tasks();
+        for (read, written) in read.tasks.iter().zip(&written) {
+            assert_same(read, written);
+        }
+        assert_same(&read.tasks[2].children[0], &written[2].children[0]);
+
+        let tags: Vec<String> = parse_tags(&read.tasks[0].description).collect();
+        assert_eq!(tags, vec!["@phone", "+family"]);
+    }
+
+    #[test]
+    fn todo_txt_subtasks_follow_their_parent() {
+        let read = Format::TodoTxt.parse(
+            "Trip id:1\n\
+             Hotel parent:1 id:2\n\
+             Room parent:2\n\
+             Train parent:1\n\
+             Groceries\n\
+             Milk parent:1\n\
+             Lost parent:2\n",
+            String::from("home"),
+        );
+
+        let descriptions = |tasks: &[Task]| -> Vec<String> {
+            tasks.iter().map(|task| task.description.clone()).collect()
+        };
+
+        assert_eq!(
+            descriptions(&read.tasks),
+            vec!["Trip", "Groceries", "Milk", "Lost"]
+        );
+        assert_eq!(
+            descriptions(&read.tasks[0].children),
+            vec!["Hotel", "Train"]
+        );
+        assert_eq!(
+            descriptions(&read.tasks[0].children[0].children),
+            vec!["Room"]
+        );
+        assert!(read.tasks[0].id != "1" && read.tasks[0].children[0].id != "2");
+    }
+
+    #[test]
+    fn todo_txt_dates_and_priorities_are_read() {
+        let read = Format::TodoTxt.parse(
+            "x 2019-11-08 2019-11-01 Pay rent +home pri:A due:2019-11-05\n\
+             \n\
+             (B) 2019-11-01 Water the plants due:tomorrow\n\
+             (D) Someday\n",
+            String::from("home"),
+        );
+
+        assert_eq!(read.tasks.len(), 3);
+
+        let rent = &read.tasks[0];
+        assert!(rent.completed);
+        assert_eq!(rent.description, "Pay rent +home");
+        assert_eq!(rent.priority, Priority::High);
+        assert_eq!(
+            rent.due,
+            Some(NaiveDate::from_ymd(2019, 11, 5).and_hms(9, 0, 0))
+        );
+
+        let plants = &read.tasks[1];
+        assert!(!plants.completed);
+        assert_eq!(plants.description, "Water the plants due:tomorrow");
+        assert_eq!(plants.priority, Priority::Normal);
+        assert_eq!(plants.due, None);
+
+        assert_eq!(read.tasks[2].priority, Priority::Low);
+    }
+
+    #[test]
+    fn markdown_is_read_back_as_written() {
+        let list = List {
+            tasks: tasks(),
+            ..List::new(String::from("Home"))
+        };
+
+        let text = Format::Markdown.write(&list);
+        assert_eq!(
+            text,
+            "# Home\n\
+             \n\
+             - [ ] (A) Call mom @phone +family due:2019-11-06 rec:weekly,mon,thu\n\
+             - [x] Buy milk +groceries due:2019-11-07T17:45 pri:C\n\
+             - [ ] Plan the trip +holidays\n  \
+             - [ ] Book a hotel @laptop\n    \
+             two nights\n    \
+             near the station\n"
+        );
+
+        let read = Format::Markdown.parse(&text, String::from("home"));
+        assert_eq!(read.name, "Home");
+        assert_eq!(read.tasks.len(), 3);
+
+        let written = tasks();
+        for (read, written) in read.tasks.iter().zip(&written) {
+            assert_same(read, written);
+            assert_eq!(read.notes, written.notes);
+        }
+
+        let hotel = &read.tasks[2].children[0];
+        assert_same(hotel, &written[2].children[0]);
+        assert_eq!(hotel.notes, "two nights\nnear the station");
+
+        assert_eq!(Format::Markdown.write(&read), text);
+    }
+
+    fn task(id: &str, description: &str, modified: i64) -> Task {
+        Task {
+            id: id.to_owned(),
+            modified,
+            ..Task::new(description.to_owned())
+        }
+    }
+
+    fn list(id: &str, tasks: Vec<Task>) -> List {
+        List {
+            id: id.to_owned(),
+            tasks,
+            ..List::new(id.to_owned())
+        }
+    }
+
+    fn saved(lists: Vec<List>) -> SavedState {
+        SavedState {
+            version: VERSION,
+            lists,
+            ..SavedState::default()
+        }
+    }
+
+    // Every task as list, depth and description, in order
+    fn outline(saved: &SavedState) -> Vec<(String, usize, String)> {
+        fn walk(
+            tasks: &[Task],
+            list: &str,
+            depth: usize,
+            outline: &mut Vec<(String, usize, String)>,
+        ) {
+            for task in tasks {
+                outline.push((list.to_owned(), depth, task.description.clone()));
+                walk(&task.children, list, depth + 1, outline);
+            }
+        }
+
+        let mut outline = Vec::new();
+        for list in &saved.lists {
+            walk(&list.tasks, &list.id, 0, &mut outline);
+        }
+
+        outline
+    }
+
+    fn line(list: &str, depth: usize, description: &str) -> (String, usize, String) {
+        (list.to_owned(), depth, description.to_owned())
+    }
+
+    #[test]
+    fn the_latest_change_wins_and_ties_go_to_the_device_named_last() {
+        let a = saved(vec![list(
+            "home",
+            vec![task("1", "older", 10), task("2", "tie from a", 20)],
+        )]);
+        let b = saved(vec![list(
+            "home",
+            vec![task("1", "newer", 30), task("2", "tie from b", 20)],
+        )]);
+
+        for states in vec![
+            vec![
+                (String::from("a"), a.clone()),
+                (String::from("b"), b.clone()),
+            ],
+            vec![
+                (String::from("b"), b.clone()),
+                (String::from("a"), a.clone()),
+            ],
+        ] {
+            let merged = merge(states, "a");
+
+            assert_eq!(
+                outline(&merged),
+                vec![line("home", 0, "newer"), line("home", 0, "tie from b")]
+            );
+        }
+    }
+
+    #[test]
+    fn deletions_lose_to_later_changes() {
+        let mut a = saved(vec![list("home", Vec::new())]);
+        a.deleted.insert(String::from("1"), 20);
+        a.deleted.insert(String::from("2"), 20);
+
+        let b = saved(vec![list(
+            "home",
+            vec![
+                task("1", "edited before", 10),
+                task("2", "edited after", 30),
+            ],
+        )]);
+
+        let merged = merge(vec![(String::from("a"), a), (String::from("b"), b)], "a");
+
+        assert_eq!(outline(&merged), vec![line("home", 0, "edited after")]);
+        assert_eq!(merged.deleted.len(), 2);
+    }
+
+    #[test]
+    fn old_deletions_are_forgotten() {
+        let now = 1000 * DELETIONS_KEPT;
+        let mut state = State::default();
+        state
+            .deleted
+            .insert(String::from("old"), now - DELETIONS_KEPT);
+        state.deleted.insert(String::from("recent"), now - 1);
+
+        state.stamp(Some(now));
+
+        assert_eq!(state.deleted.keys().collect::<Vec<_>>(), vec!["recent"]);
+    }
+
+    #[test]
+    fn tasks_moved_to_another_list_are_not_copied() {
+        let a = saved(vec![
+            list("home", vec![task("1", "moved", 10)]),
+            list("work", Vec::new()),
+        ]);
+        let b = saved(vec![
+            list("home", Vec::new()),
+            list("work", vec![task("1", "moved", 20)]),
+        ]);
+
+        let merged = merge(vec![(String::from("a"), a), (String::from("b"), b)], "a");
+
+        assert_eq!(outline(&merged), vec![line("work", 0, "moved")]);
+    }
+
+    #[test]
+    fn parent_loops_from_concurrent_indents_are_broken() {
+        // each device indented one task under the other
+        let a = saved(vec![list(
+            "home",
+            vec![Task {
+                children: vec![task("2", "second", 20)],
+                ..task("1", "first", 10)
+            }],
+        )]);
+        let b = saved(vec![list(
+            "home",
+            vec![Task {
+                children: vec![task("1", "first", 20)],
+                ..task("2", "second", 10)
+            }],
+        )]);
+
+        let merged = merge(vec![(String::from("a"), a), (String::from("b"), b)], "a");
+
+        let outline = outline(&merged);
+        assert_eq!(outline.len(), 2);
+        assert_eq!(outline[0].1, 0);
+        assert_eq!(outline[1].1, 1);
+    }
+
+    #[test]
+    fn merging_does_not_depend_on_the_order_of_the_devices() {
+        let mut a = saved(vec![
+            list(
+                "home",
+                vec![
+                    Task {
+                        children: vec![task("2", "child", 15)],
+                        ..task("1", "parent", 10)
+                    },
+                    task("3", "kept", 40),
+                ],
+            ),
+            list("work", vec![task("4", "report", 5)]),
+        ]);
+        a.deleted.insert(String::from("5"), 25);
+
+        let b = saved(vec![
+            list(
+                "work",
+                vec![task("2", "child, moved", 30), task("5", "gone", 20)],
+            ),
+            list(
+                "home",
+                vec![task("3", "kept, older", 35), task("6", "new", 1)],
+            ),
+        ]);
+        let c = saved(vec![list("home", vec![task("1", "parent, renamed", 50)])]);
+
+        let states = vec![
+            (String::from("a"), a),
+            (String::from("b"), b),
+            (String::from("c"), c),
+        ];
+        let expected = outline(&merge(states.clone(), "a"));
+
+        let mut reversed = states.clone();
+        reversed.reverse();
+        let mut rotated = states;
+        rotated.rotate_left(1);
+
+        assert_eq!(outline(&merge(reversed, "a")), expected);
+        assert_eq!(outline(&merge(rotated, "a")), expected);
+        assert!(expected.contains(&line("work", 0, "child, moved")));
+        assert!(!expected
+            .iter()
+            .any(|(_, _, description)| description == "gone"));
+    }
+
+    #[test]
+    fn version_0_files_are_migrated() {
+        let contents = r#"{
+            "input_value": "",
+            "filter": "All",
+            "tasks": [
+                {
+                    "description": "Plan the trip",
+                    "completed": false,
+                    "children": [{ "description": "Book a hotel", "completed": true }]
+                },
+                { "description": "Water the plants", "completed": false }
+            ]
+        }"#;
+
+        let saved = SavedState::parse(contents).unwrap();
+        assert_eq!(saved.version, VERSION);
+        assert_eq!(saved.lists.len(), 1);
+        assert_eq!(saved.lists[0].name, "Tasks");
+
+        let tasks = &saved.lists[0].tasks;
+        assert_eq!(tasks.len(), 2);
+        assert_eq!(tasks[0].children[0].description, "Book a hotel");
+        assert!(tasks[0].children[0].completed);
+
+        // every device migrating the same file agrees on the IDs
+        let ids = vec![
+            saved.lists[0].id.clone(),
+            tasks[0].id.clone(),
+            tasks[0].children[0].id.clone(),
+            tasks[1].id.clone(),
+        ];
+        let again = SavedState::parse(contents).unwrap();
+        assert_eq!(again.lists[0].id, ids[0]);
+        assert_eq!(again.lists[0].tasks[0].id, ids[1]);
+        assert_eq!(again.lists[0].tasks[0].children[0].id, ids[2]);
+        assert_eq!(again.lists[0].tasks[1].id, ids[3]);
+
+        let mut unique = ids.clone();
+        unique.sort();
+        unique.dedup();
+        assert_eq!(unique.len(), ids.len());
+        assert!(ids.iter().all(|id| !id.is_empty()));
+    }
+
+    #[test]
+    fn version_1_files_are_given_ids() {
+        let contents = r#"{
+            "version": 1,
+            "input_value": "",
+            "filter": "All",
+            "lists": [
+                { "name": "Home", "tasks": [{ "description": "Sweep", "completed": false }] },
+                { "name": "Work", "tasks": [{ "description": "Sweep", "completed": true }] }
+            ]
+        }"#;
+
+        let saved = SavedState::parse(contents).unwrap();
+        assert_eq!(saved.version, VERSION);
+        assert_eq!(saved.lists[0].name, "Home");
+        assert_eq!(saved.lists[1].name, "Work");
+        assert!(saved.lists[1].tasks[0].completed);
+
+        // the same description elsewhere is another task
+        assert_ne!(saved.lists[0].id, saved.lists[1].id);
+        assert_ne!(saved.lists[0].tasks[0].id, saved.lists[1].tasks[0].id);
+    }
+
+    #[test]
+    fn migrations_keep_what_is_already_there() {
+        let mut value = json!({
+            "lists": [{ "id": "home", "name": "Home", "tasks": [{ "id": "1", "description": "Sweep" }] }],
+            "tasks": [{ "description": "Dropped" }]
+        });
+
+        into_lists(&mut value);
+        with_ids(&mut value);
+
+        assert_eq!(value["lists"].as_array().map(Vec::len), Some(1));
+        assert_eq!(value["lists"][0]["id"], json!("home"));
+        assert_eq!(value["lists"][0]["tasks"][0]["id"], json!("1"));
+        assert!(value.get("tasks").is_none());
+    }
+
+    #[test]
+    fn newer_or_broken_files_are_not_read() {
+        let newer = format!(r#"{{ "version": {}, "lists": [] }}"#, VERSION + 1);
+
+        match SavedState::parse(&newer) {
+            Err(LoadError::VersionError) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+
+        for contents in &["[]", "\"\"", "{ \"lists\": ", "{ \"lists\": 3 }"] {
+            match SavedState::parse(contents) {
+                Err(LoadError::FormatError) => {}
+                other => panic!("unexpected {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn saving_rotates_the_backups() {
+        let folder = std::env::temp_dir().join(format!("todos-{}", new_id()));
+        let location = Location {
+            folder: Some(folder.clone()),
+            device: String::from("test"),
+        };
+
+        for i in 0..5 {
+            let saved = SavedState {
+                input_value: i.to_string(),
+                ..saved(Vec::new())
+            };
+
+            async_std::task::block_on(saved.write(&location, Some(Keep::Backup))).unwrap();
+        }
+
+        let current = async_std::task::block_on(SavedState::load_from(location.path(""))).unwrap();
+        assert_eq!(current.input_value, "4");
+
+        let backups = async_std::task::block_on(SavedState::backups(location.clone()));
+        let backups: Vec<_> = backups
+            .iter()
+            .map(|(number, saved)| (*number, saved.input_value.as_str()))
+            .collect();
+        assert_eq!(backups, vec![(1, "3"), (2, "2"), (3, "1")]);
+        assert!(!location.path(&format!(".{}", BACKUPS + 1)).exists());
+        assert!(!location.path(".tmp").exists());
+
+        // a broken file is put aside, and the backups are left alone
+        std::fs::write(location.path(""), "{").unwrap();
+        async_std::task::block_on(saved(Vec::new()).write(&location, Some(Keep::Broken))).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(location.path(".broken")).unwrap(),
+            "{"
+        );
+        assert_eq!(
+            async_std::task::block_on(SavedState::backups(location.clone())).len(),
+            BACKUPS
+        );
+
+        let _ = std::fs::remove_dir_all(folder);
+    }
+
+    fn loaded(tasks: Vec<Task>) -> Todos {
+        let saved = saved(vec![list("home", tasks)]);
+
+        Todos::Loaded(State::from_saved(saved, Location::default(), Keep::Backup))
+    }
+
+    fn state(todos: &mut Todos) -> &mut State {
+        match todos {
+            Todos::Loaded(state) => state,
+            _ => panic!("not loaded"),
+        }
+    }
+
+    fn send(todos: &mut Todos, i: usize, message: TaskMessage) {
+        let _ = todos.update(Message::TaskMessage(i, message));
+    }
+
+    #[test]
+    fn only_changes_are_undone() {
+        let parent = Task {
+            children: vec![task("2", "child", 0)],
+            ..task("1", "parent", 0)
+        };
+        let mut todos = loaded(vec![parent, task("3", "errand", 0)]);
+
+        // asking to confirm is not a change, the confirmation is
+        send(&mut todos, 0, TaskMessage::Completed(true));
+        assert_eq!(state(&mut todos).history.undo.len(), 0);
+        send(&mut todos, 0, TaskMessage::ConfirmCompletion);
+        assert_eq!(state(&mut todos).history.undo.len(), 1);
+
+        send(&mut todos, 1, TaskMessage::Edit);
+        send(&mut todos, 1, TaskMessage::FinishEdition);
+        assert_eq!(state(&mut todos).history.undo.len(), 1);
+
+        // an edition is undone as a whole
+        send(&mut todos, 1, TaskMessage::Edit);
+        send(
+            &mut todos,
+            1,
+            TaskMessage::DescriptionEdited(String::from("errands")),
+        );
+        send(
+            &mut todos,
+            1,
+            TaskMessage::DescriptionEdited(String::from("errands done")),
+        );
+        send(&mut todos, 1, TaskMessage::FinishEdition);
+        assert_eq!(state(&mut todos).history.undo.len(), 2);
+
+        let _ = todos.update(Message::Undo);
+        assert_eq!(state(&mut todos).tasks()[1].description, "errand");
+        assert!(state(&mut todos).tasks()[0].completed);
+
+        let _ = todos.update(Message::Undo);
+        assert!(!state(&mut todos).tasks()[0].completed);
+        assert!(!state(&mut todos).tasks()[0].children[0].completed);
+
+        let _ = todos.update(Message::Redo);
+        assert!(state(&mut todos).tasks()[0].children[0].completed);
+        assert_eq!(state(&mut todos).tasks()[1].description, "errand");
+        assert_eq!(state(&mut todos).history.redo.len(), 1);
+
+        send(&mut todos, 1, TaskMessage::Delete);
+        assert_eq!(state(&mut todos).tasks().len(), 1);
+        assert_eq!(state(&mut todos).history.redo.len(), 0);
+        assert_eq!(state(&mut todos).history.undo.len(), 2);
+    }
+
+    #[test]
+    fn history_is_bounded_and_redo_is_cleared() {
+        let snapshot = |current| Snapshot {
+            lists: Vec::new(),
+            current,
+        };
+        let mut history = History::default();
+
+        for current in 0..HISTORY + 5 {
+            history.record(snapshot(current));
+        }
+        assert_eq!(history.undo.len(), HISTORY);
+
+        let mut undone = Vec::new();
+        while let Some(previous) = history.undo(snapshot(0)) {
+            undone.push(previous.current);
+        }
+
+        // the oldest ones were dropped
+        assert_eq!(undone.len(), HISTORY);
+        assert_eq!(undone.first(), Some(&(HISTORY + 4)));
+        assert_eq!(undone.last(), Some(&5));
+        assert_eq!(history.redo.len(), HISTORY);
+
+        assert_eq!(history.redo(snapshot(5)).map(|next| next.current), Some(0));
+        history.record(snapshot(1));
+        assert!(history.redo(snapshot(2)).is_none());
+    }
+
+    fn descriptions(state: &mut State) -> Vec<String> {
+        state
+            .tasks()
+            .iter()
+            .map(|task| task.description.clone())
+            .collect()
+    }
+
+    #[test]
+    fn tasks_move_past_the_hidden_ones() {
+        let mut todos = loaded(vec![
+            task("1", "a", 0),
+            done("2", "b"),
+            done("3", "c"),
+            task("4", "d", 0),
+        ]);
+        let state = state(&mut todos);
+        state.filter = Filter::Active;
+
+        state.reorder(3, true);
+        assert_eq!(descriptions(state), vec!["d", "b", "c", "a"]);
+
+        state.reorder(3, false);
+        assert_eq!(descriptions(state), vec!["d", "b", "c", "a"]);
+
+        state.filter = Filter::All;
+        state.search_value = String::from("A ");
+        state.reorder(3, true);
+        assert_eq!(descriptions(state), vec!["d", "b", "c", "a"]);
+
+        state.search_value.clear();
+        state.reorder(3, true);
+        assert_eq!(descriptions(state), vec!["d", "b", "a", "c"]);
+    }
+
+    #[test]
+    fn tasks_only_move_in_the_manual_order() {
+        let mut todos = loaded(vec![task("1", "a", 0), task("2", "b", 0)]);
+
+        for sort in &[Sort::Due, Sort::Priority] {
+            state(&mut todos).sort = *sort;
+            send(&mut todos, 0, TaskMessage::MoveDown);
+            send(&mut todos, 1, TaskMessage::MoveUp);
+
+            assert_eq!(descriptions(state(&mut todos)), vec!["a", "b"]);
+            assert!(state(&mut todos).history.undo.is_empty());
+        }
+
+        state(&mut todos).sort = Sort::Manual;
+        send(&mut todos, 0, TaskMessage::MoveDown);
+        assert_eq!(descriptions(state(&mut todos)), vec!["b", "a"]);
+    }
+
+    fn done(id: &str, description: &str) -> Task {
+        Task {
+            completed: true,
+            ..task(id, description, 0)
+        }
+    }
+
+    fn parent(id: &str, description: &str, children: Vec<Task>) -> Task {
+        Task {
+            children,
+            ..task(id, description, 0)
+        }
+    }
+
+    #[test]
+    fn completion_is_derived_from_the_leaves() {
+        let mut trip = parent(
+            "1",
+            "trip",
+            vec![
+                parent(
+                    "2",
+                    "hotel",
+                    vec![done("3", "compare"), task("4", "book", 0)],
+                ),
+                done("5", "train"),
+            ],
+        );
+
+        // only the tasks without subtasks are counted
+        assert_eq!(trip.leaves(), 3);
+        assert_eq!(trip.leaves_left(), 1);
+
+        trip.set_completed(true);
+        assert_eq!(trip.leaves_left(), 0);
+        assert!(trip.children[0].children[1].completed);
+
+        trip.set_completed(false);
+        assert_eq!(trip.leaves_left(), 3);
+        assert!(!trip.children[1].completed);
+
+        trip.children[0].children[0].completed = true;
+        trip.children[0].children[1].completed = true;
+        trip.children[1].completed = true;
+        trip.sync();
+        assert!(trip.children[0].completed);
+        assert!(trip.completed);
+
+        trip.children[0].children[1].completed = false;
+        trip.sync();
+        assert!(!trip.children[0].completed);
+        assert!(!trip.completed);
+    }
+
+    #[test]
+    fn completion_is_derived_up_the_whole_path() {
+        let habit = Task {
+            recurrence: Some(Recurrence::Daily),
+            ..task("3", "stretch", 0)
+        };
+        let health = parent(
+            "1",
+            "health",
+            vec![parent("2", "morning", vec![habit]), done("4", "doctor")],
+        );
+        let saved = saved(vec![list("home", vec![health]), list("work", Vec::new())]);
+        let mut todos = Todos::Loaded(State::from_saved(saved, Location::default(), Keep::Backup));
+
+        let deep =
+            |message| TaskMessage::Child(0, Box::new(TaskMessage::Child(0, Box::new(message))));
+
+        // the next occurrence keeps both parents open
+        send(&mut todos, 0, deep(TaskMessage::Completed(true)));
+        {
+            let health = &state(&mut todos).tasks()[0];
+            let morning = &health.children[0];
+
+            assert_eq!(morning.children.len(), 2);
+            assert!(morning.children[0].completed);
+            assert!(!morning.children[1].completed);
+            assert!(!morning.completed);
+            assert!(!health.completed);
+        }
+
+        send(
+            &mut todos,
+            0,
+            TaskMessage::Child(
+                0,
+                Box::new(TaskMessage::Child(1, Box::new(TaskMessage::Delete))),
+            ),
+        );
+        {
+            let health = &state(&mut todos).tasks()[0];
+
+            assert!(health.children[0].completed);
+            assert!(health.completed);
+        }
+
+        // moved to another list, the parent stays as derived
+        send(&mut todos, 0, TaskMessage::MoveTo(1));
+        let state = state(&mut todos);
+        assert!(state.tasks().is_empty());
+        assert!(state.lists[1].tasks[0].completed);
+        assert_eq!(state.lists[1].tasks[0].leaves_left(), 0);
+    }
+}
@@ -0,0 +1,417 @@
+use futures::executor::{block_on, block_on_stream};
+use serde_json::{json, Value};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use calculator::data::bandwidth::{Limits, Stats};
+use calculator::data::catalog::{unshare, Catalog, CatalogError, Entry, LoadError};
+use calculator::data::control::{
+    self, reply, AddRequest, ConnectRequest, ControlError, FetchRequest, LookupRequest,
+    QuotaRequest, Request, Server, Status,
+};
+use calculator::data::downloads::{assemble, Downloads};
+use calculator::data::link::ShareLink;
+use calculator::data::node::{load_identity, Node, NodeEvent};
+use calculator::data::session::{Session, SessionError};
+use calculator::data::sharing::{self, Recipient, SharingKey};
+use calculator::data::store::{self, is_cid, BlockStore, GcStats};
+
+// Stats are written out at most this often
+const STATS_INTERVAL: Duration = Duration::from_secs(10);
+
+// fuzzynetd [--listen <address>]
+//
+// Runs the Fuzzy Net node without a window, driven through the control API;
+// the address must be a loopback one
+pub fn main() {
+    env_logger::init();
+
+    let mut args = std::env::args().skip(1);
+    let mut address = String::from(control::DEFAULT_ADDRESS);
+
+    while let Some(arg) = args.next() {
+        match (arg.as_str(), args.next()) {
+            ("--listen", Some(value)) => address = value,
+            _ => {
+                eprintln!("usage: fuzzynetd [--listen <address>]");
+                std::process::exit(2);
+            }
+        }
+    }
+
+    let daemon = match Daemon::start() {
+        Ok(daemon) => Arc::new(Mutex::new(daemon)),
+        Err(error) => {
+            eprintln!("{}", error);
+            std::process::exit(1);
+        }
+    };
+
+    let server = match Server::bind(&address) {
+        Ok(server) => server,
+        Err(ControlError::NotLoopback) => {
+            eprintln!("{}, {} is not one", ControlError::NotLoopback, address);
+            std::process::exit(2);
+        }
+        Err(_) => {
+            eprintln!("The control API could not listen on {}", address);
+            std::process::exit(1);
+        }
+    };
+
+    // downloads found complete at start are written out
+    let events = locked(&daemon, |daemon| {
+        Ok(daemon.session.node.as_ref().and_then(Node::take_events))
+    })
+    .unwrap_or(None);
+    if let Some(events) = events {
+        let daemon = daemon.clone();
+
+        std::thread::spawn(move || {
+            for event in block_on_stream(events) {
+                let _ = locked(&daemon, |daemon| {
+                    daemon.node_event(event);
+                    Ok(())
+                });
+            }
+        });
+    }
+
+    {
+        let daemon = daemon.clone();
+
+        std::thread::spawn(move || loop {
+            std::thread::sleep(STATS_INTERVAL);
+
+            let _ = locked(&daemon, |daemon| {
+                daemon.save_stats();
+                Ok(())
+            });
+        });
+    }
+
+    println!("Fuzzy Net daemon, control API on http://{}", address);
+
+    server.run(move |request| handle(&daemon, request));
+}
+
+// Runs `action` on the locked daemon, then writes out the downloads it completed
+// with the lock released, as that takes a while for large files
+fn locked<T>(
+    daemon: &Mutex<Daemon>,
+    action: impl FnOnce(&mut Daemon) -> Result<T, ControlError>,
+) -> Result<T, ControlError> {
+    let stopping = || ControlError::Failed(String::from("The daemon is stopping"));
+
+    let (result, complete, store, sharing) = {
+        let mut daemon = daemon.lock().map_err(|_| stopping())?;
+        let result = action(&mut daemon);
+        daemon.save_downloads();
+
+        (
+            result,
+            daemon.session.take_complete(),
+            daemon.session.store.clone(),
+            daemon.session.sharing.clone(),
+        )
+    };
+
+    for (root, destination) in complete {
+        let assembled = block_on(assemble(
+            store.clone(),
+            root.clone(),
+            destination,
+            sharing.clone(),
+        ));
+
+        let mut daemon = daemon.lock().map_err(|_| stopping())?;
+        if let Some(saved) = daemon.session.assembled(&root, assembled) {
+            println!("{}", saved);
+        }
+        daemon.save_downloads();
+    }
+
+    result
+}
+
+// Every request is answered with the daemon locked, but for the hashing of added files
+fn handle(daemon: &Mutex<Daemon>, request: Request) -> Result<Value, ControlError> {
+    match request {
+        Request::Status => locked(daemon, |daemon| {
+            let node = daemon.session.node().map_err(session_error)?;
+
+            reply(&Status {
+                peer_id: node.peer_id.clone(),
+                listening: daemon.session.listening.clone(),
+            })
+        }),
+        Request::List => locked(daemon, |daemon| reply(&daemon.session.catalog)),
+        // hashing a large file takes a while, other requests are answered meanwhile
+        Request::Add(AddRequest { path, recipients }) => {
+            let (store, recipients) = locked(daemon, |daemon| {
+                Ok((
+                    daemon.session.store.clone(),
+                    daemon.recipients(&recipients)?,
+                ))
+            })?;
+
+            let entry =
+                block_on(Entry::from_path(path, store, recipients)).map_err(catalog_error)?;
+
+            locked(daemon, |daemon| {
+                daemon.session.add(entry.clone()).map_err(catalog_error)?;
+                daemon.save_catalog()?;
+
+                reply(&entry)
+            })
+        }
+        Request::Remove(cid) => locked(daemon, |daemon| {
+            daemon.session.remove(&cid).ok_or(ControlError::NotFound)?;
+            daemon.save_catalog()?;
+
+            block_on(unshare(daemon.session.store.clone(), cid)).map_err(|_| {
+                ControlError::Failed(String::from("The file blocks could not be removed"))
+            })?;
+
+            Ok(json!({}))
+        }),
+        Request::Downloads => locked(daemon, |daemon| reply(&daemon.session.downloads)),
+        Request::Fetch(FetchRequest { link }) => {
+            let link = if is_cid(link.trim()) {
+                ShareLink::new(link.trim().to_owned())
+            } else {
+                ShareLink::parse(&link).map_err(|_| {
+                    ControlError::BadRequest(String::from(
+                        "This is neither a content ID nor a Fuzzy Net link",
+                    ))
+                })?
+            };
+
+            locked(daemon, |daemon| {
+                let download = daemon.session.start_download(link).map_err(session_error)?;
+
+                reply(&download)
+            })
+        }
+        Request::Cancel(root) => locked(daemon, |daemon| {
+            daemon.session.cancel(&root).map_err(session_error)?;
+            daemon.evict();
+
+            Ok(json!({}))
+        }),
+        Request::Pause(root) => locked(daemon, |daemon| {
+            daemon.session.pause(&root).map_err(session_error)?;
+
+            Ok(json!({}))
+        }),
+        Request::Resume(root) => locked(daemon, |daemon| {
+            daemon.session.resume(&root).map_err(session_error)?;
+
+            Ok(json!({}))
+        }),
+        Request::Pin(root, pinned) => locked(daemon, |daemon| {
+            daemon.session.pin(&root, pinned).map_err(session_error)?;
+            daemon.evict();
+
+            Ok(json!({}))
+        }),
+        Request::Peers => locked(daemon, |daemon| reply(&daemon.session.peers)),
+        Request::Connect(ConnectRequest { address }) => locked(daemon, |daemon| {
+            daemon
+                .session
+                .node()
+                .map_err(session_error)?
+                .dial(&address)
+                .map_err(|_| {
+                    ControlError::BadRequest(String::from("This is not a valid address"))
+                })?;
+
+            Ok(json!({}))
+        }),
+        Request::Disconnect(peer) => locked(daemon, |daemon| {
+            daemon
+                .session
+                .node()
+                .map_err(session_error)?
+                .disconnect(&peer)
+                .map_err(|_| ControlError::BadRequest(String::from("This is not a peer ID")))?;
+
+            Ok(json!({}))
+        }),
+        Request::Lookups => locked(daemon, |daemon| reply(&daemon.session.lookups)),
+        Request::Lookup(LookupRequest { cid }) => locked(daemon, |daemon| {
+            daemon.session.lookup(cid).map_err(session_error)?;
+
+            Ok(json!({}))
+        }),
+        Request::Limits => locked(daemon, |daemon| reply(&daemon.limits)),
+        Request::SetLimits(limits) => locked(daemon, |daemon| {
+            daemon.limits = limits;
+            if let Some(node) = &daemon.session.node {
+                let _ = node.set_limits(limits);
+            }
+
+            block_on(limits.save()).map_err(|_| {
+                ControlError::Failed(String::from("The bandwidth limits could not be saved"))
+            })?;
+
+            Ok(json!({}))
+        }),
+        Request::Traffic => locked(daemon, |daemon| reply(&daemon.session.traffic)),
+        Request::SetQuota(QuotaRequest { quota }) => {
+            if quota == 0 {
+                return Err(ControlError::BadRequest(String::from(
+                    "The quota can't be zero",
+                )));
+            }
+
+            locked(daemon, |daemon| {
+                daemon.session.store.set_quota(quota).map_err(|_| {
+                    ControlError::Failed(String::from("The quota could not be saved"))
+                })?;
+
+                reply(&daemon.evict())
+            })
+        }
+        Request::ClearCache => locked(daemon, |daemon| {
+            let stats = daemon.session.store.gc().map_err(|_| {
+                ControlError::Failed(String::from("The cache could not be cleaned up"))
+            })?;
+
+            reply(&stats)
+        }),
+    }
+}
+
+fn catalog_error(error: CatalogError) -> ControlError {
+    match error {
+        CatalogError::NotAFile => {
+            ControlError::BadRequest(String::from("This path is neither a file nor a directory"))
+        }
+        CatalogError::ReadError => ControlError::Failed(String::from("The file could not be read")),
+        CatalogError::AlreadyShared => {
+            ControlError::BadRequest(String::from("This file is already shared"))
+        }
+        CatalogError::StoreError => {
+            ControlError::Failed(String::from("The file could not be stored"))
+        }
+    }
+}
+
+fn session_error(error: SessionError) -> ControlError {
+    match error {
+        SessionError::NotFound => ControlError::NotFound,
+        SessionError::NotACid | SessionError::AlreadyDownloading => {
+            ControlError::BadRequest(error.to_string())
+        }
+        SessionError::Stopped | SessionError::PinError => ControlError::Failed(error.to_string()),
+    }
+}
+
+struct Daemon {
+    session: Session,
+    limits: Limits,
+}
+
+impl Daemon {
+    fn start() -> Result<Daemon, String> {
+        let store = BlockStore::default();
+        let node = Node::start(store.clone())
+            .map_err(|_| String::from("The network node could not start"))?;
+
+        let sharing = load_identity()
+            .ok()
+            .and_then(|identity| SharingKey::load_or_generate(&identity).ok());
+        if sharing.is_none() {
+            eprintln!("The sharing key could not be loaded");
+        }
+
+        // unlike the window, the daemon refuses to start over files it can't read
+        let mut catalog = match block_on(Catalog::load()) {
+            Ok(catalog) => catalog,
+            Err(LoadError::FileError) => Catalog::default(),
+            Err(LoadError::FormatError) => {
+                return Err(String::from("The catalog could not be read"))
+            }
+        };
+        if catalog.migrate(&store) > 0 && block_on(catalog.clone().save()).is_err() {
+            eprintln!("The catalog could not be saved");
+        }
+        let downloads = match block_on(Downloads::load()) {
+            Ok(downloads) => downloads,
+            Err(LoadError::FileError) => Downloads::default(),
+            Err(LoadError::FormatError) => {
+                return Err(String::from("The downloads could not be read"))
+            }
+        };
+
+        let stats = match block_on(Stats::load()) {
+            Ok(stats) => stats,
+            Err(LoadError::FileError) => Stats::default(),
+            Err(LoadError::FormatError) => return Err(String::from("The stats could not be read")),
+        };
+
+        let limits = block_on(Limits::load()).unwrap_or_default();
+        let _ = node.set_limits(limits);
+        if block_on(store::evict(store.clone())).is_err() {
+            eprintln!("The cache could not be cleaned up");
+        }
+
+        let mut session = Session::new(store, Some(node));
+        session.sharing = sharing;
+        session.catalog = catalog;
+        session.downloads = downloads;
+        session.traffic.stats = stats;
+
+        session.announce();
+        session.resume_downloads();
+
+        Ok(Daemon { session, limits })
+    }
+
+    fn node_event(&mut self, event: NodeEvent) {
+        if let (NodeEvent::Listening(addresses), Ok(node)) = (&event, self.session.node()) {
+            for address in addresses {
+                println!("Listening on {}/p2p/{}", address, node.peer_id);
+            }
+        }
+
+        if let Some(notice) = self.session.node_event(event) {
+            eprintln!("{}", notice);
+        }
+    }
+
+    fn recipients(&self, keys: &[String]) -> Result<Vec<Recipient>, ControlError> {
+        sharing::recipients(
+            keys.iter().map(String::as_str),
+            self.session.sharing.as_ref(),
+        )
+        .map_err(|key| ControlError::BadRequest(format!("{} is not a sharing key", key)))
+    }
+
+    fn evict(&self) -> GcStats {
+        self.session.store.evict().unwrap_or_else(|_| {
+            eprintln!("The cache could not be cleaned up");
+            GcStats::default()
+        })
+    }
+
+    fn save_catalog(&self) -> Result<(), ControlError> {
+        block_on(self.session.catalog.clone().save())
+            .map_err(|_| ControlError::Failed(String::from("The catalog could not be saved")))
+    }
+
+    fn save_stats(&mut self) {
+        let traffic = &mut self.session.traffic;
+
+        if traffic.take_changed() && block_on(traffic.stats.clone().save()).is_err() {
+            eprintln!("The stats could not be saved");
+        }
+    }
+
+    fn save_downloads(&mut self) {
+        if self.session.take_changed() && block_on(self.session.downloads.clone().save()).is_err() {
+            eprintln!("The downloads could not be saved");
+        }
+    }
+}
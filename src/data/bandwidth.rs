@@ -0,0 +1,325 @@
+use super::catalog::{LoadError, SaveError};
+use super::exchange::Ledger;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::Instant;
+
+// Rates in bytes per second, 0 means unlimited
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Limits {
+    pub upload: u64,
+    pub download: u64,
+    pub peer_upload: u64,
+    pub peer_download: u64,
+}
+
+// Lets `rate` bytes through per second with bursts of up to one second; a block larger
+// than what is left still goes through, the bucket then stays empty until it is paid back
+#[derive(Debug, Clone)]
+pub struct TokenBucket {
+    rate: u64,
+    tokens: f64,
+    last: Instant,
+}
+
+impl TokenBucket {
+    pub fn new(rate: u64) -> TokenBucket {
+        TokenBucket {
+            rate,
+            tokens: rate as f64,
+            last: Instant::now(),
+        }
+    }
+
+    pub fn set_rate(&mut self, rate: u64) {
+        self.rate = rate;
+        self.tokens = self.tokens.min(rate as f64);
+    }
+
+    fn refill(&mut self) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last);
+        self.last = now;
+
+        let refilled = self.tokens + elapsed.as_secs_f64() * self.rate as f64;
+        self.tokens = refilled.min(self.rate as f64);
+    }
+
+    pub fn is_ready(&mut self) -> bool {
+        if self.rate == 0 {
+            return true;
+        }
+
+        self.refill();
+        self.tokens >= 0.0
+    }
+
+    pub fn take(&mut self, bytes: u64) {
+        if self.rate > 0 {
+            self.refill();
+            self.tokens -= bytes as f64;
+        }
+    }
+
+    pub fn give(&mut self, bytes: u64) {
+        if self.rate > 0 {
+            self.tokens = (self.tokens + bytes as f64).min(self.rate as f64);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PeerStats {
+    pub bytes_in: u64,
+    pub bytes_out: u64,
+    pub blocks_served: u64,
+    pub blocks_received: u64,
+}
+
+impl PeerStats {
+    pub fn total(&self) -> u64 {
+        self.bytes_in + self.bytes_out
+    }
+}
+
+// What was exchanged with every peer, over all sessions
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Stats {
+    pub peers: HashMap<String, PeerStats>,
+}
+
+impl Stats {
+    // `since` is the part of the session ledger already recorded
+    pub fn record(&mut self, peer: &str, ledger: &Ledger, since: &Ledger) {
+        let stats = self.peers.entry(peer.to_owned()).or_default();
+
+        stats.bytes_in += ledger.bytes_received.saturating_sub(since.bytes_received);
+        stats.bytes_out += ledger.bytes_sent.saturating_sub(since.bytes_sent);
+        stats.blocks_received += ledger.blocks_received.saturating_sub(since.blocks_received);
+        stats.blocks_served += ledger.blocks_sent.saturating_sub(since.blocks_sent);
+    }
+
+    // Adds up what two sessions recorded
+    pub fn merge(&mut self, other: Stats) {
+        for (peer, other) in other.peers {
+            let stats = self.peers.entry(peer).or_default();
+
+            stats.bytes_in += other.bytes_in;
+            stats.bytes_out += other.bytes_out;
+            stats.blocks_received += other.blocks_received;
+            stats.blocks_served += other.blocks_served;
+        }
+    }
+
+    // Biggest exchanges first
+    pub fn ranked(&self) -> Vec<(&String, &PeerStats)> {
+        let mut peers: Vec<_> = self.peers.iter().collect();
+        peers.sort_by(|a, b| b.1.total().cmp(&a.1.total()));
+
+        peers
+    }
+}
+
+// What this session exchanged in all, and what every peer exchanged over all sessions;
+// the node reports the whole session ledger of a peer every time, only what is new
+// is added up
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Traffic {
+    pub sent: u64,
+    pub received: u64,
+    pub stats: Stats,
+    #[serde(skip)]
+    recorded: HashMap<String, Ledger>,
+    #[serde(skip)]
+    changed: bool,
+}
+
+impl Traffic {
+    pub fn record(&mut self, peer: String, ledger: Ledger) {
+        let since = self.recorded.get(&peer).cloned().unwrap_or_default();
+
+        self.stats.record(&peer, &ledger, &since);
+        self.sent += ledger.bytes_sent.saturating_sub(since.bytes_sent);
+        self.received += ledger.bytes_received.saturating_sub(since.bytes_received);
+
+        self.recorded.insert(peer, ledger);
+        self.changed = true;
+    }
+
+    // Whether the stats changed since this was last asked, they are to be saved then
+    pub fn take_changed(&mut self) -> bool {
+        std::mem::replace(&mut self.changed, false)
+    }
+}
+
+fn path(file: &str) -> PathBuf {
+    let mut path =
+        if let Some(project_dirs) = directories::ProjectDirs::from("rs", "Iced", "FuzzyNet") {
+            project_dirs.data_dir().into()
+        } else {
+            std::env::current_dir().unwrap_or(PathBuf::new())
+        };
+
+    path.push(file);
+
+    path
+}
+
+async fn load<T: serde::de::DeserializeOwned>(path: PathBuf) -> Result<T, LoadError> {
+    let contents = std::fs::read_to_string(path).map_err(|_| LoadError::FileError)?;
+
+    serde_json::from_str(&contents).map_err(|_| LoadError::FormatError)
+}
+
+async fn save<T: Serialize>(path: PathBuf, value: T) -> Result<(), SaveError> {
+    let json = serde_json::to_string_pretty(&value).map_err(|_| SaveError::FormatError)?;
+
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).map_err(|_| SaveError::DirectoryError)?;
+    }
+
+    std::fs::write(path, json).map_err(|_| SaveError::WriteError)
+}
+
+impl Limits {
+    pub async fn load() -> Result<Limits, LoadError> {
+        load(path("bandwidth.json")).await
+    }
+
+    pub async fn save(self) -> Result<(), SaveError> {
+        save(path("bandwidth.json"), self).await
+    }
+}
+
+impl Stats {
+    pub async fn load() -> Result<Stats, LoadError> {
+        load(path("stats.json")).await
+    }
+
+    pub async fn save(self) -> Result<(), SaveError> {
+        save(path("stats.json"), self).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+    use std::time::Duration;
+
+    fn ledger(bytes_sent: u64, bytes_received: u64) -> Ledger {
+        Ledger {
+            bytes_sent,
+            bytes_received,
+            blocks_sent: 1,
+            blocks_received: 1,
+        }
+    }
+
+    #[test]
+    fn buckets_let_bursts_through_then_wait_to_be_paid_back() {
+        let mut bucket = TokenBucket::new(1000);
+        assert!(bucket.is_ready());
+
+        // a block larger than the bucket still goes through
+        bucket.take(1500);
+        assert!(!bucket.is_ready());
+
+        // 0.4 s pays back 400 bytes, not yet enough
+        bucket.last -= Duration::from_millis(400);
+        assert!(!bucket.is_ready());
+        bucket.last -= Duration::from_millis(200);
+        assert!(bucket.is_ready());
+
+        // a long pause refills no more than one second
+        bucket.last -= Duration::from_secs(10);
+        bucket.refill();
+        assert!((bucket.tokens - 1000.0).abs() < 1.0);
+
+        // what was taken for a block that wasn't sent is given back
+        bucket.take(800);
+        bucket.give(800);
+        assert!((bucket.tokens - 1000.0).abs() < 1.0);
+        bucket.give(800);
+        assert!((bucket.tokens - 1000.0).abs() < 1.0);
+    }
+
+    #[test]
+    fn unlimited_buckets_are_always_ready() {
+        let mut bucket = TokenBucket::new(0);
+        bucket.take(u64::max_value() / 2);
+        assert!(bucket.is_ready());
+
+        // lowering the rate drops the tokens above it
+        let mut bucket = TokenBucket::new(1000);
+        bucket.set_rate(100);
+        bucket.take(200);
+        assert!(!bucket.is_ready());
+
+        bucket.set_rate(0);
+        assert!(bucket.is_ready());
+    }
+
+    #[test]
+    fn ledgers_are_added_once() {
+        let mut traffic = Traffic::default();
+        assert!(!traffic.take_changed());
+
+        traffic.record(String::from("alice"), ledger(100, 10));
+        traffic.record(String::from("alice"), ledger(150, 10));
+        traffic.record(String::from("bob"), ledger(0, 40));
+        assert!(traffic.take_changed());
+        assert!(!traffic.take_changed());
+
+        assert_eq!((traffic.sent, traffic.received), (150, 50));
+        assert_eq!(
+            traffic.stats.peers["alice"],
+            PeerStats {
+                bytes_in: 10,
+                bytes_out: 150,
+                blocks_served: 1,
+                blocks_received: 1,
+            }
+        );
+
+        let ranked: Vec<&String> = traffic
+            .stats
+            .ranked()
+            .into_iter()
+            .map(|(peer, _)| peer)
+            .collect();
+        assert_eq!(ranked, vec!["alice", "bob"]);
+    }
+
+    #[test]
+    fn stats_are_saved_and_merged_with_the_previous_sessions() {
+        let dir = std::env::temp_dir().join(format!("fuzzynet-stats-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let file = dir.join("stats.json");
+
+        match block_on(load::<Stats>(file.clone())) {
+            Err(LoadError::FileError) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+
+        let mut traffic = Traffic::default();
+        traffic.record(String::from("alice"), ledger(100, 10));
+        block_on(save(file.clone(), traffic.stats.clone())).unwrap();
+
+        let mut stats: Stats = block_on(load(file.clone())).unwrap();
+        assert_eq!(stats.peers, traffic.stats.peers);
+
+        stats.merge(traffic.stats);
+        assert_eq!(stats.peers["alice"].bytes_out, 200);
+        assert_eq!(stats.peers["alice"].blocks_served, 2);
+
+        std::fs::write(&file, "{").unwrap();
+        match block_on(load::<Stats>(file)) {
+            Err(LoadError::FormatError) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
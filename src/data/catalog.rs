@@ -0,0 +1,265 @@
+use super::sharing::Recipient;
+use super::store::{BlockStore, StoreError};
+use iced::button;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entry {
+    pub name: String,
+    pub path: PathBuf,
+    pub size: u64,
+    pub hash: String,
+    pub added: u64,
+    #[serde(default)]
+    pub cid: String,
+    // peers a private file is shared with, empty when it is public
+    #[serde(default)]
+    pub access: Vec<String>,
+    #[serde(default)]
+    pub directory: bool,
+
+    #[serde(skip)]
+    pub select_button: button::State,
+    #[serde(skip)]
+    pub open_button: button::State,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Catalog {
+    pub entries: Vec<Entry>,
+}
+
+#[derive(Debug, Clone)]
+pub enum CatalogError {
+    NotAFile,
+    ReadError,
+    AlreadyShared,
+    StoreError,
+}
+
+#[derive(Debug, Clone)]
+pub enum LoadError {
+    FileError,
+    FormatError,
+}
+
+#[derive(Debug, Clone)]
+pub enum SaveError {
+    DirectoryError,
+    WriteError,
+    FormatError,
+}
+
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit + 1 < UNITS.len() {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+pub fn format_age(added: u64) -> String {
+    let secs = now().saturating_sub(added);
+
+    match secs {
+        0..=59 => String::from("just now"),
+        60..=3599 => format!("{} min ago", secs / 60),
+        3600..=86399 => format!("{} h ago", secs / 3600),
+        _ => format!("{} days ago", secs / 86400),
+    }
+}
+
+impl Entry {
+    pub async fn from_path(
+        path: PathBuf,
+        store: BlockStore,
+        recipients: Vec<Recipient>,
+    ) -> Result<Entry, CatalogError> {
+        let directory = path.is_dir();
+        if !path.is_file() && !directory {
+            return Err(CatalogError::NotAFile);
+        }
+
+        // the file is read once, its hash comes with its blocks
+        let added = store
+            .add_path(&path, &recipients)
+            .map_err(|error| match error {
+                StoreError::IoError => CatalogError::ReadError,
+                _ => CatalogError::StoreError,
+            })?;
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+
+        Ok(Entry {
+            name,
+            path,
+            size: added.size,
+            hash: added.hash,
+            added: now(),
+            cid: added.root,
+            directory,
+            access: recipients
+                .into_iter()
+                .map(|recipient| recipient.peer)
+                .collect(),
+            select_button: button::State::new(),
+            open_button: button::State::new(),
+        })
+    }
+}
+
+// The blocks of a file that left the catalog are only cached from now on,
+// they go as soon as the store is over its quota
+pub async fn unshare(store: BlockStore, cid: String) -> Result<u64, CatalogError> {
+    store
+        .remove_root(&cid)
+        .and_then(|_| store.evict())
+        .map(|stats| stats.freed)
+        .map_err(|_| CatalogError::StoreError)
+}
+
+impl Catalog {
+    // A duplicate was already stored as a root, it goes back to being cached unless
+    // its blocks are those of the entry already shared
+    pub fn add(&mut self, entry: Entry, store: &BlockStore) -> Result<(), CatalogError> {
+        if self.entries.iter().any(|known| known.hash == entry.hash) {
+            if !self.entries.iter().any(|known| known.cid == entry.cid) {
+                store
+                    .remove_root(&entry.cid)
+                    .map_err(|_| CatalogError::StoreError)?;
+            }
+
+            return Err(CatalogError::AlreadyShared);
+        }
+
+        self.entries.push(entry);
+        Ok(())
+    }
+
+    pub fn remove(&mut self, index: usize) -> Option<Entry> {
+        if index < self.entries.len() {
+            Some(self.entries.remove(index))
+        } else {
+            None
+        }
+    }
+
+    // Entries from before the block store have no cid and can't be provided, their
+    // files are stored again; the ones that can't be read are left for the next start.
+    // Returns how many entries were migrated
+    pub fn migrate(&mut self, store: &BlockStore) -> usize {
+        let mut migrated = 0;
+
+        for entry in self
+            .entries
+            .iter_mut()
+            .filter(|entry| entry.cid.is_empty() && entry.access.is_empty())
+        {
+            if let Ok(added) = store.add_path(&entry.path, &[]) {
+                entry.cid = added.root;
+                entry.size = added.size;
+                entry.hash = added.hash;
+                entry.directory = entry.path.is_dir();
+                migrated += 1;
+            }
+        }
+
+        migrated
+    }
+
+    pub fn total_size(&self) -> u64 {
+        self.entries.iter().map(|entry| entry.size).sum()
+    }
+
+    fn path() -> PathBuf {
+        let mut path =
+            if let Some(project_dirs) = directories::ProjectDirs::from("rs", "Iced", "FuzzyNet") {
+                project_dirs.data_dir().into()
+            } else {
+                std::env::current_dir().unwrap_or(PathBuf::new())
+            };
+
+        path.push("catalog.json");
+
+        path
+    }
+
+    pub async fn load() -> Result<Catalog, LoadError> {
+        let contents = std::fs::read_to_string(Self::path()).map_err(|_| LoadError::FileError)?;
+
+        serde_json::from_str(&contents).map_err(|_| LoadError::FormatError)
+    }
+
+    pub async fn save(self) -> Result<(), SaveError> {
+        let json = serde_json::to_string_pretty(&self).map_err(|_| SaveError::FormatError)?;
+
+        let path = Self::path();
+
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).map_err(|_| SaveError::DirectoryError)?;
+        }
+
+        std::fs::write(path, json).map_err(|_| SaveError::WriteError)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entries_without_a_cid_are_stored_again() {
+        let dir = std::env::temp_dir().join(format!("fuzzynet-catalog-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("old.txt"), b"shared before the store").unwrap();
+
+        // as saved before entries had a cid
+        let json = format!(
+            r#"{{"entries": [
+                {{"name": "old.txt", "path": {:?}, "size": 0, "hash": "", "added": 1}},
+                {{"name": "gone.txt", "path": {:?}, "size": 0, "hash": "", "added": 2}}
+            ]}}"#,
+            dir.join("old.txt"),
+            dir.join("gone.txt")
+        );
+        let mut catalog: Catalog = serde_json::from_str(&json).unwrap();
+        let store = BlockStore::new(dir.join("store"));
+
+        assert_eq!(catalog.migrate(&store), 1);
+        let old = &catalog.entries[0];
+        assert_eq!(store.roots(), vec![old.cid.clone()]);
+        assert_eq!(old.size, b"shared before the store".len() as u64);
+        assert!(!old.hash.is_empty());
+
+        // the missing file is tried again next time
+        assert!(catalog.entries[1].cid.is_empty());
+        assert_eq!(catalog.migrate(&store), 0);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,617 @@
+use super::bandwidth::{Limits, Traffic};
+use super::catalog::{Catalog, Entry};
+use super::crypto;
+use super::downloads::{Download, Downloads};
+use super::exchange::Ledger;
+use super::store::GcStats;
+use ring::constant_time::verify_slices_are_equal;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{IpAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use std::{error, fmt};
+
+// Loopback only, requests also need the token the daemon writes at start
+pub const DEFAULT_ADDRESS: &str = "127.0.0.1:4050";
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+const MAX_BODY_SIZE: usize = 1024 * 1024;
+
+// Control API of the daemon, HTTP/1.1 with JSON bodies, one request per connection:
+//
+// GET    /status          Status
+// GET    /files           Catalog
+// POST   /files           AddRequest -> Entry
+// DELETE /files/<cid>     {}
+// GET    /downloads       Downloads
+// POST   /downloads       FetchRequest -> Download
+// DELETE /downloads/<cid> {}
+// POST   /downloads/<cid>/pause    {}
+// POST   /downloads/<cid>/resume   {}
+// POST   /downloads/<cid>/pin      {}
+// POST   /downloads/<cid>/unpin    {}
+// GET    /peers           [PeerInfo]
+// POST   /peers           ConnectRequest -> {}
+// DELETE /peers/<id>      {}
+// GET    /lookups         [Lookup]
+// POST   /lookups         LookupRequest -> {}
+// GET    /limits          Limits
+// PUT    /limits          Limits -> {}
+// GET    /traffic         Traffic
+// PUT    /quota           QuotaRequest -> GcStats
+// DELETE /cache           GcStats
+//
+// Every request carries `Authorization: Bearer <token>`, the token being in the
+// `control.token` file next to the catalog; requests for another host than localhost,
+// or coming from a web page (with an Origin header), are refused.
+//
+// Errors come back with a 4xx or 5xx status and {"error": "..."}
+#[derive(Debug, Clone)]
+pub enum Request {
+    Status,
+    List,
+    Add(AddRequest),
+    Remove(String),
+    Downloads,
+    Fetch(FetchRequest),
+    Cancel(String),
+    Pause(String),
+    Resume(String),
+    Pin(String, bool),
+    Peers,
+    Connect(ConnectRequest),
+    Disconnect(String),
+    Lookups,
+    Lookup(LookupRequest),
+    Limits,
+    SetLimits(Limits),
+    Traffic,
+    SetQuota(QuotaRequest),
+    ClearCache,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Status {
+    pub peer_id: String,
+    pub listening: Vec<String>,
+}
+
+// `recipients` are exported sharing keys, none shares the file publicly
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AddRequest {
+    pub path: PathBuf,
+    #[serde(default)]
+    pub recipients: Vec<String>,
+}
+
+// A share link or a bare content ID
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FetchRequest {
+    pub link: String,
+}
+
+// A multiaddress ending with /p2p/<peer id>
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConnectRequest {
+    pub address: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LookupRequest {
+    pub cid: String,
+}
+
+// In bytes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuotaRequest {
+    pub quota: u64,
+}
+
+// A provider lookup on the DHT, `providers` is filled once it completes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Lookup {
+    pub cid: String,
+    pub providers: Option<Vec<String>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PeerInfo {
+    pub id: String,
+    pub address: String,
+    pub connected: bool,
+    pub nearby: bool,
+    // round trip time in milliseconds
+    pub rtt: Option<u64>,
+    pub ledger: Ledger,
+}
+
+// Everything the window shows, gathered in one go
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    pub status: Status,
+    pub files: Catalog,
+    pub downloads: Downloads,
+    pub peers: Vec<PeerInfo>,
+    pub lookups: Vec<Lookup>,
+    pub traffic: Traffic,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ControlError {
+    ConnectError,
+    ListenError,
+    NotLoopback,
+    FormatError,
+    Unauthorized,
+    Forbidden,
+    NotFound,
+    BadRequest(String),
+    Failed(String),
+}
+
+impl ControlError {
+    fn status(&self) -> u16 {
+        match self {
+            ControlError::NotFound => 404,
+            ControlError::Unauthorized => 401,
+            ControlError::Forbidden => 403,
+            ControlError::BadRequest(_) | ControlError::FormatError => 400,
+            _ => 500,
+        }
+    }
+}
+
+impl fmt::Display for ControlError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ControlError::ConnectError => write!(f, "The daemon could not be reached"),
+            ControlError::ListenError => write!(f, "The control API could not listen"),
+            ControlError::NotLoopback => {
+                write!(f, "The control API only listens on loopback addresses")
+            }
+            ControlError::FormatError => write!(f, "The request is malformed"),
+            ControlError::Unauthorized => write!(f, "The control token is missing or wrong"),
+            ControlError::Forbidden => write!(f, "Requests from web pages are refused"),
+            ControlError::NotFound => write!(f, "Not found"),
+            ControlError::BadRequest(message) | ControlError::Failed(message) => {
+                write!(f, "{}", message)
+            }
+        }
+    }
+}
+
+impl error::Error for ControlError {}
+
+pub fn reply<T: Serialize>(value: &T) -> Result<Value, ControlError> {
+    serde_json::to_value(value).map_err(|_| ControlError::Failed(String::from("Encoding failed")))
+}
+
+fn parse_body<T: DeserializeOwned>(body: &[u8]) -> Result<T, ControlError> {
+    serde_json::from_slice(body).map_err(|_| ControlError::FormatError)
+}
+
+impl Request {
+    fn parse(method: &str, path: &str, body: &[u8]) -> Result<Request, ControlError> {
+        let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+
+        match (method, segments.as_slice()) {
+            ("GET", ["status"]) => Ok(Request::Status),
+            ("GET", ["files"]) => Ok(Request::List),
+            ("POST", ["files"]) => Ok(Request::Add(parse_body(body)?)),
+            ("DELETE", ["files", cid]) => Ok(Request::Remove((*cid).to_owned())),
+            ("GET", ["downloads"]) => Ok(Request::Downloads),
+            ("POST", ["downloads"]) => Ok(Request::Fetch(parse_body(body)?)),
+            ("DELETE", ["downloads", cid]) => Ok(Request::Cancel((*cid).to_owned())),
+            ("POST", ["downloads", cid, "pause"]) => Ok(Request::Pause((*cid).to_owned())),
+            ("POST", ["downloads", cid, "resume"]) => Ok(Request::Resume((*cid).to_owned())),
+            ("POST", ["downloads", cid, "pin"]) => Ok(Request::Pin((*cid).to_owned(), true)),
+            ("POST", ["downloads", cid, "unpin"]) => Ok(Request::Pin((*cid).to_owned(), false)),
+            ("GET", ["peers"]) => Ok(Request::Peers),
+            ("POST", ["peers"]) => Ok(Request::Connect(parse_body(body)?)),
+            ("DELETE", ["peers", id]) => Ok(Request::Disconnect((*id).to_owned())),
+            ("GET", ["lookups"]) => Ok(Request::Lookups),
+            ("POST", ["lookups"]) => Ok(Request::Lookup(parse_body(body)?)),
+            ("GET", ["limits"]) => Ok(Request::Limits),
+            ("PUT", ["limits"]) => Ok(Request::SetLimits(parse_body(body)?)),
+            ("GET", ["traffic"]) => Ok(Request::Traffic),
+            ("PUT", ["quota"]) => Ok(Request::SetQuota(parse_body(body)?)),
+            ("DELETE", ["cache"]) => Ok(Request::ClearCache),
+            _ => Err(ControlError::NotFound),
+        }
+    }
+}
+
+fn token_path() -> PathBuf {
+    let mut path =
+        if let Some(project_dirs) = directories::ProjectDirs::from("rs", "Iced", "FuzzyNet") {
+            project_dirs.data_dir().into()
+        } else {
+            std::env::current_dir().unwrap_or(PathBuf::new())
+        };
+
+    path.push("control.token");
+
+    path
+}
+
+fn read_token() -> Result<String, ControlError> {
+    std::fs::read_to_string(token_path())
+        .map(|token| token.trim().to_owned())
+        .map_err(|_| ControlError::Unauthorized)
+}
+
+// localhost, 127.0.0.1 or [::1], with or without a port; anything else may be a
+// name rebound to this machine by some web page
+fn is_local_host(host: &str) -> bool {
+    let name = if host.starts_with('[') {
+        host.find(']').map_or(host, |end| &host[1..end])
+    } else {
+        host.rsplitn(2, ':').last().unwrap_or(host)
+    };
+
+    name.eq_ignore_ascii_case("localhost")
+        || name.parse::<IpAddr>().map_or(false, |ip| ip.is_loopback())
+}
+
+pub struct Server {
+    listener: TcpListener,
+    token: String,
+}
+
+impl Server {
+    // A new token is written every time, only the user running the daemon can read it
+    pub fn bind(address: &str) -> Result<Server, ControlError> {
+        let addresses: Vec<_> = address
+            .to_socket_addrs()
+            .map_err(|_| ControlError::ListenError)?
+            .collect();
+        if addresses.is_empty() || addresses.iter().any(|address| !address.ip().is_loopback()) {
+            return Err(ControlError::NotLoopback);
+        }
+
+        let listener = TcpListener::bind(&addresses[..]).map_err(|_| ControlError::ListenError)?;
+
+        let token = crypto::random_key()
+            .map(|key| crypto::encode(&key))
+            .map_err(|_| ControlError::ListenError)?;
+        let path = token_path();
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).map_err(|_| ControlError::ListenError)?;
+        }
+        crypto::write_private(&path, token.as_bytes()).map_err(|_| ControlError::ListenError)?;
+
+        Ok(Server { listener, token })
+    }
+
+    // Every connection is answered on its own thread, for as long as the process runs
+    pub fn run<F>(self, handler: F)
+    where
+        F: Fn(Request) -> Result<Value, ControlError> + Send + Sync + 'static,
+    {
+        let handler = Arc::new(handler);
+        let token = Arc::new(self.token);
+
+        for stream in self.listener.incoming() {
+            if let Ok(stream) = stream {
+                let handler = handler.clone();
+                let token = token.clone();
+
+                std::thread::spawn(move || {
+                    let _ = respond(stream, &token, &*handler);
+                });
+            }
+        }
+    }
+}
+
+fn respond<F>(mut stream: TcpStream, token: &str, handler: &F) -> io::Result<()>
+where
+    F: Fn(Request) -> Result<Value, ControlError>,
+{
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let mut parts = line.split_whitespace();
+    let method = parts.next().unwrap_or_default().to_owned();
+    let path = parts.next().unwrap_or_default().to_owned();
+
+    let mut length = 0;
+    let mut host = None;
+    let mut origin = false;
+    let mut authorization = None;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+            break;
+        }
+
+        let mut header = header.splitn(2, ':');
+        let name = header
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_ascii_lowercase();
+        let value = header.next().unwrap_or_default().trim().to_owned();
+
+        match name.as_str() {
+            "content-length" => length = value.parse().unwrap_or(0),
+            "host" => host = Some(value),
+            "origin" => origin = true,
+            "authorization" => authorization = Some(value),
+            _ => {}
+        }
+    }
+
+    let expected = format!("Bearer {}", token);
+    let authorized = authorization.map_or(false, |authorization| {
+        verify_slices_are_equal(authorization.as_bytes(), expected.as_bytes()).is_ok()
+    });
+
+    let result = if origin || !host.as_ref().map_or(false, |host| is_local_host(host)) {
+        Err(ControlError::Forbidden)
+    } else if !authorized {
+        Err(ControlError::Unauthorized)
+    } else if length > MAX_BODY_SIZE {
+        Err(ControlError::FormatError)
+    } else {
+        let mut body = vec![0u8; length];
+        reader.read_exact(&mut body)?;
+
+        Request::parse(&method, &path, &body).and_then(|request| handler(request))
+    };
+
+    let (status, body) = match result {
+        Ok(value) => (200, value),
+        Err(error) => (error.status(), json!({ "error": error.to_string() })),
+    };
+    let body = serde_json::to_vec(&body).unwrap_or_default();
+
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status,
+        match status {
+            200 => "OK",
+            400 => "Bad Request",
+            401 => "Unauthorized",
+            403 => "Forbidden",
+            404 => "Not Found",
+            _ => "Internal Server Error",
+        },
+        body.len()
+    )?;
+    stream.write_all(&body)
+}
+
+// Used by the window to drive a running daemon
+#[derive(Debug, Clone)]
+pub struct Client {
+    address: String,
+}
+
+impl Client {
+    pub fn new(address: &str) -> Client {
+        Client {
+            address: address.to_owned(),
+        }
+    }
+
+    fn call<T: DeserializeOwned>(
+        &self,
+        method: &str,
+        path: &str,
+        body: Option<Value>,
+    ) -> Result<T, ControlError> {
+        let address = self
+            .address
+            .to_socket_addrs()
+            .ok()
+            .and_then(|mut addresses| addresses.next())
+            .ok_or(ControlError::ConnectError)?;
+
+        let mut stream = TcpStream::connect_timeout(&address, TIMEOUT)
+            .map_err(|_| ControlError::ConnectError)?;
+        stream
+            .set_read_timeout(Some(TIMEOUT))
+            .map_err(|_| ControlError::ConnectError)?;
+
+        let body = match body {
+            Some(body) => serde_json::to_vec(&body).map_err(|_| ControlError::FormatError)?,
+            None => Vec::new(),
+        };
+        let token = read_token()?;
+
+        write!(
+            stream,
+            "{} {} HTTP/1.1\r\nHost: {}\r\nAuthorization: Bearer {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            method,
+            path,
+            self.address,
+            token,
+            body.len()
+        )
+        .and_then(|_| stream.write_all(&body))
+        .map_err(|_| ControlError::ConnectError)?;
+
+        // the daemon closes the connection once it answered
+        let mut response = Vec::new();
+        stream
+            .read_to_end(&mut response)
+            .map_err(|_| ControlError::ConnectError)?;
+
+        let split = response
+            .windows(4)
+            .position(|window| window == b"\r\n\r\n")
+            .ok_or(ControlError::FormatError)?;
+        let head = String::from_utf8_lossy(&response[..split]);
+        let body = &response[split + 4..];
+
+        let status: u16 = head
+            .split_whitespace()
+            .nth(1)
+            .and_then(|status| status.parse().ok())
+            .ok_or(ControlError::FormatError)?;
+
+        if status == 200 {
+            return parse_body(body);
+        }
+
+        let message = serde_json::from_slice::<Value>(body)
+            .ok()
+            .and_then(|value| value["error"].as_str().map(String::from))
+            .unwrap_or_default();
+
+        Err(match status {
+            401 => ControlError::Unauthorized,
+            403 => ControlError::Forbidden,
+            404 => ControlError::NotFound,
+            400..=499 => ControlError::BadRequest(message),
+            _ => ControlError::Failed(message),
+        })
+    }
+
+    pub async fn status(self) -> Result<Status, ControlError> {
+        self.call("GET", "/status", None)
+    }
+
+    pub async fn list(self) -> Result<Catalog, ControlError> {
+        self.call("GET", "/files", None)
+    }
+
+    pub async fn add(self, path: PathBuf, recipients: Vec<String>) -> Result<Entry, ControlError> {
+        let request = reply(&AddRequest { path, recipients })?;
+
+        self.call("POST", "/files", Some(request))
+    }
+
+    pub async fn remove(self, cid: String) -> Result<(), ControlError> {
+        self.call::<Value>("DELETE", &format!("/files/{}", cid), None)
+            .map(|_| ())
+    }
+
+    pub async fn downloads(self) -> Result<Downloads, ControlError> {
+        self.call("GET", "/downloads", None)
+    }
+
+    pub async fn fetch(self, link: String) -> Result<Download, ControlError> {
+        let request = reply(&FetchRequest { link })?;
+
+        self.call("POST", "/downloads", Some(request))
+    }
+
+    pub async fn cancel(self, cid: String) -> Result<(), ControlError> {
+        self.call::<Value>("DELETE", &format!("/downloads/{}", cid), None)
+            .map(|_| ())
+    }
+
+    pub async fn pause(self, cid: String) -> Result<(), ControlError> {
+        self.call::<Value>("POST", &format!("/downloads/{}/pause", cid), None)
+            .map(|_| ())
+    }
+
+    pub async fn resume(self, cid: String) -> Result<(), ControlError> {
+        self.call::<Value>("POST", &format!("/downloads/{}/resume", cid), None)
+            .map(|_| ())
+    }
+
+    pub async fn pin(self, cid: String, pinned: bool) -> Result<(), ControlError> {
+        let action = if pinned { "pin" } else { "unpin" };
+
+        self.call::<Value>("POST", &format!("/downloads/{}/{}", cid, action), None)
+            .map(|_| ())
+    }
+
+    pub async fn peers(self) -> Result<Vec<PeerInfo>, ControlError> {
+        self.call("GET", "/peers", None)
+    }
+
+    pub async fn connect(self, address: String) -> Result<(), ControlError> {
+        let request = reply(&ConnectRequest { address })?;
+
+        self.call::<Value>("POST", "/peers", Some(request))
+            .map(|_| ())
+    }
+
+    pub async fn disconnect(self, peer: String) -> Result<(), ControlError> {
+        self.call::<Value>("DELETE", &format!("/peers/{}", peer), None)
+            .map(|_| ())
+    }
+
+    pub async fn lookups(self) -> Result<Vec<Lookup>, ControlError> {
+        self.call("GET", "/lookups", None)
+    }
+
+    pub async fn lookup(self, cid: String) -> Result<(), ControlError> {
+        let request = reply(&LookupRequest { cid })?;
+
+        self.call::<Value>("POST", "/lookups", Some(request))
+            .map(|_| ())
+    }
+
+    pub async fn set_limits(self, limits: Limits) -> Result<(), ControlError> {
+        let request = reply(&limits)?;
+
+        self.call::<Value>("PUT", "/limits", Some(request))
+            .map(|_| ())
+    }
+
+    pub async fn traffic(self) -> Result<Traffic, ControlError> {
+        self.call("GET", "/traffic", None)
+    }
+
+    pub async fn set_quota(self, quota: u64) -> Result<GcStats, ControlError> {
+        let request = reply(&QuotaRequest { quota })?;
+
+        self.call("PUT", "/quota", Some(request))
+    }
+
+    pub async fn clear_cache(self) -> Result<GcStats, ControlError> {
+        self.call("DELETE", "/cache", None)
+    }
+
+    pub async fn snapshot(self) -> Result<Snapshot, ControlError> {
+        Ok(Snapshot {
+            status: self.clone().status().await?,
+            files: self.clone().list().await?,
+            downloads: self.clone().downloads().await?,
+            peers: self.clone().peers().await?,
+            lookups: self.clone().lookups().await?,
+            traffic: self.traffic().await?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_loopback_hosts_are_local() {
+        assert!(is_local_host("localhost"));
+        assert!(is_local_host("LOCALHOST:4050"));
+        assert!(is_local_host("127.0.0.1:4050"));
+        assert!(is_local_host("[::1]:4050"));
+        assert!(is_local_host("[::1]"));
+
+        assert!(!is_local_host(""));
+        assert!(!is_local_host("example.com"));
+        assert!(!is_local_host("localhost.example.com:4050"));
+        assert!(!is_local_host("192.168.1.2:4050"));
+        assert!(!is_local_host("[::1"));
+    }
+
+    #[test]
+    fn only_loopback_addresses_are_bound() {
+        assert_eq!(
+            Server::bind("0.0.0.0:0").err(),
+            Some(ControlError::NotLoopback)
+        );
+    }
+}
@@ -0,0 +1,406 @@
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, CHACHA20_POLY1305, NONCE_LEN};
+use ring::digest::{self, SHA256};
+use ring::rand::{SecureRandom, SystemRandom};
+use ring::signature::{self, Ed25519KeyPair, KeyPair as _};
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::Path;
+use x25519_dalek::{PublicKey, StaticSecret};
+
+#[derive(Debug, Clone)]
+pub enum CryptoError {
+    RandomError,
+    KeyError,
+    SealError,
+    OpenError,
+    FormatError,
+}
+
+pub fn random_bytes(buf: &mut [u8]) -> Result<(), CryptoError> {
+    SystemRandom::new()
+        .fill(buf)
+        .map_err(|_| CryptoError::RandomError)
+}
+
+pub fn random_key() -> Result<[u8; 32], CryptoError> {
+    let mut key = [0u8; 32];
+    random_bytes(&mut key)?;
+    Ok(key)
+}
+
+// Key files are only readable by their owner
+pub fn write_private(path: &Path, data: &[u8]) -> std::io::Result<()> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    let mut file = options.open(path)?;
+
+    // the mode only applies to files created just now
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        file.set_permissions(std::fs::Permissions::from_mode(0o600))?;
+    }
+
+    file.write_all(data)
+}
+
+pub fn encode(bytes: &[u8]) -> String {
+    bs58::encode(bytes).into_string()
+}
+
+pub fn decode(text: &str) -> Result<Vec<u8>, CryptoError> {
+    bs58::decode(text.trim())
+        .into_vec()
+        .map_err(|_| CryptoError::FormatError)
+}
+
+pub fn decode_array(text: &str) -> Result<[u8; 32], CryptoError> {
+    let bytes = decode(text)?;
+    if bytes.len() != 32 {
+        return Err(CryptoError::FormatError);
+    }
+
+    let mut array = [0u8; 32];
+    array.copy_from_slice(&bytes);
+    Ok(array)
+}
+
+// Symmetric encryption (ChaCha20-Poly1305), tag appended to the ciphertext
+pub fn seal(key: &[u8; 32], nonce: [u8; NONCE_LEN], data: &[u8]) -> Result<Vec<u8>, CryptoError> {
+    let key = UnboundKey::new(&CHACHA20_POLY1305, key).map_err(|_| CryptoError::KeyError)?;
+    let mut in_out = data.to_vec();
+
+    LessSafeKey::new(key)
+        .seal_in_place_append_tag(
+            Nonce::assume_unique_for_key(nonce),
+            Aad::empty(),
+            &mut in_out,
+        )
+        .map_err(|_| CryptoError::SealError)?;
+
+    Ok(in_out)
+}
+
+pub fn open(key: &[u8; 32], nonce: [u8; NONCE_LEN], data: &[u8]) -> Result<Vec<u8>, CryptoError> {
+    let key = UnboundKey::new(&CHACHA20_POLY1305, key).map_err(|_| CryptoError::KeyError)?;
+    let mut in_out = data.to_vec();
+
+    let plain = LessSafeKey::new(key)
+        .open_in_place(
+            Nonce::assume_unique_for_key(nonce),
+            Aad::empty(),
+            &mut in_out,
+        )
+        .map_err(|_| CryptoError::OpenError)?;
+
+    Ok(plain.to_vec())
+}
+
+pub fn random_nonce() -> Result<[u8; NONCE_LEN], CryptoError> {
+    let mut nonce = [0u8; NONCE_LEN];
+    random_bytes(&mut nonce)?;
+    Ok(nonce)
+}
+
+pub fn decode_nonce(text: &str) -> Result<[u8; NONCE_LEN], CryptoError> {
+    let bytes = decode(text)?;
+    if bytes.len() != NONCE_LEN {
+        return Err(CryptoError::FormatError);
+    }
+
+    let mut nonce = [0u8; NONCE_LEN];
+    nonce.copy_from_slice(&bytes);
+    Ok(nonce)
+}
+
+// Long-lived keys of the local user, stored in the keyring
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SecretKeys {
+    pub name: String,
+    signing: String,
+    exchange: String,
+}
+
+// The part of a key that can be handed out to other people
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PublicKeys {
+    pub name: String,
+    pub signing: String,
+    pub exchange: String,
+}
+
+const EXPORT_PREFIX: &str = "fuzzykey1";
+
+const SECRET_EXPORT_PREFIX: &str = "fuzzysecret1";
+
+// Exported keys separate their fields with ':'
+pub fn is_valid_name(name: &str) -> bool {
+    !name.is_empty() && !name.contains(':')
+}
+
+impl SecretKeys {
+    pub fn generate(name: &str) -> Result<SecretKeys, CryptoError> {
+        if !is_valid_name(name) {
+            return Err(CryptoError::FormatError);
+        }
+
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new())
+            .map_err(|_| CryptoError::KeyError)?;
+
+        Ok(SecretKeys {
+            name: name.to_owned(),
+            signing: encode(pkcs8.as_ref()),
+            exchange: encode(&random_key()?),
+        })
+    }
+
+    fn signing_pair(&self) -> Result<Ed25519KeyPair, CryptoError> {
+        Ed25519KeyPair::from_pkcs8(&decode(&self.signing)?).map_err(|_| CryptoError::KeyError)
+    }
+
+    pub fn exchange_secret(&self) -> Result<StaticSecret, CryptoError> {
+        Ok(StaticSecret::from(decode_array(&self.exchange)?))
+    }
+
+    pub fn public(&self) -> Result<PublicKeys, CryptoError> {
+        let signing = self.signing_pair()?;
+        let exchange = PublicKey::from(&self.exchange_secret()?);
+
+        Ok(PublicKeys {
+            name: self.name.clone(),
+            signing: encode(signing.public_key().as_ref()),
+            exchange: encode(exchange.as_bytes()),
+        })
+    }
+
+    pub fn sign(&self, data: &[u8]) -> Result<String, CryptoError> {
+        Ok(encode(self.signing_pair()?.sign(data).as_ref()))
+    }
+
+    // fuzzysecret1:<name>:<signing key pair>:<exchange secret>, to restore the key elsewhere
+    pub fn export(&self) -> String {
+        format!(
+            "{}:{}:{}:{}",
+            SECRET_EXPORT_PREFIX, self.name, self.signing, self.exchange
+        )
+    }
+
+    pub fn import(text: &str) -> Result<SecretKeys, CryptoError> {
+        let parts: Vec<&str> = text.trim().split(':').collect();
+
+        match parts.as_slice() {
+            [prefix, name, signing, exchange]
+                if *prefix == SECRET_EXPORT_PREFIX && is_valid_name(name) =>
+            {
+                let keys = SecretKeys {
+                    name: (*name).to_owned(),
+                    signing: (*signing).to_owned(),
+                    exchange: (*exchange).to_owned(),
+                };
+
+                // reject keys that do not decode
+                let _ = keys.public()?;
+
+                Ok(keys)
+            }
+            _ => Err(CryptoError::FormatError),
+        }
+    }
+}
+
+impl PublicKeys {
+    pub fn exchange_key(&self) -> Result<PublicKey, CryptoError> {
+        Ok(PublicKey::from(decode_array(&self.exchange)?))
+    }
+
+    pub fn verify(&self, data: &[u8], signature: &str) -> bool {
+        match (decode(&self.signing), decode(signature)) {
+            (Ok(key), Ok(signature)) => signature::UnparsedPublicKey::new(&signature::ED25519, key)
+                .verify(data, &signature)
+                .is_ok(),
+            _ => false,
+        }
+    }
+
+    // fuzzykey1:<name>:<signing key>:<exchange key>
+    pub fn export(&self) -> String {
+        format!(
+            "{}:{}:{}:{}",
+            EXPORT_PREFIX, self.name, self.signing, self.exchange
+        )
+    }
+
+    pub fn import(text: &str) -> Result<PublicKeys, CryptoError> {
+        let parts: Vec<&str> = text.trim().split(':').collect();
+
+        match parts.as_slice() {
+            [prefix, name, signing, exchange]
+                if *prefix == EXPORT_PREFIX && is_valid_name(name) =>
+            {
+                let keys = PublicKeys {
+                    name: (*name).to_owned(),
+                    signing: (*signing).to_owned(),
+                    exchange: (*exchange).to_owned(),
+                };
+
+                // reject keys that do not decode
+                let _ = keys.exchange_key()?;
+                decode_array(&keys.signing)?;
+
+                Ok(keys)
+            }
+            _ => Err(CryptoError::FormatError),
+        }
+    }
+
+    // Short fingerprint to compare keys out of band
+    pub fn fingerprint(&self) -> String {
+        let hash = digest::digest(
+            &SHA256,
+            format!("{}{}", self.signing, self.exchange).as_bytes(),
+        );
+
+        encode(&hash.as_ref()[..8])
+    }
+}
+
+// A symmetric key wrapped for one recipient with an ephemeral X25519 exchange
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WrappedKey {
+    pub recipient: String,
+    pub ephemeral: String,
+    pub nonce: String,
+    pub sealed: String,
+}
+
+fn key_encryption_key(shared: &[u8], ephemeral: &PublicKey, recipient: &PublicKey) -> [u8; 32] {
+    let mut context = digest::Context::new(&SHA256);
+    context.update(shared);
+    context.update(ephemeral.as_bytes());
+    context.update(recipient.as_bytes());
+
+    let mut key = [0u8; 32];
+    key.copy_from_slice(context.finish().as_ref());
+    key
+}
+
+pub fn wrap_key(key: &[u8; 32], recipient: &PublicKey) -> Result<WrappedKey, CryptoError> {
+    let ephemeral = StaticSecret::from(random_key()?);
+    let ephemeral_public = PublicKey::from(&ephemeral);
+    let shared = ephemeral.diffie_hellman(recipient);
+    let kek = key_encryption_key(shared.as_bytes(), &ephemeral_public, recipient);
+    let nonce = random_nonce()?;
+
+    Ok(WrappedKey {
+        recipient: encode(recipient.as_bytes()),
+        ephemeral: encode(ephemeral_public.as_bytes()),
+        nonce: encode(&nonce),
+        sealed: encode(&seal(&kek, nonce, key)?),
+    })
+}
+
+pub fn unwrap_key(wrapped: &WrappedKey, secret: &StaticSecret) -> Result<[u8; 32], CryptoError> {
+    let ephemeral = PublicKey::from(decode_array(&wrapped.ephemeral)?);
+    let recipient = PublicKey::from(secret);
+    let shared = secret.diffie_hellman(&ephemeral);
+    let kek = key_encryption_key(shared.as_bytes(), &ephemeral, &recipient);
+
+    let key = open(
+        &kek,
+        decode_nonce(&wrapped.nonce)?,
+        &decode(&wrapped.sealed)?,
+    )?;
+    if key.len() != 32 {
+        return Err(CryptoError::FormatError);
+    }
+
+    let mut array = [0u8; 32];
+    array.copy_from_slice(&key);
+    Ok(array)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sealed_data_opens_with_the_same_key_and_nonce() {
+        let key = random_key().unwrap();
+        let nonce = random_nonce().unwrap();
+        let sealed = seal(&key, nonce, b"secret").unwrap();
+
+        assert_eq!(open(&key, nonce, &sealed).unwrap(), b"secret");
+        assert!(open(&random_key().unwrap(), nonce, &sealed).is_err());
+        assert!(open(&key, random_nonce().unwrap(), &sealed).is_err());
+
+        let mut tampered = sealed.clone();
+        tampered[0] ^= 1;
+        assert!(open(&key, nonce, &tampered).is_err());
+    }
+
+    #[test]
+    fn wrapped_keys_open_for_their_recipient_only() {
+        let key = random_key().unwrap();
+        let alice = SecretKeys::generate("alice").unwrap();
+        let bob = SecretKeys::generate("bob").unwrap();
+
+        let recipient = alice.public().unwrap().exchange_key().unwrap();
+        let wrapped = wrap_key(&key, &recipient).unwrap();
+
+        assert_eq!(
+            unwrap_key(&wrapped, &alice.exchange_secret().unwrap()).unwrap(),
+            key
+        );
+        assert!(unwrap_key(&wrapped, &bob.exchange_secret().unwrap()).is_err());
+    }
+
+    #[test]
+    fn signatures_verify_with_the_signer_key_only() {
+        let alice = SecretKeys::generate("alice").unwrap();
+        let bob = SecretKeys::generate("bob").unwrap();
+        let signature = alice.sign(b"message").unwrap();
+
+        assert!(alice.public().unwrap().verify(b"message", &signature));
+        assert!(!alice.public().unwrap().verify(b"massage", &signature));
+        assert!(!bob.public().unwrap().verify(b"message", &signature));
+        assert!(!alice
+            .public()
+            .unwrap()
+            .verify(b"message", "not a signature"));
+    }
+
+    #[test]
+    fn keys_are_imported_as_exported() {
+        let alice = SecretKeys::generate("alice").unwrap();
+        let public = alice.public().unwrap();
+
+        assert_eq!(PublicKeys::import(&public.export()).unwrap(), public);
+
+        let restored = SecretKeys::import(&format!(" {}\n", alice.export())).unwrap();
+        assert_eq!(restored.name, "alice");
+        assert_eq!(restored.public().unwrap(), public);
+
+        // one is not the other
+        assert!(PublicKeys::import(&alice.export()).is_err());
+        assert!(SecretKeys::import(&public.export()).is_err());
+        assert!(PublicKeys::import("fuzzykey1:alice:abc:def").is_err());
+        assert!(SecretKeys::import("fuzzysecret1:alice:abc:def").is_err());
+    }
+
+    #[test]
+    fn names_can_not_hold_the_separator() {
+        assert!(SecretKeys::generate("").is_err());
+        assert!(SecretKeys::generate("alice:bob").is_err());
+
+        let public = SecretKeys::generate("alice").unwrap().public().unwrap();
+        let export = public.export().replacen("alice", "ali:ce", 1);
+        assert!(PublicKeys::import(&export).is_err());
+    }
+}
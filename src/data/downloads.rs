@@ -0,0 +1,401 @@
+use super::catalog::{now, LoadError, SaveError};
+use super::sharing::SharingKey;
+use super::store::{exists, part_path, BlockStore, StoreError};
+use iced::button;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+// Throughput is averaged over the last few seconds
+const RATE_WINDOW: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum State {
+    Locating,
+    Fetching,
+    Paused,
+    Complete(PathBuf),
+    Failed(String),
+}
+
+// A file or a directory fetched by its root cid; the blocks already in the store are never
+// asked again, which is how an interrupted download picks up where it stopped
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Download {
+    pub root: String,
+    pub name: String,
+    pub size: u64,
+    pub chunks: Vec<String>,
+    pub providers: Vec<String>,
+    pub peers: Vec<String>,
+    pub state: State,
+    pub started: u64,
+    #[serde(default)]
+    pub pinned: bool,
+    #[serde(default)]
+    pub directory: bool,
+
+    // manifests and directories still missing to know every chunk, the root until refreshed
+    #[serde(skip)]
+    pub pending: Vec<String>,
+    #[serde(skip)]
+    pub known: bool,
+    #[serde(skip)]
+    pub present: usize,
+    #[serde(skip)]
+    pub rate: u64,
+    #[serde(skip)]
+    samples: VecDeque<(Instant, u64)>,
+    #[serde(skip)]
+    pub pin_button: button::State,
+    #[serde(skip)]
+    pub pause_button: button::State,
+    #[serde(skip)]
+    pub cancel_button: button::State,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Downloads {
+    pub downloads: Vec<Download>,
+}
+
+impl Download {
+    pub fn new(root: String) -> Download {
+        Download {
+            name: root.clone(),
+            root,
+            size: 0,
+            chunks: Vec::new(),
+            providers: Vec::new(),
+            peers: Vec::new(),
+            state: State::Locating,
+            started: now(),
+            pinned: false,
+            directory: false,
+            pending: Vec::new(),
+            known: false,
+            present: 0,
+            rate: 0,
+            samples: VecDeque::new(),
+            pin_button: button::State::new(),
+            pause_button: button::State::new(),
+            cancel_button: button::State::new(),
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        match self.state {
+            State::Locating | State::Fetching => true,
+            _ => false,
+        }
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.known && self.present == self.chunks.len()
+    }
+
+    pub fn progress(&self) -> f32 {
+        if self.chunks.is_empty() {
+            0.0
+        } else {
+            self.present as f32 / self.chunks.len() as f32
+        }
+    }
+
+    // Walks the tree as far as the store allows and counts the verified chunks
+    pub fn refresh(&mut self, store: &BlockStore) {
+        let tree = store.walk(&self.root);
+
+        if let Some(name) = tree.name {
+            self.name = name;
+            self.size = tree.size;
+            self.directory = tree.directory;
+        }
+        self.known = tree.missing.is_empty();
+        self.pending = tree.missing;
+        self.chunks = tree.chunks;
+        self.present = self.chunks.iter().filter(|chunk| store.has(chunk)).count();
+    }
+
+    // The blocks still to fetch: the missing manifests and directories, and the chunks
+    // of every file known so far
+    pub fn wanted(&self, store: &BlockStore) -> Vec<String> {
+        let mut wanted: Vec<String> = self.pending.clone();
+
+        for chunk in &self.chunks {
+            if !store.has(chunk) && !wanted.contains(chunk) {
+                wanted.push(chunk.clone());
+            }
+        }
+
+        wanted
+    }
+
+    // A block of this download arrived, the same chunk may appear more than once in a file
+    pub fn received(&mut self, cid: &str, peer: String, size: usize) {
+        self.present += self.chunks.iter().filter(|chunk| *chunk == cid).count();
+        self.samples.push_back((Instant::now(), size as u64));
+
+        if !self.peers.contains(&peer) {
+            self.peers.push(peer);
+        }
+    }
+
+    pub fn tick(&mut self, now: Instant) {
+        while let Some((at, _)) = self.samples.front() {
+            if now.duration_since(*at) > RATE_WINDOW {
+                self.samples.pop_front();
+            } else {
+                break;
+            }
+        }
+
+        let bytes: u64 = self.samples.iter().map(|(_, size)| size).sum();
+        self.rate = bytes / RATE_WINDOW.as_secs();
+    }
+
+    pub fn destination(&self) -> PathBuf {
+        self.destination_in(&download_dir())
+    }
+
+    // The name comes from another peer, only its last component is kept; a number is
+    // added to it when something already has that name, nothing is replaced
+    fn destination_in(&self, dir: &Path) -> PathBuf {
+        let name = Path::new(&self.name);
+        let (stem, extension) = match (name.file_stem(), name.file_name()) {
+            (Some(stem), Some(_)) => (
+                stem.to_string_lossy().into_owned(),
+                name.extension()
+                    .map(|extension| format!(".{}", extension.to_string_lossy()))
+                    .unwrap_or_default(),
+            ),
+            _ => (self.root.clone(), String::new()),
+        };
+
+        let mut path = dir.join(format!("{}{}", stem, extension));
+        let mut copy = 1;
+
+        while exists(&path) || exists(&part_path(&path)) {
+            path = dir.join(format!("{} ({}){}", stem, copy, extension));
+            copy += 1;
+        }
+
+        path
+    }
+}
+
+fn download_dir() -> PathBuf {
+    if let Some(user_dirs) = directories::UserDirs::new() {
+        if let Some(dir) = user_dirs.download_dir() {
+            return dir.into();
+        }
+    }
+
+    let mut path =
+        if let Some(project_dirs) = directories::ProjectDirs::from("rs", "Iced", "FuzzyNet") {
+            project_dirs.data_dir().into()
+        } else {
+            std::env::current_dir().unwrap_or(PathBuf::new())
+        };
+
+    path.push("downloads");
+
+    path
+}
+
+// Writes the finished file out of the store, checking every chunk once more
+pub async fn assemble(
+    store: BlockStore,
+    root: String,
+    destination: PathBuf,
+    sharing: Option<SharingKey>,
+) -> Result<PathBuf, StoreError> {
+    store.read_tree(&root, &destination, sharing.as_ref())?;
+
+    Ok(destination)
+}
+
+impl Downloads {
+    pub fn find(&mut self, root: &str) -> Option<&mut Download> {
+        self.downloads
+            .iter_mut()
+            .find(|download| download.root == root)
+    }
+
+    fn path() -> PathBuf {
+        let mut path =
+            if let Some(project_dirs) = directories::ProjectDirs::from("rs", "Iced", "FuzzyNet") {
+                project_dirs.data_dir().into()
+            } else {
+                std::env::current_dir().unwrap_or(PathBuf::new())
+            };
+
+        path.push("downloads.json");
+
+        path
+    }
+
+    pub async fn load() -> Result<Downloads, LoadError> {
+        let contents = std::fs::read_to_string(Self::path()).map_err(|_| LoadError::FileError)?;
+
+        serde_json::from_str(&contents).map_err(|_| LoadError::FormatError)
+    }
+
+    pub async fn save(self) -> Result<(), SaveError> {
+        let json = serde_json::to_string_pretty(&self).map_err(|_| SaveError::FormatError)?;
+
+        let path = Self::path();
+
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).map_err(|_| SaveError::DirectoryError)?;
+        }
+
+        std::fs::write(path, json).map_err(|_| SaveError::WriteError)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scratch(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "fuzzynet-downloads-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        dir
+    }
+
+    fn named(name: &str) -> Download {
+        let mut download = Download::new(String::from("QmRoot"));
+        download.name = name.to_owned();
+        download
+    }
+
+    #[test]
+    fn downloads_follow_the_tree_as_blocks_arrive() {
+        let dir = scratch("tree");
+        let source = BlockStore::new(dir.join("source"));
+        std::fs::write(dir.join("file.bin"), vec![7; 300 * 1024]).unwrap();
+        let root = source.add_path(&dir.join("file.bin"), &[]).unwrap().root;
+        let chunks = source.manifest(&root).unwrap().chunks;
+
+        let store = BlockStore::new(dir.join("store"));
+        let mut download = Download::new(root.clone());
+        assert_eq!(download.state, State::Locating);
+        assert!(download.is_active());
+
+        // only the root is known to be missing at first
+        download.refresh(&store);
+        assert!(!download.is_complete());
+        assert_eq!(download.wanted(&store), vec![root.clone()]);
+        assert_eq!(download.progress(), 0.0);
+
+        store.put(&source.get(&root).unwrap()).unwrap();
+        download.refresh(&store);
+        download.received(&root, String::from("alice"), 100);
+        assert_eq!(download.name, "file.bin");
+        assert_eq!(download.size, 300 * 1024);
+        assert_eq!(download.wanted(&store), chunks);
+
+        store.put(&source.get(&chunks[0]).unwrap()).unwrap();
+        download.received(&chunks[0], String::from("bob"), 256 * 1024);
+        assert_eq!(download.progress(), 0.5);
+        assert_eq!(download.peers, vec!["alice", "bob"]);
+
+        store.put(&source.get(&chunks[1]).unwrap()).unwrap();
+        download.received(&chunks[1], String::from("bob"), 44 * 1024);
+        assert!(download.is_complete());
+        assert!(download.wanted(&store).is_empty());
+
+        // what was counted is checked against the store again
+        download.refresh(&store);
+        assert_eq!(download.present, 2);
+
+        download.state = State::Paused;
+        assert!(!download.is_active());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn rates_cover_the_last_seconds() {
+        let mut download = named("file.bin");
+        let start = Instant::now();
+
+        download.received("QmChunk", String::from("alice"), 5000);
+        download.tick(start);
+        assert_eq!(download.rate, 1000);
+
+        download.tick(start + RATE_WINDOW + Duration::from_secs(1));
+        assert_eq!(download.rate, 0);
+    }
+
+    #[test]
+    fn only_the_state_is_saved() {
+        let mut download = named("file.bin");
+        download.pending = vec![String::from("QmPending")];
+        download.present = 3;
+        download.state = State::Failed(String::from("no provider found"));
+
+        let json = serde_json::to_string(&Downloads {
+            downloads: vec![download],
+        })
+        .unwrap();
+        let loaded: Downloads = serde_json::from_str(&json).unwrap();
+
+        let download = &loaded.downloads[0];
+        assert_eq!(download.name, "file.bin");
+        assert_eq!(
+            download.state,
+            State::Failed(String::from("no provider found"))
+        );
+        assert!(download.pending.is_empty());
+        assert_eq!(download.present, 0);
+
+        // saved before pins and directories
+        let older = json
+            .replace(",\"pinned\":false", "")
+            .replace(",\"directory\":false", "");
+        let loaded: Downloads = serde_json::from_str(&older).unwrap();
+        assert!(!loaded.downloads[0].pinned);
+    }
+
+    #[test]
+    fn destinations_stay_in_the_directory() {
+        let dir = Path::new("/downloads");
+
+        assert_eq!(named("file.txt").destination_in(dir), dir.join("file.txt"));
+        assert_eq!(
+            named("../../etc/passwd").destination_in(dir),
+            dir.join("passwd")
+        );
+        assert_eq!(named("/etc/").destination_in(dir), dir.join("etc"));
+        assert_eq!(named("..").destination_in(dir), dir.join("QmRoot"));
+        assert_eq!(named("").destination_in(dir), dir.join("QmRoot"));
+    }
+
+    #[test]
+    fn destinations_never_replace_anything() {
+        let dir = scratch("destination");
+        let download = named("report.pdf");
+
+        std::fs::write(dir.join("report.pdf"), b"mine").unwrap();
+        assert_eq!(download.destination_in(&dir), dir.join("report (1).pdf"));
+
+        // nor a file being written
+        std::fs::write(dir.join("report (1).pdf.part"), b"").unwrap();
+        assert_eq!(download.destination_in(&dir), dir.join("report (2).pdf"));
+
+        std::fs::create_dir(dir.join("photos")).unwrap();
+        assert_eq!(named("photos").destination_in(&dir), dir.join("photos (1)"));
+        assert_eq!(named(".config").destination_in(&dir), dir.join(".config"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,1054 @@
+use super::bandwidth::{Limits, TokenBucket};
+use super::store::{is_cid, BlockStore, CHUNK_SIZE};
+use libp2p::core::{upgrade, ConnectedPoint, InboundUpgrade, OutboundUpgrade, UpgradeInfo};
+use libp2p::swarm::{NetworkBehaviour, NetworkBehaviourAction, OneShotHandler, PollParameters};
+use libp2p::tokio_io::{AsyncRead, AsyncWrite};
+use libp2p::{Multiaddr, PeerId};
+use protobuf::wire_format::WireType;
+use protobuf::{CodedInputStream, CodedOutputStream, ProtobufError};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::marker::PhantomData;
+use std::time::{Duration, Instant};
+use std::{error, fmt, io, iter};
+use tokio::prelude::{Async, Future};
+use tokio::timer::Delay;
+
+const PROTOCOL: &[u8] = b"/fuzzynet/exchange/1.0.0";
+
+// A message carries at most one block, a chunk plus some room for the framing
+const MAX_MESSAGE_SIZE: usize = 2 * 1024 * 1024;
+
+// How long to wait before trying again once a rate limit is reached
+const THROTTLE_DELAY: Duration = Duration::from_millis(100);
+
+// A provider that sends none of the blocks asked for this long is given up on,
+// they are asked to the next one
+const WANT_TIMEOUT: Duration = Duration::from_secs(30);
+
+// Blocks asked to one provider at a time, the others wait for an answer
+const MAX_ASKED: usize = 32;
+
+// Blocks queued for upload, for one peer and in all; what is asked beyond is answered
+// with a dont_have
+const MAX_QUEUED: usize = 64;
+const MAX_OUTGOING: usize = 1024;
+
+// Wire format, protobuf encoded:
+//
+// message Message {
+//     repeated string wants = 1;
+//     repeated string dont_have = 2;
+//     repeated Block blocks = 3;
+// }
+//
+// message Block {
+//     string cid = 1;
+//     bytes data = 2;
+// }
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExchangeMessage {
+    pub wants: Vec<String>,
+    pub dont_have: Vec<String>,
+    pub blocks: Vec<Block>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Block {
+    pub cid: String,
+    pub data: Vec<u8>,
+}
+
+impl ExchangeMessage {
+    pub fn encode(&self) -> Result<Vec<u8>, ProtobufError> {
+        let mut bytes = Vec::new();
+
+        {
+            let mut stream = CodedOutputStream::vec(&mut bytes);
+
+            for cid in &self.wants {
+                stream.write_string(1, cid)?;
+            }
+            for cid in &self.dont_have {
+                stream.write_string(2, cid)?;
+            }
+            for block in &self.blocks {
+                let size = protobuf::rt::string_size(1, &block.cid)
+                    + protobuf::rt::bytes_size(2, &block.data);
+
+                stream.write_tag(3, WireType::WireTypeLengthDelimited)?;
+                stream.write_raw_varint32(size)?;
+                stream.write_string(1, &block.cid)?;
+                stream.write_bytes(2, &block.data)?;
+            }
+
+            stream.flush()?;
+        }
+
+        Ok(bytes)
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<ExchangeMessage, ProtobufError> {
+        let mut message = ExchangeMessage::default();
+        let mut stream = CodedInputStream::from_bytes(bytes);
+
+        while !stream.eof()? {
+            let (field, wire_type) = stream.read_tag_unpack()?;
+
+            match field {
+                1 => message.wants.push(stream.read_string()?),
+                2 => message.dont_have.push(stream.read_string()?),
+                3 => {
+                    let length = stream.read_raw_varint32()?;
+                    let limit = stream.push_limit(u64::from(length))?;
+                    let mut block = Block {
+                        cid: String::new(),
+                        data: Vec::new(),
+                    };
+
+                    while !stream.eof()? {
+                        let (field, wire_type) = stream.read_tag_unpack()?;
+
+                        match field {
+                            1 => block.cid = stream.read_string()?,
+                            2 => block.data = stream.read_bytes()?,
+                            _ => stream.skip_field(wire_type)?,
+                        }
+                    }
+
+                    stream.pop_limit(limit);
+                    message.blocks.push(block);
+                }
+                _ => stream.skip_field(wire_type)?,
+            }
+        }
+
+        Ok(message)
+    }
+}
+
+#[derive(Debug)]
+pub enum ExchangeDecodeError {
+    ReadError(upgrade::ReadOneError),
+    ProtobufError(ProtobufError),
+}
+
+impl From<upgrade::ReadOneError> for ExchangeDecodeError {
+    fn from(error: upgrade::ReadOneError) -> Self {
+        ExchangeDecodeError::ReadError(error)
+    }
+}
+
+impl From<ProtobufError> for ExchangeDecodeError {
+    fn from(error: ProtobufError) -> Self {
+        ExchangeDecodeError::ProtobufError(error)
+    }
+}
+
+impl fmt::Display for ExchangeDecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ExchangeDecodeError::ReadError(error) => write!(f, "Error while reading: {}", error),
+            ExchangeDecodeError::ProtobufError(error) => {
+                write!(f, "Error while decoding protobuf: {}", error)
+            }
+        }
+    }
+}
+
+impl error::Error for ExchangeDecodeError {}
+
+// Receiving side of the protocol, reads a single message per substream
+#[derive(Debug, Clone, Default)]
+pub struct ExchangeConfig;
+
+impl UpgradeInfo for ExchangeConfig {
+    type Info = &'static [u8];
+    type InfoIter = iter::Once<Self::Info>;
+
+    fn protocol_info(&self) -> Self::InfoIter {
+        iter::once(PROTOCOL)
+    }
+}
+
+impl<TSocket> InboundUpgrade<TSocket> for ExchangeConfig
+where
+    TSocket: AsyncRead + AsyncWrite,
+{
+    type Output = ExchangeMessage;
+    type Error = ExchangeDecodeError;
+    type Future = upgrade::ReadOneThen<
+        upgrade::Negotiated<TSocket>,
+        (),
+        fn(Vec<u8>, ()) -> Result<ExchangeMessage, ExchangeDecodeError>,
+    >;
+
+    fn upgrade_inbound(self, socket: upgrade::Negotiated<TSocket>, _: Self::Info) -> Self::Future {
+        upgrade::read_one_then(socket, MAX_MESSAGE_SIZE, (), |packet, ()| {
+            Ok(ExchangeMessage::decode(&packet)?)
+        })
+    }
+}
+
+// Sending side, a message opens its own substream and writes itself
+impl UpgradeInfo for ExchangeMessage {
+    type Info = &'static [u8];
+    type InfoIter = iter::Once<Self::Info>;
+
+    fn protocol_info(&self) -> Self::InfoIter {
+        iter::once(PROTOCOL)
+    }
+}
+
+impl<TSocket> OutboundUpgrade<TSocket> for ExchangeMessage
+where
+    TSocket: AsyncWrite + AsyncRead,
+{
+    type Output = ();
+    type Error = io::Error;
+    type Future = upgrade::WriteOne<upgrade::Negotiated<TSocket>>;
+
+    fn upgrade_outbound(self, socket: upgrade::Negotiated<TSocket>, _: Self::Info) -> Self::Future {
+        // encoding into a Vec can't fail
+        upgrade::write_one(socket, self.encode().unwrap_or_default())
+    }
+}
+
+#[derive(Debug)]
+pub enum InnerMessage {
+    Rx(ExchangeMessage),
+    Sent,
+}
+
+impl From<ExchangeMessage> for InnerMessage {
+    fn from(message: ExchangeMessage) -> InnerMessage {
+        InnerMessage::Rx(message)
+    }
+}
+
+impl From<()> for InnerMessage {
+    fn from(_: ()) -> InnerMessage {
+        InnerMessage::Sent
+    }
+}
+
+// What was exchanged with one peer
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Ledger {
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    pub blocks_sent: u64,
+    pub blocks_received: u64,
+}
+
+#[derive(Debug, Clone)]
+pub enum ExchangeEvent {
+    Received {
+        cid: String,
+        peer: PeerId,
+        size: usize,
+    },
+    Corrupted {
+        cid: String,
+        peer: PeerId,
+    },
+    Missing(String),
+    Ledger {
+        peer: PeerId,
+        ledger: Ledger,
+    },
+}
+
+// A block we are looking for, asked to one provider at a time; `asked` tells since
+// when that provider was last heard of
+#[derive(Debug)]
+struct Want {
+    providers: Vec<PeerId>,
+    asked: Option<(PeerId, Instant)>,
+    tried: HashSet<PeerId>,
+}
+
+pub struct Exchange<TSubstream> {
+    store: BlockStore,
+    wants: HashMap<String, Want>,
+    ledgers: HashMap<PeerId, Ledger>,
+    connected: HashSet<PeerId>,
+    dialing: HashSet<PeerId>,
+    limits: Limits,
+    upload: TokenBucket,
+    download: TokenBucket,
+    peer_upload: HashMap<PeerId, TokenBucket>,
+    peer_download: HashMap<PeerId, TokenBucket>,
+    // blocks asked to us, waiting for the upload limits
+    outgoing: VecDeque<(PeerId, String)>,
+    delay: Option<Delay>,
+    // when the providers asked are checked again
+    expiry: Option<Delay>,
+    events: VecDeque<NetworkBehaviourAction<ExchangeMessage, ExchangeEvent>>,
+    marker: PhantomData<TSubstream>,
+}
+
+impl<TSubstream> Exchange<TSubstream> {
+    pub fn new(store: BlockStore) -> Self {
+        Exchange {
+            store,
+            wants: HashMap::new(),
+            ledgers: HashMap::new(),
+            connected: HashSet::new(),
+            dialing: HashSet::new(),
+            limits: Limits::default(),
+            upload: TokenBucket::new(0),
+            download: TokenBucket::new(0),
+            peer_upload: HashMap::new(),
+            peer_download: HashMap::new(),
+            outgoing: VecDeque::new(),
+            delay: None,
+            expiry: None,
+            events: VecDeque::new(),
+            marker: PhantomData,
+        }
+    }
+
+    pub fn ledger(&self, peer: &PeerId) -> Ledger {
+        self.ledgers.get(peer).cloned().unwrap_or_default()
+    }
+
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+        self.upload.set_rate(limits.upload);
+        self.download.set_rate(limits.download);
+
+        for bucket in self.peer_upload.values_mut() {
+            bucket.set_rate(limits.peer_upload);
+        }
+        for bucket in self.peer_download.values_mut() {
+            bucket.set_rate(limits.peer_download);
+        }
+
+        self.schedule();
+        self.serve();
+    }
+
+    // Blocks already in the store are not asked again
+    pub fn fetch(&mut self, cids: Vec<String>, providers: Vec<PeerId>) {
+        for cid in cids {
+            if !is_cid(&cid) || self.store.has(&cid) {
+                continue;
+            }
+
+            let want = self.wants.entry(cid).or_insert_with(|| Want {
+                providers: Vec::new(),
+                asked: None,
+                tried: HashSet::new(),
+            });
+
+            for provider in &providers {
+                if !want.providers.contains(provider) {
+                    want.providers.push(provider.clone());
+                }
+            }
+        }
+
+        self.schedule();
+    }
+
+    pub fn cancel(&mut self, cids: &[String]) {
+        for cid in cids {
+            self.wants.remove(cid);
+        }
+    }
+
+    // Hands every pending want to the connected provider with the least work,
+    // which spreads the blocks of a file over all its providers
+    fn schedule(&mut self) {
+        let now = Instant::now();
+        self.expire(now);
+
+        let mut load: HashMap<PeerId, usize> = HashMap::new();
+        for want in self.wants.values() {
+            if let Some((peer, _)) = &want.asked {
+                *load.entry(peer.clone()).or_insert(0) += 1;
+            }
+        }
+
+        let mut batches: HashMap<PeerId, Vec<String>> = HashMap::new();
+        let mut missing = Vec::new();
+        let mut dial = Vec::new();
+        let mut throttled = false;
+        let connected = &self.connected;
+        let download = &mut self.download;
+        let peer_download = &mut self.peer_download;
+        let limits = self.limits;
+
+        for (cid, want) in self.wants.iter_mut() {
+            if want.asked.is_some() {
+                continue;
+            }
+
+            let tried = &want.tried;
+            let candidates: Vec<PeerId> = want
+                .providers
+                .iter()
+                .filter(|peer| !tried.contains(*peer))
+                .cloned()
+                .collect();
+
+            if candidates.is_empty() {
+                missing.push(cid.clone());
+                continue;
+            }
+
+            if !candidates.iter().any(|peer| connected.contains(peer)) {
+                dial.extend(candidates);
+                continue;
+            }
+
+            let available = candidates
+                .iter()
+                .filter(|peer| connected.contains(*peer))
+                .map(|peer| (peer, load.get(peer).cloned().unwrap_or(0)))
+                .filter(|(_, load)| *load < MAX_ASKED)
+                .min_by_key(|(_, load)| *load)
+                .map(|(peer, _)| peer.clone());
+
+            match available {
+                Some(peer) => {
+                    // the size of a block is only known once it arrives
+                    let bucket = peer_download
+                        .entry(peer.clone())
+                        .or_insert_with(|| TokenBucket::new(limits.peer_download));
+
+                    if !download.is_ready() || !bucket.is_ready() {
+                        throttled = true;
+                        continue;
+                    }
+
+                    download.take(CHUNK_SIZE as u64);
+                    bucket.take(CHUNK_SIZE as u64);
+
+                    *load.entry(peer.clone()).or_insert(0) += 1;
+                    batches.entry(peer.clone()).or_default().push(cid.clone());
+                    want.asked = Some((peer, now));
+                }
+                // every provider has enough to do, asked again once one answers
+                None => {}
+            }
+        }
+
+        if throttled {
+            self.throttle();
+        }
+
+        for cid in missing {
+            self.wants.remove(&cid);
+            self.events.push_back(NetworkBehaviourAction::GenerateEvent(
+                ExchangeEvent::Missing(cid),
+            ));
+        }
+
+        for peer in dial {
+            if !self.dialing.contains(&peer) && !self.connected.contains(&peer) {
+                self.dialing.insert(peer.clone());
+                self.events
+                    .push_back(NetworkBehaviourAction::DialPeer { peer_id: peer });
+            }
+        }
+
+        for (peer, wants) in batches {
+            self.events.push_back(NetworkBehaviourAction::SendEvent {
+                peer_id: peer,
+                event: ExchangeMessage {
+                    wants,
+                    ..ExchangeMessage::default()
+                },
+            });
+        }
+
+        if self.expiry.is_none() && self.wants.values().any(|want| want.asked.is_some()) {
+            self.expiry = Some(Delay::new(now + WANT_TIMEOUT));
+        }
+    }
+
+    // Gives up on the providers that sent nothing for too long
+    fn expire(&mut self, now: Instant) {
+        let expired: Vec<(String, PeerId)> = self
+            .wants
+            .iter()
+            .filter_map(|(cid, want)| match &want.asked {
+                Some((peer, since)) if now.duration_since(*since) >= WANT_TIMEOUT => {
+                    Some((cid.clone(), peer.clone()))
+                }
+                _ => None,
+            })
+            .collect();
+
+        for (cid, peer) in expired {
+            self.give_up_on(&peer, &cid);
+        }
+    }
+
+    // A provider that answers is still working on the rest of what it was asked
+    fn heard_from(&mut self, peer: &PeerId) {
+        let now = Instant::now();
+
+        for want in self.wants.values_mut() {
+            if let Some((asked, since)) = &mut want.asked {
+                if asked == peer {
+                    *since = now;
+                }
+            }
+        }
+    }
+
+    fn throttle(&mut self) {
+        if self.delay.is_none() {
+            self.delay = Some(Delay::new(Instant::now() + THROTTLE_DELAY));
+        }
+    }
+
+    fn update_ledger(&mut self, peer: &PeerId, update: impl FnOnce(&mut Ledger)) {
+        let ledger = self.ledgers.entry(peer.clone()).or_default();
+        update(ledger);
+
+        let event = ExchangeEvent::Ledger {
+            peer: peer.clone(),
+            ledger: *ledger,
+        };
+        self.events
+            .push_back(NetworkBehaviourAction::GenerateEvent(event));
+    }
+
+    // Queues what we have and tells right away what we don't;
+    // anything that isn't a cid is ignored, it would otherwise end up in a path
+    fn answer(&mut self, peer: PeerId, wants: Vec<String>) {
+        let mut dont_have = Vec::new();
+
+        let mut queued = self
+            .outgoing
+            .iter()
+            .filter(|(waiting, _)| *waiting == peer)
+            .count();
+
+        for cid in wants.into_iter().filter(|cid| is_cid(cid)) {
+            let full = queued >= MAX_QUEUED || self.outgoing.len() >= MAX_OUTGOING;
+
+            if !full && self.store.has(&cid) {
+                self.outgoing.push_back((peer.clone(), cid));
+                queued += 1;
+            } else {
+                dont_have.push(cid);
+            }
+        }
+
+        if !dont_have.is_empty() {
+            self.send_dont_have(peer, dont_have);
+        }
+
+        self.serve();
+    }
+
+    fn send_dont_have(&mut self, peer: PeerId, dont_have: Vec<String>) {
+        self.events.push_back(NetworkBehaviourAction::SendEvent {
+            peer_id: peer,
+            event: ExchangeMessage {
+                dont_have,
+                ..ExchangeMessage::default()
+            },
+        });
+    }
+
+    // Sends the queued blocks the upload limits allow, one block per message
+    fn serve(&mut self) {
+        let mut waiting = VecDeque::new();
+
+        while let Some((peer, cid)) = self.outgoing.pop_front() {
+            // nobody to send it to anymore
+            if !self.connected.contains(&peer) {
+                continue;
+            }
+
+            let limits = self.limits;
+            let bucket = self
+                .peer_upload
+                .entry(peer.clone())
+                .or_insert_with(|| TokenBucket::new(limits.peer_upload));
+
+            if !self.upload.is_ready() || !bucket.is_ready() {
+                waiting.push_back((peer, cid));
+                continue;
+            }
+
+            match self.store.get(&cid) {
+                Ok(data) => {
+                    let size = data.len() as u64;
+                    self.upload.take(size);
+                    bucket.take(size);
+
+                    self.events.push_back(NetworkBehaviourAction::SendEvent {
+                        peer_id: peer.clone(),
+                        event: ExchangeMessage {
+                            blocks: vec![Block { cid, data }],
+                            ..ExchangeMessage::default()
+                        },
+                    });
+                    self.update_ledger(&peer, |ledger| {
+                        ledger.bytes_sent += size;
+                        ledger.blocks_sent += 1;
+                    });
+                }
+                // evicted since it was asked
+                Err(_) => self.send_dont_have(peer, vec![cid]),
+            }
+        }
+
+        if !waiting.is_empty() {
+            self.outgoing = waiting;
+            self.throttle();
+        }
+    }
+
+    fn receive(&mut self, peer: PeerId, block: Block) {
+        // blocks nobody asked for are dropped
+        if !is_cid(&block.cid) || !self.wants.contains_key(&block.cid) {
+            return;
+        }
+
+        let size = block.data.len();
+
+        // a full chunk was counted when it was asked
+        let unused = CHUNK_SIZE.saturating_sub(size) as u64;
+        self.download.give(unused);
+        if let Some(bucket) = self.peer_download.get_mut(&peer) {
+            bucket.give(unused);
+        }
+
+        match self.store.put_verified(&block.cid, &block.data) {
+            Ok(()) => {
+                self.wants.remove(&block.cid);
+                self.update_ledger(&peer, |ledger| {
+                    ledger.bytes_received += size as u64;
+                    ledger.blocks_received += 1;
+                });
+                self.events.push_back(NetworkBehaviourAction::GenerateEvent(
+                    ExchangeEvent::Received {
+                        cid: block.cid,
+                        peer,
+                        size,
+                    },
+                ));
+            }
+            Err(_) => {
+                self.give_up_on(&peer, &block.cid);
+                self.events.push_back(NetworkBehaviourAction::GenerateEvent(
+                    ExchangeEvent::Corrupted {
+                        cid: block.cid,
+                        peer,
+                    },
+                ));
+            }
+        }
+    }
+
+    // The block will be asked to another provider on the next schedule
+    fn give_up_on(&mut self, peer: &PeerId, cid: &str) {
+        if let Some(want) = self.wants.get_mut(cid) {
+            want.tried.insert(peer.clone());
+            if want.asked.as_ref().map(|(asked, _)| asked) == Some(peer) {
+                want.asked = None;
+            }
+        }
+    }
+}
+
+impl<TSubstream> NetworkBehaviour for Exchange<TSubstream>
+where
+    TSubstream: AsyncRead + AsyncWrite,
+{
+    type ProtocolsHandler =
+        OneShotHandler<TSubstream, ExchangeConfig, ExchangeMessage, InnerMessage>;
+    type OutEvent = ExchangeEvent;
+
+    fn new_handler(&mut self) -> Self::ProtocolsHandler {
+        Default::default()
+    }
+
+    fn addresses_of_peer(&mut self, _: &PeerId) -> Vec<Multiaddr> {
+        Vec::new()
+    }
+
+    fn inject_connected(&mut self, peer: PeerId, _: ConnectedPoint) {
+        self.dialing.remove(&peer);
+        self.connected.insert(peer);
+        self.schedule();
+    }
+
+    fn inject_disconnected(&mut self, peer: &PeerId, _: ConnectedPoint) {
+        self.connected.remove(peer);
+        self.peer_upload.remove(peer);
+        self.peer_download.remove(peer);
+
+        let asked: Vec<String> = self
+            .wants
+            .iter()
+            .filter(|(_, want)| want.asked.as_ref().map(|(asked, _)| asked) == Some(peer))
+            .map(|(cid, _)| cid.clone())
+            .collect();
+
+        for cid in asked {
+            self.give_up_on(peer, &cid);
+        }
+
+        self.schedule();
+    }
+
+    fn inject_dial_failure(&mut self, peer: &PeerId) {
+        self.dialing.remove(peer);
+
+        for want in self.wants.values_mut() {
+            if want.providers.contains(peer) {
+                want.tried.insert(peer.clone());
+            }
+        }
+
+        self.schedule();
+    }
+
+    fn inject_node_event(&mut self, peer: PeerId, event: InnerMessage) {
+        let message = match event {
+            InnerMessage::Rx(message) => message,
+            InnerMessage::Sent => return,
+        };
+
+        if !message.wants.is_empty() {
+            self.answer(peer.clone(), message.wants);
+        }
+
+        if !message.blocks.is_empty() || !message.dont_have.is_empty() {
+            self.heard_from(&peer);
+        }
+
+        for block in message.blocks {
+            self.receive(peer.clone(), block);
+        }
+
+        for cid in message.dont_have {
+            self.give_up_on(&peer, &cid);
+        }
+
+        self.schedule();
+    }
+
+    fn poll(
+        &mut self,
+        _: &mut impl PollParameters,
+    ) -> Async<NetworkBehaviourAction<ExchangeMessage, ExchangeEvent>> {
+        if elapsed(&mut self.expiry) {
+            self.expiry = None;
+            self.schedule();
+        }
+
+        if elapsed(&mut self.delay) {
+            self.delay = None;
+            self.schedule();
+            self.serve();
+        }
+
+        // polled once so that new timers wake the task up
+        let _ = elapsed(&mut self.expiry);
+        let _ = elapsed(&mut self.delay);
+
+        match self.events.pop_front() {
+            Some(event) => Async::Ready(event),
+            None => Async::NotReady,
+        }
+    }
+}
+
+fn elapsed(timer: &mut Option<Delay>) -> bool {
+    match timer {
+        Some(timer) => match timer.poll() {
+            Ok(Async::NotReady) => false,
+            _ => true,
+        },
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::store::cid;
+    use std::io::Cursor;
+
+    type TestExchange = Exchange<Cursor<Vec<u8>>>;
+
+    fn exchange(name: &str) -> TestExchange {
+        let dir =
+            std::env::temp_dir().join(format!("fuzzynet-exchange-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+
+        Exchange::new(BlockStore::new(dir))
+    }
+
+    fn connect(exchange: &mut TestExchange) -> PeerId {
+        let peer = PeerId::random();
+        let endpoint = ConnectedPoint::Dialer {
+            address: "/ip4/127.0.0.1/tcp/4001".parse().unwrap(),
+        };
+        exchange.inject_connected(peer.clone(), endpoint);
+
+        peer
+    }
+
+    // The messages sent so far, by peer
+    fn sent(exchange: &mut TestExchange) -> Vec<(PeerId, ExchangeMessage)> {
+        exchange
+            .events
+            .drain(..)
+            .filter_map(|event| match event {
+                NetworkBehaviourAction::SendEvent { peer_id, event } => Some((peer_id, event)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn generated(exchange: &mut TestExchange) -> Vec<ExchangeEvent> {
+        exchange
+            .events
+            .drain(..)
+            .filter_map(|event| match event {
+                NetworkBehaviourAction::GenerateEvent(event) => Some(event),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn messages_survive_the_wire_encoding() {
+        let message = ExchangeMessage {
+            wants: vec![cid(b"a"), cid(b"b")],
+            dont_have: vec![cid(b"c")],
+            blocks: vec![
+                Block {
+                    cid: cid(b"d"),
+                    data: b"d".to_vec(),
+                },
+                Block {
+                    cid: cid(b""),
+                    data: Vec::new(),
+                },
+            ],
+        };
+
+        let bytes = message.encode().unwrap();
+        assert_eq!(ExchangeMessage::decode(&bytes).unwrap(), message);
+        assert_eq!(
+            ExchangeMessage::decode(&[]).unwrap(),
+            ExchangeMessage::default()
+        );
+
+        // fields from a later version are skipped
+        let mut extended = bytes.clone();
+        {
+            let mut stream = CodedOutputStream::vec(&mut extended);
+            stream.write_string(7, "later").unwrap();
+            stream.flush().unwrap();
+        }
+        assert_eq!(ExchangeMessage::decode(&extended).unwrap(), message);
+
+        // a truncated message is refused
+        assert!(ExchangeMessage::decode(&bytes[..bytes.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn wants_are_answered_with_blocks_or_dont_have() {
+        let mut provider = exchange("answer");
+        let have = provider.store.put(b"here").unwrap();
+        let missing = cid(b"elsewhere");
+        let peer = connect(&mut provider);
+
+        let message = ExchangeMessage {
+            wants: vec![have.clone(), missing.clone(), String::from("../../secret")],
+            ..ExchangeMessage::default()
+        };
+        provider.inject_node_event(peer.clone(), InnerMessage::Rx(message));
+
+        let sent = sent(&mut provider);
+        assert_eq!(sent.len(), 2);
+        assert!(sent.iter().all(|(to, _)| *to == peer));
+        assert_eq!(sent[0].1.dont_have, vec![missing]);
+        assert_eq!(
+            sent[1].1.blocks,
+            vec![Block {
+                cid: have,
+                data: b"here".to_vec(),
+            }]
+        );
+
+        assert_eq!(provider.ledger(&peer).bytes_sent, 4);
+        assert_eq!(provider.ledger(&peer).blocks_sent, 1);
+    }
+
+    #[test]
+    fn uploads_wait_in_a_bounded_queue() {
+        let mut provider = exchange("bounded");
+        let blocks: Vec<String> = (0..MAX_QUEUED + 2)
+            .map(|i| {
+                provider
+                    .store
+                    .put(format!("block {}", i).as_bytes())
+                    .unwrap()
+            })
+            .collect();
+        let peer = connect(&mut provider);
+
+        // the first block empties the bucket, the others wait
+        provider.set_limits(Limits {
+            upload: 1,
+            ..Limits::default()
+        });
+
+        let message = ExchangeMessage {
+            wants: blocks.clone(),
+            ..ExchangeMessage::default()
+        };
+        provider.inject_node_event(peer.clone(), InnerMessage::Rx(message));
+
+        let sent = sent(&mut provider);
+        assert_eq!(sent[0].1.dont_have, blocks[MAX_QUEUED..].to_vec());
+        assert_eq!(sent[1].1.blocks[0].cid, blocks[0]);
+        assert_eq!(sent.len(), 2);
+        assert_eq!(provider.outgoing.len(), MAX_QUEUED - 1);
+    }
+
+    #[test]
+    fn only_verified_blocks_are_stored() {
+        let mut requester = exchange("verified");
+        let peer = connect(&mut requester);
+        let wanted = cid(b"wanted");
+
+        requester.fetch(vec![wanted.clone()], vec![peer.clone()]);
+        let asked = sent(&mut requester);
+        assert_eq!(asked.len(), 1);
+        assert_eq!(asked[0].1.wants, vec![wanted.clone()]);
+
+        let block = |data: &[u8]| {
+            InnerMessage::Rx(ExchangeMessage {
+                blocks: vec![Block {
+                    cid: wanted.clone(),
+                    data: data.to_vec(),
+                }],
+                ..ExchangeMessage::default()
+            })
+        };
+
+        // a corrupted copy is dropped, and with no other provider the block is missing
+        requester.inject_node_event(peer.clone(), block(b"forged"));
+        assert!(!requester.store.has(&wanted));
+        let events = generated(&mut requester);
+        match &events[..] {
+            [ExchangeEvent::Corrupted { cid, .. }, ExchangeEvent::Missing(missing)] => {
+                assert_eq!(cid, &wanted);
+                assert_eq!(missing, &wanted);
+            }
+            _ => panic!("unexpected {:?}", events),
+        }
+
+        // nobody wants it anymore
+        requester.inject_node_event(peer.clone(), block(b"wanted"));
+        assert!(!requester.store.has(&wanted));
+
+        let other = connect(&mut requester);
+        requester.fetch(vec![wanted.clone()], vec![other.clone()]);
+        requester.inject_node_event(other.clone(), block(b"wanted"));
+        assert!(requester.store.has(&wanted));
+        assert!(requester.wants.is_empty());
+        assert_eq!(requester.ledger(&other).blocks_received, 1);
+    }
+
+    #[test]
+    fn blocks_are_spread_over_the_providers() {
+        let mut requester = exchange("spread");
+        let providers = vec![connect(&mut requester), connect(&mut requester)];
+        let cids: Vec<String> = (0..4).map(|i| cid(&[i])).collect();
+
+        requester.fetch(cids.clone(), providers.clone());
+
+        let sent = sent(&mut requester);
+        assert_eq!(sent.len(), 2);
+        for (peer, message) in &sent {
+            assert!(providers.contains(peer));
+            assert_eq!(message.wants.len(), 2);
+        }
+
+        let mut asked: Vec<String> = sent
+            .into_iter()
+            .flat_map(|(_, message)| message.wants)
+            .collect();
+        asked.sort();
+        let mut expected = cids;
+        expected.sort();
+        assert_eq!(asked, expected);
+    }
+
+    #[test]
+    fn providers_are_asked_a_bounded_number_of_blocks() {
+        let mut requester = exchange("asked");
+        let peer = connect(&mut requester);
+        let cids: Vec<String> = (0..MAX_ASKED + 3).map(|i| cid(&[i as u8])).collect();
+
+        requester.fetch(cids, vec![peer.clone()]);
+        assert_eq!(sent(&mut requester)[0].1.wants.len(), MAX_ASKED);
+
+        // the rest is asked as blocks come in
+        let (cid, _) = requester
+            .wants
+            .iter()
+            .find(|(_, want)| want.asked.is_some())
+            .unwrap();
+        let cid = cid.clone();
+        let message = ExchangeMessage {
+            dont_have: vec![cid],
+            ..ExchangeMessage::default()
+        };
+        requester.inject_node_event(peer, InnerMessage::Rx(message));
+
+        let sent = sent(&mut requester);
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].1.wants.len(), 1);
+    }
+
+    #[test]
+    fn silent_providers_are_given_up_on() {
+        let mut requester = exchange("silent");
+        let first = connect(&mut requester);
+        let second = connect(&mut requester);
+        let wanted = cid(b"wanted");
+
+        requester.fetch(vec![wanted.clone()], vec![first.clone(), second.clone()]);
+        let asked = sent(&mut requester)[0].0.clone();
+
+        // an answer about another block keeps the provider in
+        let earlier = Instant::now() - WANT_TIMEOUT / 2;
+        requester.wants.get_mut(&wanted).unwrap().asked = Some((asked.clone(), earlier));
+        let message = ExchangeMessage {
+            dont_have: vec![cid(b"other")],
+            ..ExchangeMessage::default()
+        };
+        requester.inject_node_event(asked.clone(), InnerMessage::Rx(message));
+        assert!(sent(&mut requester).is_empty());
+        let (_, since) = requester.wants[&wanted].asked.clone().unwrap();
+        assert!(since > earlier);
+
+        // silence doesn't
+        let expired = Instant::now() - WANT_TIMEOUT;
+        requester.wants.get_mut(&wanted).unwrap().asked = Some((asked.clone(), expired));
+        requester.schedule();
+
+        let sent = sent(&mut requester);
+        assert_eq!(sent.len(), 1);
+        assert_ne!(sent[0].0, asked);
+        assert_eq!(sent[0].1.wants, vec![wanted.clone()]);
+        assert!(requester.wants[&wanted].tried.contains(&asked));
+    }
+}
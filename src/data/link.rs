@@ -0,0 +1,211 @@
+use super::store::is_cid;
+use std::fmt;
+
+const SCHEME: &str = "fuzzynet://";
+
+// Bumped whenever the meaning of the link changes, older versions keep parsing
+const VERSION: &str = "v1";
+
+// fuzzynet://v1/<cid>?name=<name>&via=<multiaddr>&via=<multiaddr>
+//
+// `via` addresses end with /p2p/<peer id> and are peers known to provide the file,
+// query values are percent-encoded
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShareLink {
+    pub cid: String,
+    pub name: Option<String>,
+    pub via: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LinkError {
+    NotALink,
+    UnsupportedVersion(String),
+    InvalidCid,
+    InvalidQuery,
+}
+
+impl ShareLink {
+    pub fn new(cid: String) -> ShareLink {
+        ShareLink {
+            cid,
+            name: None,
+            via: Vec::new(),
+        }
+    }
+
+    pub fn parse(link: &str) -> Result<ShareLink, LinkError> {
+        let link = link.trim();
+        if !link.starts_with(SCHEME) {
+            return Err(LinkError::NotALink);
+        }
+
+        let rest = &link[SCHEME.len()..];
+
+        let (path, query) = match rest.find('?') {
+            Some(i) => (&rest[..i], &rest[i + 1..]),
+            None => (rest, ""),
+        };
+
+        let mut parts = path.trim_end_matches('/').splitn(2, '/');
+        let version = parts.next().unwrap_or_default();
+        let cid = parts.next().ok_or(LinkError::NotALink)?;
+
+        if version != VERSION {
+            return Err(LinkError::UnsupportedVersion(version.to_owned()));
+        }
+        if !is_cid(cid) {
+            return Err(LinkError::InvalidCid);
+        }
+
+        let mut share = ShareLink::new(cid.to_owned());
+
+        for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+            let mut pair = pair.splitn(2, '=');
+            let key = pair.next().unwrap_or_default();
+            let value = decode(pair.next().ok_or(LinkError::InvalidQuery)?)?;
+
+            match key {
+                "name" => share.name = Some(value),
+                "via" => share.via.push(value),
+                // unknown parameters may come from a newer version, they are skipped
+                _ => {}
+            }
+        }
+
+        Ok(share)
+    }
+}
+
+impl fmt::Display for ShareLink {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}/{}", SCHEME, VERSION, self.cid)?;
+
+        let mut separator = '?';
+        for (key, value) in self
+            .name
+            .iter()
+            .map(|name| ("name", name))
+            .chain(self.via.iter().map(|address| ("via", address)))
+        {
+            write!(f, "{}{}={}", separator, key, encode(value))?;
+            separator = '&';
+        }
+
+        Ok(())
+    }
+}
+
+fn encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+fn decode(value: &str) -> Result<String, LinkError> {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = value
+                .get(i + 1..i + 3)
+                .filter(|hex| hex.bytes().all(|byte| byte.is_ascii_hexdigit()))
+                .ok_or(LinkError::InvalidQuery)?;
+            decoded.push(u8::from_str_radix(hex, 16).map_err(|_| LinkError::InvalidQuery)?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+
+    String::from_utf8(decoded).map_err(|_| LinkError::InvalidQuery)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::store::cid;
+    use super::*;
+
+    #[test]
+    fn names_and_addresses_are_percent_encoded() {
+        let cid = cid(b"shared");
+        let link = ShareLink {
+            cid: cid.clone(),
+            name: Some(String::from("Ünïcode & spaces=?.txt")),
+            via: vec![
+                String::from("/ip4/127.0.0.1/tcp/4001/p2p/QmPeer"),
+                String::from("/dns4/example.com/tcp/4001"),
+            ],
+        };
+
+        let text = link.to_string();
+        assert_eq!(
+            text,
+            format!(
+                "fuzzynet://v1/{}?name=%C3%9Cn%C3%AFcode%20%26%20spaces%3D%3F.txt\
+                 &via=%2Fip4%2F127.0.0.1%2Ftcp%2F4001%2Fp2p%2FQmPeer\
+                 &via=%2Fdns4%2Fexample.com%2Ftcp%2F4001",
+                cid
+            )
+        );
+        assert_eq!(ShareLink::parse(&text), Ok(link));
+
+        let bare = ShareLink::new(cid.clone());
+        assert_eq!(bare.to_string(), format!("fuzzynet://v1/{}", cid));
+        assert_eq!(ShareLink::parse(&format!(" {}/ ", bare)), Ok(bare));
+    }
+
+    #[test]
+    fn only_known_versions_and_cids_are_read() {
+        let cid = cid(b"shared");
+
+        assert_eq!(
+            ShareLink::parse(&format!("https://v1/{}", cid)),
+            Err(LinkError::NotALink)
+        );
+        assert_eq!(ShareLink::parse("fuzzynet://v1"), Err(LinkError::NotALink));
+        assert_eq!(
+            ShareLink::parse(&format!("fuzzynet://v2/{}", cid)),
+            Err(LinkError::UnsupportedVersion(String::from("v2")))
+        );
+        assert_eq!(
+            ShareLink::parse("fuzzynet://v1/QmNotACid"),
+            Err(LinkError::InvalidCid)
+        );
+        assert_eq!(
+            ShareLink::parse(&format!("fuzzynet://v1/{}/../secret", cid)),
+            Err(LinkError::InvalidCid)
+        );
+    }
+
+    #[test]
+    fn unknown_query_keys_are_skipped() {
+        let cid = cid(b"shared");
+        let link = ShareLink::parse(&format!(
+            "fuzzynet://v1/{}?size=42&name=a%20b&&expires=never",
+            cid
+        ))
+        .unwrap();
+
+        assert_eq!(link.name, Some(String::from("a b")));
+        assert!(link.via.is_empty());
+
+        for query in &[
+            "name", "name=%", "name=%4", "name=%zz", "name=%+1", "name=%FF",
+        ] {
+            assert_eq!(
+                ShareLink::parse(&format!("fuzzynet://v1/{}?{}", cid, query)),
+                Err(LinkError::InvalidQuery)
+            );
+        }
+    }
+}
@@ -0,0 +1,439 @@
+use super::crypto::{self, CryptoError, PublicKeys, SecretKeys, WrappedKey};
+use iced::button;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Email {
+    pub from: String,
+    pub to: String,
+    pub subject: String,
+    pub body: Body,
+    pub folder: String,
+    pub tags: Vec<String>,
+    pub read: bool,
+
+    #[serde(skip)]
+    pub open_button: button::State,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Body {
+    Plain(String),
+    Sealed(Envelope),
+}
+
+// An encrypted body, readable by every wrapped key; the sender signs it together with
+// the headers, so that neither can be swapped
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Envelope {
+    pub sender: PublicKeys,
+    pub keys: Vec<WrappedKey>,
+    pub nonce: String,
+    pub ciphertext: String,
+    pub signature: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verification {
+    Verified,
+    UnknownKey,
+    // signed by someone else than the message claims
+    WrongSender,
+    BadSignature,
+}
+
+impl Verification {
+    pub fn badge(&self, signer: &str) -> String {
+        match self {
+            Verification::Verified => format!("Verified sender: {}", signer),
+            Verification::UnknownKey => format!("Unknown key of {}", signer),
+            Verification::WrongSender => format!("Signed by {}, not the sender!", signer),
+            Verification::BadSignature => String::from("Bad signature!"),
+        }
+    }
+}
+
+impl Email {
+    pub fn new(from: &str, to: &str, subject: &str, body: Body, folder: &str) -> Email {
+        Email {
+            from: from.to_owned(),
+            to: to.to_owned(),
+            subject: subject.to_owned(),
+            body,
+            folder: folder.to_owned(),
+            tags: Vec::new(),
+            read: false,
+            open_button: button::State::new(),
+        }
+    }
+
+    pub fn is_secure(&self) -> bool {
+        match self.body {
+            Body::Plain(_) => false,
+            Body::Sealed(_) => true,
+        }
+    }
+
+    // Searchable text of the message; sealed bodies are left out
+    pub fn plain_text(&self) -> &str {
+        match &self.body {
+            Body::Plain(text) => text,
+            Body::Sealed(_) => "",
+        }
+    }
+}
+
+// What the signature covers, every field prefixed with its length
+fn signed_data(from: &str, to: &str, subject: &str, nonce: &str, ciphertext: &str) -> Vec<u8> {
+    let mut data = Vec::new();
+
+    for field in &[from, to, subject, nonce, ciphertext] {
+        data.extend_from_slice(&(field.len() as u64).to_be_bytes());
+        data.extend_from_slice(field.as_bytes());
+    }
+
+    data
+}
+
+impl Envelope {
+    // The message comes from the name of the sender's key
+    pub fn seal(
+        sender: &SecretKeys,
+        recipients: &[&PublicKeys],
+        to: &str,
+        subject: &str,
+        text: &str,
+    ) -> Result<Envelope, CryptoError> {
+        let key = crypto::random_key()?;
+        let nonce = crypto::random_nonce()?;
+        let ciphertext = crypto::seal(&key, nonce, text.as_bytes())?;
+
+        let mut keys = Vec::new();
+        for recipient in recipients {
+            keys.push(crypto::wrap_key(&key, &recipient.exchange_key()?)?);
+        }
+
+        // the sender can always read their own copy
+        let sender_public = sender.public()?;
+        keys.push(crypto::wrap_key(&key, &sender_public.exchange_key()?)?);
+
+        let nonce = crypto::encode(&nonce);
+        let ciphertext = crypto::encode(&ciphertext);
+        let signature =
+            sender.sign(&signed_data(&sender.name, to, subject, &nonce, &ciphertext))?;
+
+        Ok(Envelope {
+            sender: sender_public,
+            keys,
+            nonce,
+            ciphertext,
+            signature,
+        })
+    }
+
+    pub fn open(&self, own: &SecretKeys) -> Result<String, CryptoError> {
+        let secret = own.exchange_secret()?;
+        let public = own.public()?;
+
+        let wrapped = self
+            .keys
+            .iter()
+            .find(|wrapped| wrapped.recipient == public.exchange)
+            .ok_or(CryptoError::KeyError)?;
+
+        let key = crypto::unwrap_key(wrapped, &secret)?;
+        let plain = crypto::open(
+            &key,
+            crypto::decode_nonce(&self.nonce)?,
+            &crypto::decode(&self.ciphertext)?,
+        )?;
+
+        String::from_utf8(plain).map_err(|_| CryptoError::FormatError)
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Keyring {
+    pub own: Option<SecretKeys>,
+    pub contacts: Vec<PublicKeys>,
+}
+
+impl Keyring {
+    pub fn contact(&self, name: &str) -> Option<&PublicKeys> {
+        self.contacts.iter().find(|keys| keys.name == name)
+    }
+
+    // Importing a key for a known name replaces the previous one
+    pub fn import(&mut self, keys: PublicKeys) {
+        self.contacts.retain(|contact| contact.name != keys.name);
+        self.contacts.push(keys);
+    }
+
+    // Plain messages carry no signature
+    pub fn verify(&self, email: &Email) -> Option<Verification> {
+        let envelope = match &email.body {
+            Body::Plain(_) => return None,
+            Body::Sealed(envelope) => envelope,
+        };
+
+        let data = signed_data(
+            &email.from,
+            &email.to,
+            &email.subject,
+            &envelope.nonce,
+            &envelope.ciphertext,
+        );
+
+        if !envelope.sender.verify(&data, &envelope.signature) {
+            return Some(Verification::BadSignature);
+        }
+        if email.from != envelope.sender.name {
+            return Some(Verification::WrongSender);
+        }
+
+        let own = self.own.as_ref().and_then(|own| own.public().ok());
+        let known = own
+            .iter()
+            .chain(self.contacts.iter())
+            .any(|keys| *keys == envelope.sender);
+
+        if known {
+            Some(Verification::Verified)
+        } else {
+            Some(Verification::UnknownKey)
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Mailbox {
+    pub emails: Vec<Email>,
+}
+
+// Persistence
+#[derive(Debug, Clone)]
+pub enum LoadError {
+    FileError,
+    FormatError,
+}
+
+#[derive(Debug, Clone)]
+pub enum SaveError {
+    DirectoryError,
+    WriteError,
+    FormatError,
+}
+
+pub fn path(file: &str) -> std::path::PathBuf {
+    let mut path = if let Some(project_dirs) = directories::ProjectDirs::from("rs", "Iced", "Mail")
+    {
+        project_dirs.data_dir().into()
+    } else {
+        std::env::current_dir().unwrap_or(std::path::PathBuf::new())
+    };
+
+    path.push(file);
+
+    path
+}
+
+pub fn load<T: serde::de::DeserializeOwned>(file: &str) -> Result<T, LoadError> {
+    let contents = std::fs::read_to_string(path(file)).map_err(|_| LoadError::FileError)?;
+
+    serde_json::from_str(&contents).map_err(|_| LoadError::FormatError)
+}
+
+pub fn save<T: Serialize>(file: &str, value: &T) -> Result<(), SaveError> {
+    let json = serde_json::to_string_pretty(value).map_err(|_| SaveError::FormatError)?;
+
+    let path = path(file);
+
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).map_err(|_| SaveError::DirectoryError)?;
+    }
+
+    // the keyring holds the secret key, nothing here is for other users
+    crypto::write_private(&path, json.as_bytes()).map_err(|_| SaveError::WriteError)
+}
+
+impl Keyring {
+    pub fn load() -> Keyring {
+        load("keyring.json").unwrap_or_default()
+    }
+
+    pub fn save(&self) -> Result<(), SaveError> {
+        save("keyring.json", self)
+    }
+}
+
+impl Mailbox {
+    pub fn load() -> Mailbox {
+        load("mailbox.json").unwrap_or_default()
+    }
+
+    pub fn save(&self) -> Result<(), SaveError> {
+        save("mailbox.json", self)
+    }
+
+    pub fn in_folder(&self, folder: &str) -> Vec<usize> {
+        self.emails
+            .iter()
+            .enumerate()
+            .filter(|(_, email)| email.folder == folder)
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    pub fn folders(&self) -> Vec<String> {
+        let mut folders: Vec<String> = self
+            .emails
+            .iter()
+            .map(|email| email.folder.clone())
+            .filter(|folder| !STANDARD_FOLDERS.contains(&folder.as_str()))
+            .collect();
+        folders.sort();
+        folders.dedup();
+        folders
+    }
+
+    pub fn tags(&self) -> Vec<String> {
+        let mut tags: Vec<String> = self
+            .emails
+            .iter()
+            .flat_map(|email| email.tags.iter().cloned())
+            .collect();
+        tags.sort();
+        tags.dedup();
+        tags
+    }
+}
+
+pub const STANDARD_FOLDERS: [&str; 4] = ["inbox", "sent", "spam", "trash"];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sealed(from: &SecretKeys, to: &SecretKeys, subject: &str, text: &str) -> Email {
+        let recipient = to.public().unwrap();
+        let envelope = Envelope::seal(from, &[&recipient], &to.name, subject, text).unwrap();
+
+        Email::new(
+            &from.name,
+            &to.name,
+            subject,
+            Body::Sealed(envelope),
+            "inbox",
+        )
+    }
+
+    fn keyring(own: &SecretKeys, contacts: &[&SecretKeys]) -> Keyring {
+        Keyring {
+            own: Some(own.clone()),
+            contacts: contacts
+                .iter()
+                .map(|contact| contact.public().unwrap())
+                .collect(),
+        }
+    }
+
+    fn envelope(email: &Email) -> &Envelope {
+        match &email.body {
+            Body::Sealed(envelope) => envelope,
+            Body::Plain(_) => panic!("the message is not sealed"),
+        }
+    }
+
+    #[test]
+    fn sealed_mail_opens_for_its_recipient_and_sender() {
+        let alice = SecretKeys::generate("alice").unwrap();
+        let bob = SecretKeys::generate("bob").unwrap();
+        let eve = SecretKeys::generate("eve").unwrap();
+        let email = sealed(&alice, &bob, "Hello", "Hi Bob");
+
+        assert_eq!(envelope(&email).open(&bob).unwrap(), "Hi Bob");
+        assert_eq!(envelope(&email).open(&alice).unwrap(), "Hi Bob");
+        assert!(envelope(&email).open(&eve).is_err());
+        assert_eq!(email.plain_text(), "");
+    }
+
+    #[test]
+    fn signatures_cover_the_headers() {
+        let alice = SecretKeys::generate("alice").unwrap();
+        let bob = SecretKeys::generate("bob").unwrap();
+        let email = sealed(&alice, &bob, "Hello", "Hi Bob");
+
+        assert_eq!(
+            keyring(&bob, &[&alice]).verify(&email),
+            Some(Verification::Verified)
+        );
+        assert_eq!(
+            keyring(&bob, &[]).verify(&email),
+            Some(Verification::UnknownKey)
+        );
+
+        let mut changed = email.clone();
+        changed.subject = String::from("Pay this invoice");
+        assert_eq!(
+            keyring(&bob, &[&alice]).verify(&changed),
+            Some(Verification::BadSignature)
+        );
+
+        let mut changed = email.clone();
+        changed.to = String::from("carol");
+        assert_eq!(
+            keyring(&bob, &[&alice]).verify(&changed),
+            Some(Verification::BadSignature)
+        );
+
+        // folders, tags and the read mark are the reader's own
+        let mut moved = email.clone();
+        moved.folder = String::from("work");
+        moved.tags.push(String::from("later"));
+        moved.read = true;
+        assert_eq!(
+            keyring(&bob, &[&alice]).verify(&moved),
+            Some(Verification::Verified)
+        );
+
+        let plain = Email::new("alice", "bob", "Hello", Body::Plain(String::new()), "inbox");
+        assert_eq!(keyring(&bob, &[&alice]).verify(&plain), None);
+    }
+
+    #[test]
+    fn mail_signed_by_someone_else_is_flagged() {
+        let alice = SecretKeys::generate("alice").unwrap();
+        let bob = SecretKeys::generate("bob").unwrap();
+        let eve = SecretKeys::generate("eve").unwrap();
+
+        // eve signs a message that claims to come from alice
+        let recipient = bob.public().unwrap();
+        let envelope = Envelope::seal(&eve, &[&recipient], "bob", "Hello", "Hi Bob").unwrap();
+        let forged = Email::new("alice", "bob", "Hello", Body::Sealed(envelope), "inbox");
+
+        assert_eq!(
+            keyring(&bob, &[&alice, &eve]).verify(&forged),
+            Some(Verification::BadSignature)
+        );
+
+        // even once the signature matches the headers
+        let mut envelope = Envelope::seal(&eve, &[&recipient], "bob", "Hello", "Hi Bob").unwrap();
+        envelope.signature = eve
+            .sign(&signed_data(
+                "alice",
+                "bob",
+                "Hello",
+                &envelope.nonce,
+                &envelope.ciphertext,
+            ))
+            .unwrap();
+        let forged = Email::new("alice", "bob", "Hello", Body::Sealed(envelope), "inbox");
+
+        let verification = keyring(&bob, &[&alice, &eve]).verify(&forged);
+        assert_eq!(verification, Some(Verification::WrongSender));
+        assert_eq!(
+            verification.unwrap().badge("eve"),
+            "Signed by eve, not the sender!"
+        );
+    }
+}
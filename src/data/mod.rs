@@ -1 +1,15 @@
+pub mod bandwidth;
+pub mod catalog;
+pub mod control;
+pub mod crypto;
+pub mod downloads;
+pub mod exchange;
+pub mod link;
+pub mod mail;
+pub mod node;
+pub mod rules;
+pub mod session;
+pub mod sharing;
+pub mod store;
 pub mod styles;
+pub mod time;
@@ -0,0 +1,832 @@
+use super::bandwidth::Limits;
+use super::crypto;
+use super::exchange::{Exchange, ExchangeEvent, Ledger};
+use super::store::BlockStore;
+use futures::channel::mpsc as gui;
+use libp2p::core::ConnectedPoint;
+use libp2p::identity::{self, ed25519};
+use libp2p::kad::record::store::MemoryStore;
+use libp2p::kad::record::Key;
+use libp2p::kad::{GetProvidersError, GetProvidersOk, Kademlia, KademliaEvent};
+use libp2p::mdns::{Mdns, MdnsEvent};
+use libp2p::multiaddr::Protocol;
+use libp2p::ping::{Ping, PingConfig, PingEvent, PingSuccess};
+use libp2p::swarm::protocols_handler::DummyProtocolsHandler;
+use libp2p::swarm::{
+    NetworkBehaviour, NetworkBehaviourAction, NetworkBehaviourEventProcess, PollParameters,
+};
+use libp2p::tokio_io::{AsyncRead, AsyncWrite};
+use libp2p::{Multiaddr, NetworkBehaviour, PeerId, Swarm};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::marker::PhantomData;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::prelude::{future, Async, Stream};
+use tokio::sync::mpsc;
+use tokio::timer::Interval;
+
+// Provider records expire on the other peers, announce them again regularly
+const PROVIDE_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+#[derive(Debug, Clone)]
+pub enum NodeEvent {
+    Listening(Vec<String>),
+    Connected {
+        peer: String,
+        address: String,
+    },
+    Disconnected(String),
+    Discovered {
+        peer: String,
+        address: String,
+    },
+    Expired(String),
+    Providers {
+        cid: String,
+        providers: Vec<String>,
+    },
+    Routing {
+        peers: usize,
+    },
+    Block {
+        cid: String,
+        peer: String,
+        size: usize,
+    },
+    BlockCorrupted {
+        cid: String,
+        peer: String,
+    },
+    BlockMissing(String),
+    Ledger {
+        peer: String,
+        ledger: Ledger,
+    },
+    Ping {
+        peer: String,
+        rtt: Duration,
+    },
+    Error(String),
+}
+
+#[derive(Debug, Clone)]
+pub enum NodeCommand {
+    Dial(Multiaddr),
+    Disconnect(PeerId),
+    Provide(Vec<String>),
+    FindProviders(String),
+    Fetch {
+        cids: Vec<String>,
+        providers: Vec<PeerId>,
+    },
+    Cancel(Vec<String>),
+    AddAddress(PeerId, Multiaddr),
+    Limits(Limits),
+}
+
+#[derive(Debug, Clone)]
+pub enum NodeError {
+    IdentityError,
+    DiscoveryError,
+    ListenError,
+    AddressError,
+    Stopped,
+}
+
+// Handle kept by the application, the swarm itself runs on its own thread
+#[derive(Debug, Clone)]
+pub struct Node {
+    pub peer_id: String,
+    commands: mpsc::UnboundedSender<NodeCommand>,
+    events: Arc<Mutex<Option<gui::UnboundedReceiver<NodeEvent>>>>,
+}
+
+fn identity_path() -> PathBuf {
+    let mut path =
+        if let Some(project_dirs) = directories::ProjectDirs::from("rs", "Iced", "FuzzyNet") {
+            project_dirs.data_dir().into()
+        } else {
+            std::env::current_dir().unwrap_or(PathBuf::new())
+        };
+
+    path.push("identity.key");
+
+    path
+}
+
+// The node keeps the same PeerId between runs
+pub fn load_identity() -> Result<identity::Keypair, NodeError> {
+    let path = identity_path();
+
+    if let Ok(mut bytes) = std::fs::read(&path) {
+        return ed25519::Keypair::decode(&mut bytes)
+            .map(identity::Keypair::Ed25519)
+            .map_err(|_| NodeError::IdentityError);
+    }
+
+    let keypair = ed25519::Keypair::generate();
+
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).map_err(|_| NodeError::IdentityError)?;
+    }
+    crypto::write_private(&path, &keypair.encode()[..]).map_err(|_| NodeError::IdentityError)?;
+
+    Ok(identity::Keypair::Ed25519(keypair))
+}
+
+impl Node {
+    pub fn start(store: BlockStore) -> Result<Node, NodeError> {
+        let listen = "/ip4/0.0.0.0/tcp/0"
+            .parse()
+            .map_err(|_| NodeError::AddressError)?;
+
+        Node::spawn(load_identity()?, listen, store)
+    }
+
+    pub fn spawn(
+        keypair: identity::Keypair,
+        listen: Multiaddr,
+        store: BlockStore,
+    ) -> Result<Node, NodeError> {
+        let peer_id = PeerId::from(keypair.public());
+        let (command_sender, mut commands) = mpsc::unbounded_channel();
+        let (events, event_receiver) = gui::unbounded();
+
+        let transport = libp2p::build_development_transport(keypair);
+        let behaviour = Behaviour {
+            ping: Ping::new(PingConfig::new().with_keep_alive(true)),
+            presence: Presence::default(),
+            mdns: Mdns::new().map_err(|_| NodeError::DiscoveryError)?,
+            kademlia: Kademlia::new(peer_id.clone(), MemoryStore::new(peer_id.clone())),
+            exchange: Exchange::new(store),
+            events: VecDeque::new(),
+            providing: Vec::new(),
+            connected: HashSet::new(),
+            dialing: VecDeque::new(),
+        };
+
+        let mut swarm = Swarm::new(transport, behaviour, peer_id.clone());
+        Swarm::listen_on(&mut swarm, listen).map_err(|_| NodeError::ListenError)?;
+
+        std::thread::spawn(move || {
+            let mut listening = Vec::new();
+            // peers the user disconnected from, kept away until the user connects again
+            let mut banned = HashSet::new();
+            let mut routing = 0;
+            let mut refresh = None;
+
+            tokio::run(future::poll_fn(move || -> Result<Async<()>, ()> {
+                loop {
+                    match commands.poll() {
+                        Ok(Async::Ready(Some(NodeCommand::Dial(mut address)))) => {
+                            // the transport dials what comes before the peer ID; without one
+                            // there is no telling who is there, every ban is lifted
+                            match address.pop() {
+                                Some(Protocol::P2p(hash)) => {
+                                    if let Ok(peer) = PeerId::from_multihash(hash) {
+                                        banned.remove(&peer);
+                                        Swarm::unban_peer_id(&mut swarm, peer);
+                                    }
+                                }
+                                last => {
+                                    if let Some(last) = last {
+                                        address.push(last);
+                                    }
+
+                                    for peer in banned.drain() {
+                                        Swarm::unban_peer_id(&mut swarm, peer);
+                                    }
+                                }
+                            }
+
+                            if let Err(error) = Swarm::dial_addr(&mut swarm, address) {
+                                let _ =
+                                    events.unbounded_send(NodeEvent::Error(format!("{:?}", error)));
+                            }
+                        }
+                        Ok(Async::Ready(Some(NodeCommand::Disconnect(peer)))) => {
+                            // banning closes the connection, mDNS won't bring the peer back;
+                            // the swarm doesn't tell the behaviours, so they are told here
+                            let endpoint = swarm.presence.endpoints.remove(&peer);
+
+                            Swarm::ban_peer_id(&mut swarm, peer.clone());
+                            banned.insert(peer.clone());
+
+                            if let Some(endpoint) = endpoint {
+                                NetworkBehaviour::inject_disconnected(&mut *swarm, &peer, endpoint);
+                            }
+                        }
+                        Ok(Async::Ready(Some(command))) => swarm.handle(command),
+                        // every handle is gone, stop the node
+                        Ok(Async::Ready(None)) | Err(_) => return Ok(Async::Ready(())),
+                        Ok(Async::NotReady) => break,
+                    }
+                }
+
+                loop {
+                    match swarm.poll() {
+                        Ok(Async::Ready(Some(event))) => {
+                            let _ = events.unbounded_send(event);
+                        }
+                        Ok(Async::Ready(None)) | Ok(Async::NotReady) => break,
+                        Err(error) => {
+                            let _ = events.unbounded_send(NodeEvent::Error(error.to_string()));
+                            break;
+                        }
+                    }
+                }
+
+                // the interval needs the runtime, so it is created on the first poll
+                let refresh =
+                    refresh.get_or_insert_with(|| Interval::new_interval(PROVIDE_INTERVAL));
+                while let Ok(Async::Ready(Some(_))) = refresh.poll() {
+                    swarm.announce();
+                }
+
+                let peers = swarm.kademlia.kbuckets_entries().count();
+                if peers != routing {
+                    // the first peers in the routing table can finally store our records
+                    if routing == 0 {
+                        swarm.announce();
+                    }
+                    routing = peers;
+                    let _ = events.unbounded_send(NodeEvent::Routing { peers });
+                }
+
+                let addresses: Vec<String> = Swarm::listeners(&swarm)
+                    .map(|address| address.to_string())
+                    .collect();
+
+                if addresses != listening {
+                    listening = addresses.clone();
+                    let _ = events.unbounded_send(NodeEvent::Listening(addresses));
+                }
+
+                Ok(Async::NotReady)
+            }));
+        });
+
+        Ok(Node {
+            peer_id: peer_id.to_base58(),
+            commands: command_sender,
+            events: Arc::new(Mutex::new(Some(event_receiver))),
+        })
+    }
+
+    fn send(&self, command: NodeCommand) -> Result<(), NodeError> {
+        self.commands
+            .clone()
+            .try_send(command)
+            .map_err(|_| NodeError::Stopped)
+    }
+
+    pub fn dial(&self, address: &str) -> Result<(), NodeError> {
+        let address = address
+            .trim()
+            .parse()
+            .map_err(|_| NodeError::AddressError)?;
+
+        self.send(NodeCommand::Dial(address))
+    }
+
+    // Remembers where a peer can be reached, the address has to end with /p2p/<peer id>
+    pub fn add_address(&self, address: &str) -> Result<String, NodeError> {
+        let mut address: Multiaddr = address
+            .trim()
+            .parse()
+            .map_err(|_| NodeError::AddressError)?;
+
+        let peer = match address.pop() {
+            Some(Protocol::P2p(hash)) => {
+                PeerId::from_multihash(hash).map_err(|_| NodeError::AddressError)?
+            }
+            _ => return Err(NodeError::AddressError),
+        };
+
+        self.send(NodeCommand::AddAddress(peer.clone(), address))?;
+
+        Ok(peer.to_base58())
+    }
+
+    pub fn disconnect(&self, peer: &str) -> Result<(), NodeError> {
+        let peer = peer.parse().map_err(|_| NodeError::AddressError)?;
+
+        self.send(NodeCommand::Disconnect(peer))
+    }
+
+    // Replaces the set of content IDs this node announces on the DHT
+    pub fn provide(&self, roots: Vec<String>) -> Result<(), NodeError> {
+        self.send(NodeCommand::Provide(roots))
+    }
+
+    pub fn find_providers(&self, cid: &str) -> Result<(), NodeError> {
+        self.send(NodeCommand::FindProviders(cid.trim().to_owned()))
+    }
+
+    // Blocks are verified and stored as they arrive, each one reported as a Block event
+    pub fn fetch(&self, cids: Vec<String>, providers: &[String]) -> Result<(), NodeError> {
+        let providers = providers
+            .iter()
+            .filter_map(|peer| peer.parse().ok())
+            .collect();
+
+        self.send(NodeCommand::Fetch { cids, providers })
+    }
+
+    pub fn cancel(&self, cids: Vec<String>) -> Result<(), NodeError> {
+        self.send(NodeCommand::Cancel(cids))
+    }
+
+    pub fn set_limits(&self, limits: Limits) -> Result<(), NodeError> {
+        self.send(NodeCommand::Limits(limits))
+    }
+
+    // Without a window the events are read straight from the receiver, which can be taken once
+    pub fn take_events(&self) -> Option<gui::UnboundedReceiver<NodeEvent>> {
+        self.events
+            .lock()
+            .ok()
+            .and_then(|mut receiver| receiver.take())
+    }
+
+    // Only the first subscription gets the events, there is a single receiver
+    pub fn events(&self) -> iced::Subscription<NodeEvent> {
+        iced::Subscription::from_recipe(Events {
+            peer_id: self.peer_id.clone(),
+            receiver: self.events.clone(),
+        })
+    }
+}
+
+struct Events {
+    peer_id: String,
+    receiver: Arc<Mutex<Option<gui::UnboundedReceiver<NodeEvent>>>>,
+}
+
+impl<H, I> iced_native::subscription::Recipe<H, I> for Events
+where
+    H: std::hash::Hasher,
+{
+    type Output = NodeEvent;
+
+    fn hash(&self, state: &mut H) {
+        use std::hash::Hash;
+
+        std::any::TypeId::of::<Self>().hash(state);
+        self.peer_id.hash(state);
+    }
+
+    fn stream(
+        self: Box<Self>,
+        _input: futures::stream::BoxStream<'static, I>,
+    ) -> futures::stream::BoxStream<'static, Self::Output> {
+        use futures::stream::StreamExt;
+
+        let receiver = self
+            .receiver
+            .lock()
+            .ok()
+            .and_then(|mut receiver| receiver.take());
+
+        match receiver {
+            Some(receiver) => receiver.boxed(),
+            None => futures::stream::empty().boxed(),
+        }
+    }
+}
+
+#[derive(NetworkBehaviour)]
+#[behaviour(out_event = "NodeEvent", poll_method = "poll")]
+pub struct Behaviour<TSubstream: AsyncRead + AsyncWrite> {
+    ping: Ping<TSubstream>,
+    presence: Presence<TSubstream>,
+    mdns: Mdns<TSubstream>,
+    kademlia: Kademlia<TSubstream, MemoryStore>,
+    exchange: Exchange<TSubstream>,
+
+    #[behaviour(ignore)]
+    events: VecDeque<NodeEvent>,
+    #[behaviour(ignore)]
+    providing: Vec<String>,
+    #[behaviour(ignore)]
+    connected: HashSet<String>,
+    #[behaviour(ignore)]
+    dialing: VecDeque<PeerId>,
+}
+
+impl<TSubstream: AsyncRead + AsyncWrite> NetworkBehaviourEventProcess<PingEvent>
+    for Behaviour<TSubstream>
+{
+    fn inject_event(&mut self, event: PingEvent) {
+        if let Ok(PingSuccess::Ping { rtt }) = event.result {
+            self.events.push_back(NodeEvent::Ping {
+                peer: event.peer.to_base58(),
+                rtt,
+            });
+        }
+    }
+}
+
+impl<TSubstream: AsyncRead + AsyncWrite> NetworkBehaviourEventProcess<NodeEvent>
+    for Behaviour<TSubstream>
+{
+    fn inject_event(&mut self, event: NodeEvent) {
+        match &event {
+            NodeEvent::Connected { peer, .. } => {
+                self.connected.insert(peer.clone());
+            }
+            NodeEvent::Disconnected(peer) => {
+                self.connected.remove(peer);
+            }
+            _ => {}
+        }
+
+        self.events.push_back(event);
+    }
+}
+
+// Peers found on the local network are dialed right away
+impl<TSubstream: AsyncRead + AsyncWrite> NetworkBehaviourEventProcess<MdnsEvent>
+    for Behaviour<TSubstream>
+{
+    fn inject_event(&mut self, event: MdnsEvent) {
+        match event {
+            MdnsEvent::Discovered(list) => {
+                for (peer, address) in list {
+                    self.kademlia.add_address(&peer, address.clone());
+                    self.events.push_back(NodeEvent::Discovered {
+                        peer: peer.to_base58(),
+                        address: address.to_string(),
+                    });
+
+                    if !self.connected.contains(&peer.to_base58()) && !self.dialing.contains(&peer)
+                    {
+                        self.dialing.push_back(peer);
+                    }
+                }
+            }
+            MdnsEvent::Expired(list) => {
+                for (peer, _) in list {
+                    if !self.mdns.has_node(&peer) {
+                        self.events.push_back(NodeEvent::Expired(peer.to_base58()));
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<TSubstream: AsyncRead + AsyncWrite> NetworkBehaviourEventProcess<KademliaEvent>
+    for Behaviour<TSubstream>
+{
+    fn inject_event(&mut self, event: KademliaEvent) {
+        let (key, providers) = match event {
+            KademliaEvent::GetProvidersResult(Ok(GetProvidersOk { key, providers, .. })) => {
+                (key, providers)
+            }
+            // a timed out lookup still reports the providers it found on the way
+            KademliaEvent::GetProvidersResult(Err(GetProvidersError::Timeout {
+                key,
+                providers,
+                ..
+            })) => (key, providers),
+            _ => return,
+        };
+
+        self.events.push_back(NodeEvent::Providers {
+            cid: String::from_utf8_lossy(&key.to_vec()).into_owned(),
+            providers: providers.iter().map(PeerId::to_base58).collect(),
+        });
+    }
+}
+
+impl<TSubstream: AsyncRead + AsyncWrite> NetworkBehaviourEventProcess<ExchangeEvent>
+    for Behaviour<TSubstream>
+{
+    fn inject_event(&mut self, event: ExchangeEvent) {
+        let event = match event {
+            ExchangeEvent::Received { cid, peer, size } => NodeEvent::Block {
+                cid,
+                peer: peer.to_base58(),
+                size,
+            },
+            ExchangeEvent::Corrupted { cid, peer } => NodeEvent::BlockCorrupted {
+                cid,
+                peer: peer.to_base58(),
+            },
+            ExchangeEvent::Missing(cid) => NodeEvent::BlockMissing(cid),
+            ExchangeEvent::Ledger { peer, ledger } => NodeEvent::Ledger {
+                peer: peer.to_base58(),
+                ledger,
+            },
+        };
+
+        self.events.push_back(event);
+    }
+}
+
+impl<TSubstream: AsyncRead + AsyncWrite> Behaviour<TSubstream> {
+    fn handle(&mut self, command: NodeCommand) {
+        match command {
+            NodeCommand::Provide(roots) => {
+                for cid in &self.providing {
+                    if !roots.contains(cid) {
+                        self.kademlia.stop_providing(&Key::new(cid));
+                    }
+                }
+
+                self.providing = roots;
+                self.announce();
+            }
+            NodeCommand::FindProviders(cid) => {
+                self.kademlia.get_providers(Key::new(&cid));
+            }
+            NodeCommand::Fetch { cids, providers } => {
+                self.exchange.fetch(cids, providers);
+            }
+            NodeCommand::Cancel(cids) => {
+                self.exchange.cancel(&cids);
+            }
+            NodeCommand::AddAddress(peer, address) => {
+                self.kademlia.add_address(&peer, address);
+            }
+            NodeCommand::Limits(limits) => {
+                self.exchange.set_limits(limits);
+            }
+            // handled by the swarm itself
+            NodeCommand::Dial(_) | NodeCommand::Disconnect(_) => {}
+        }
+    }
+
+    fn announce(&mut self) {
+        for cid in &self.providing {
+            let _ = self.kademlia.start_providing(Key::new(cid));
+        }
+    }
+
+    fn poll<TEvent>(&mut self) -> Async<NetworkBehaviourAction<TEvent, NodeEvent>> {
+        if let Some(peer_id) = self.dialing.pop_front() {
+            return Async::Ready(NetworkBehaviourAction::DialPeer { peer_id });
+        }
+
+        match self.events.pop_front() {
+            Some(event) => Async::Ready(NetworkBehaviourAction::GenerateEvent(event)),
+            None => Async::NotReady,
+        }
+    }
+}
+
+// Reports connections opening and closing, the swarm doesn't tell us otherwise
+pub struct Presence<TSubstream> {
+    events: VecDeque<NodeEvent>,
+    endpoints: HashMap<PeerId, ConnectedPoint>,
+    marker: PhantomData<TSubstream>,
+}
+
+impl<TSubstream> Default for Presence<TSubstream> {
+    fn default() -> Self {
+        Presence {
+            events: VecDeque::new(),
+            endpoints: HashMap::new(),
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<TSubstream: AsyncRead + AsyncWrite> NetworkBehaviour for Presence<TSubstream> {
+    type ProtocolsHandler = DummyProtocolsHandler<TSubstream>;
+    type OutEvent = NodeEvent;
+
+    fn new_handler(&mut self) -> Self::ProtocolsHandler {
+        DummyProtocolsHandler::default()
+    }
+
+    fn addresses_of_peer(&mut self, _: &PeerId) -> Vec<Multiaddr> {
+        Vec::new()
+    }
+
+    fn inject_connected(&mut self, peer: PeerId, endpoint: ConnectedPoint) {
+        let address = match &endpoint {
+            ConnectedPoint::Dialer { address } => address.clone(),
+            ConnectedPoint::Listener { send_back_addr, .. } => send_back_addr.clone(),
+        };
+        self.endpoints.insert(peer.clone(), endpoint);
+
+        self.events.push_back(NodeEvent::Connected {
+            peer: peer.to_base58(),
+            address: address.to_string(),
+        });
+    }
+
+    fn inject_disconnected(&mut self, peer: &PeerId, _: ConnectedPoint) {
+        self.endpoints.remove(peer);
+        self.events
+            .push_back(NodeEvent::Disconnected(peer.to_base58()));
+    }
+
+    fn inject_node_event(&mut self, _: PeerId, event: void::Void) {
+        void::unreachable(event)
+    }
+
+    fn poll(
+        &mut self,
+        _: &mut impl PollParameters,
+    ) -> Async<NetworkBehaviourAction<void::Void, NodeEvent>> {
+        match self.events.pop_front() {
+            Some(event) => Async::Ready(NetworkBehaviourAction::GenerateEvent(event)),
+            None => Async::NotReady,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::stream::StreamExt;
+    use std::sync::mpsc::Receiver;
+    use std::time::Instant;
+
+    fn spawn(name: &str) -> (Node, Receiver<NodeEvent>) {
+        let dir =
+            std::env::temp_dir().join(format!("fuzzynet-node-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let node = Node::spawn(
+            identity::Keypair::generate_ed25519(),
+            "/ip4/127.0.0.1/tcp/0".parse().unwrap(),
+            BlockStore::new(dir),
+        )
+        .unwrap();
+
+        // the events are read on a thread of their own so that tests can wait for them
+        let mut receiver = node.take_events().unwrap();
+        let (sender, events) = std::sync::mpsc::channel();
+        std::thread::spawn(move || {
+            while let Some(event) = futures::executor::block_on(receiver.next()) {
+                if sender.send(event).is_err() {
+                    break;
+                }
+            }
+        });
+
+        (node, events)
+    }
+
+    // The first event `found` keeps something of, if one comes in time
+    fn wait<T>(
+        events: &Receiver<NodeEvent>,
+        timeout: Duration,
+        mut found: impl FnMut(NodeEvent) -> Option<T>,
+    ) -> Option<T> {
+        let deadline = Instant::now() + timeout;
+
+        loop {
+            let left = deadline.checked_duration_since(Instant::now())?;
+
+            if let Some(found) = events.recv_timeout(left).ok().and_then(&mut found) {
+                return Some(found);
+            }
+        }
+    }
+
+    fn address(node: &Node, events: &Receiver<NodeEvent>) -> String {
+        let address = wait(events, Duration::from_secs(10), |event| match event {
+            NodeEvent::Listening(addresses) => addresses.into_iter().next(),
+            _ => None,
+        })
+        .expect("the node is not listening");
+
+        format!("{}/p2p/{}", address, node.peer_id)
+    }
+
+    fn connected(events: &Receiver<NodeEvent>, to: &Node, timeout: Duration) -> bool {
+        wait(events, timeout, |event| match event {
+            NodeEvent::Connected { peer, .. } if peer == to.peer_id => Some(()),
+            _ => None,
+        })
+        .is_some()
+    }
+
+    fn disconnected(events: &Receiver<NodeEvent>, from: &Node) -> bool {
+        wait(events, Duration::from_secs(10), |event| match event {
+            NodeEvent::Disconnected(peer) if peer == from.peer_id => Some(()),
+            _ => None,
+        })
+        .is_some()
+    }
+
+    // Whether the peer is still connected once the events stop for a second
+    fn settled(events: &Receiver<NodeEvent>, to: &Node) -> bool {
+        let mut connected = true;
+
+        wait(events, Duration::from_secs(1), |event| {
+            match event {
+                NodeEvent::Connected { peer, .. } if peer == to.peer_id => connected = true,
+                NodeEvent::Disconnected(peer) if peer == to.peer_id => connected = false,
+                _ => {}
+            }
+
+            None::<()>
+        });
+
+        connected
+    }
+
+    #[test]
+    fn disconnected_peers_stay_away_until_dialed_again() {
+        let (a, a_events) = spawn("ban-a");
+        let (b, b_events) = spawn("ban-b");
+        let a_address = address(&a, &a_events);
+        let b_address = address(&b, &b_events);
+
+        // mDNS dials too, and connections opened at the same time can close each other;
+        // the test goes on with one that lasts
+        let mut dials = 0;
+        loop {
+            b.dial(&a_address).unwrap();
+            assert!(connected(&b_events, &a, Duration::from_secs(10)));
+
+            if settled(&b_events, &a) {
+                break;
+            }
+
+            dials += 1;
+            assert!(dials < 5);
+        }
+        a_events.try_iter().count();
+
+        b.disconnect(&a.peer_id).unwrap();
+        assert!(disconnected(&b_events, &a));
+        assert!(disconnected(&a_events, &b));
+
+        // the other side can't come back on its own
+        a.dial(&b_address).unwrap();
+        assert!(!connected(&b_events, &a, Duration::from_secs(3)));
+
+        b.dial(&a_address).unwrap();
+        assert!(connected(&b_events, &a, Duration::from_secs(10)));
+    }
+
+    #[test]
+    fn nodes_on_the_same_host_find_each_other() {
+        let (a, a_events) = spawn("mdns-a");
+        let (b, b_events) = spawn("mdns-b");
+
+        let discovered = |events: &Receiver<NodeEvent>, other: &Node| {
+            wait(events, Duration::from_secs(30), |event| match event {
+                NodeEvent::Discovered { peer, address } if peer == other.peer_id => Some(address),
+                _ => None,
+            })
+        };
+
+        let address = discovered(&a_events, &b).expect("b was not discovered");
+        assert!(address.parse::<Multiaddr>().is_ok());
+        assert!(discovered(&b_events, &a).is_some());
+
+        // discovered peers are dialed without being asked
+        assert!(connected(&a_events, &b, Duration::from_secs(10)));
+        assert!(connected(&b_events, &a, Duration::from_secs(10)));
+    }
+
+    #[test]
+    fn providers_are_found_across_the_cluster() {
+        let (a, a_events) = spawn("cluster-a");
+        let (_b, _b_events) = spawn("cluster-b");
+        let (c, c_events) = spawn("cluster-c");
+
+        // the store `a` was spawned with
+        let store = BlockStore::new(
+            std::env::temp_dir().join(format!("fuzzynet-node-cluster-a-{}", std::process::id())),
+        );
+        let cid = store.put(b"held by a").unwrap();
+        a.provide(vec![cid.clone()]).unwrap();
+
+        let routing = |events: &Receiver<NodeEvent>| {
+            wait(events, Duration::from_secs(30), |event| match event {
+                NodeEvent::Routing { peers } if peers >= 2 => Some(()),
+                _ => None,
+            })
+        };
+        assert!(routing(&a_events).is_some());
+        assert!(routing(&c_events).is_some());
+
+        // records sent while the connections settle get lost, `a` announces them again as it
+        // would every few minutes
+        let found = (0..20).any(|_| {
+            a.provide(vec![cid.clone()]).unwrap();
+            std::thread::sleep(Duration::from_millis(500));
+            c.find_providers(&cid).unwrap();
+
+            wait(&c_events, Duration::from_secs(3), |event| match event {
+                NodeEvent::Providers {
+                    cid: found,
+                    providers,
+                } if found == cid => Some(providers),
+                _ => None,
+            })
+            .map_or(false, |providers| providers.contains(&a.peer_id))
+        });
+        assert!(found);
+    }
+}
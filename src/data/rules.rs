@@ -0,0 +1,280 @@
+use super::mail::{self, Email, Mailbox, SaveError};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Condition {
+    FromContains(String),
+    ToContains(String),
+    SubjectContains(String),
+    BodyContains(String),
+    HasTag(String),
+    IsSecure,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Action {
+    Tag(String),
+    MoveTo(String),
+    MarkRead,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Rule {
+    pub name: String,
+    pub conditions: Vec<Condition>,
+    pub actions: Vec<Action>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Rules {
+    pub rules: Vec<Rule>,
+}
+
+fn contains(haystack: &str, needle: &str) -> bool {
+    haystack.to_lowercase().contains(&needle.to_lowercase())
+}
+
+impl Condition {
+    pub fn matches(&self, email: &Email) -> bool {
+        match self {
+            Condition::FromContains(text) => contains(&email.from, text),
+            Condition::ToContains(text) => contains(&email.to, text),
+            Condition::SubjectContains(text) => contains(&email.subject, text),
+            Condition::BodyContains(text) => contains(email.plain_text(), text),
+            Condition::HasTag(tag) => email.tags.iter().any(|t| t == tag),
+            Condition::IsSecure => email.is_secure(),
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            Condition::FromContains(text) => format!("from contains \"{}\"", text),
+            Condition::ToContains(text) => format!("to contains \"{}\"", text),
+            Condition::SubjectContains(text) => format!("subject contains \"{}\"", text),
+            Condition::BodyContains(text) => format!("body contains \"{}\"", text),
+            Condition::HasTag(tag) => format!("tagged {}", tag),
+            Condition::IsSecure => String::from("is encrypted"),
+        }
+    }
+}
+
+impl Action {
+    pub fn apply(&self, email: &mut Email) {
+        match self {
+            Action::Tag(tag) => {
+                if !email.tags.contains(tag) {
+                    email.tags.push(tag.clone());
+                }
+            }
+            Action::MoveTo(folder) => email.folder = folder.clone(),
+            Action::MarkRead => email.read = true,
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            Action::Tag(tag) => format!("tag {}", tag),
+            Action::MoveTo(folder) => format!("move to {}", folder),
+            Action::MarkRead => String::from("mark read"),
+        }
+    }
+}
+
+impl Rule {
+    pub fn new(name: &str, conditions: Vec<Condition>, actions: Vec<Action>) -> Rule {
+        Rule {
+            name: name.to_owned(),
+            conditions,
+            actions,
+        }
+    }
+
+    // A rule without conditions never matches, so it can't swallow the inbox
+    pub fn matches(&self, email: &Email) -> bool {
+        !self.conditions.is_empty()
+            && self
+                .conditions
+                .iter()
+                .all(|condition| condition.matches(email))
+    }
+
+    pub fn describe(&self) -> String {
+        let conditions: Vec<String> = self.conditions.iter().map(Condition::describe).collect();
+        let actions: Vec<String> = self.actions.iter().map(Action::describe).collect();
+
+        format!(
+            "{}: if {} then {}",
+            self.name,
+            conditions.join(" and "),
+            actions.join(", ")
+        )
+    }
+}
+
+impl Rules {
+    pub fn load() -> Rules {
+        mail::load("rules.json").unwrap_or_default()
+    }
+
+    pub fn save(&self) -> Result<(), SaveError> {
+        mail::save("rules.json", self)
+    }
+
+    // Applies every matching rule in order, returns how many rules matched
+    pub fn run(&self, email: &mut Email) -> usize {
+        let mut matched = 0;
+
+        for rule in &self.rules {
+            if rule.matches(email) {
+                for action in &rule.actions {
+                    action.apply(email);
+                }
+                matched += 1;
+            }
+        }
+
+        matched
+    }
+
+    // Runs the rules over everything in a folder, returns how many messages matched a rule
+    pub fn run_folder(&self, mailbox: &mut Mailbox, folder: &str) -> usize {
+        mailbox
+            .emails
+            .iter_mut()
+            .filter(|email| email.folder == folder)
+            .map(|email| self.run(email))
+            .filter(|matched| *matched > 0)
+            .count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::crypto::SecretKeys;
+    use super::super::mail::{Body, Envelope};
+    use super::*;
+
+    fn plain(from: &str, subject: &str, text: &str, folder: &str) -> Email {
+        Email::new(
+            from,
+            "me@fuzzy.net",
+            subject,
+            Body::Plain(text.to_owned()),
+            folder,
+        )
+    }
+
+    #[test]
+    fn conditions_match_ignoring_case() {
+        let mut email = plain(
+            "Alice@Example.com",
+            "Weekly Report",
+            "Numbers are up",
+            "inbox",
+        );
+        email.tags.push(String::from("work"));
+
+        for condition in &[
+            Condition::FromContains(String::from("alice@example")),
+            Condition::ToContains(String::from("ME@")),
+            Condition::SubjectContains(String::from("report")),
+            Condition::BodyContains(String::from("NUMBERS")),
+            Condition::HasTag(String::from("work")),
+        ] {
+            assert!(condition.matches(&email), "{}", condition.describe());
+        }
+
+        for condition in &[
+            Condition::FromContains(String::from("bob")),
+            Condition::ToContains(String::from("you@")),
+            Condition::SubjectContains(String::from("invoice")),
+            Condition::BodyContains(String::from("down")),
+            Condition::HasTag(String::from("Work")),
+            Condition::IsSecure,
+        ] {
+            assert!(!condition.matches(&email), "{}", condition.describe());
+        }
+    }
+
+    #[test]
+    fn sealed_bodies_are_secure_but_not_searched() {
+        let alice = SecretKeys::generate("alice").unwrap();
+        let bob = SecretKeys::generate("bob").unwrap();
+        let recipient = bob.public().unwrap();
+        let envelope =
+            Envelope::seal(&alice, &[&recipient], "bob", "Secret", "launch codes").unwrap();
+        let email = Email::new("alice", "bob", "Secret", Body::Sealed(envelope), "inbox");
+
+        assert!(Condition::IsSecure.matches(&email));
+        assert!(!Condition::BodyContains(String::from("launch")).matches(&email));
+    }
+
+    #[test]
+    fn matching_rules_apply_their_actions_in_order() {
+        let rules = Rules {
+            rules: vec![
+                Rule::new(
+                    "reports",
+                    vec![
+                        Condition::FromContains(String::from("alice")),
+                        Condition::SubjectContains(String::from("report")),
+                    ],
+                    vec![
+                        Action::Tag(String::from("work")),
+                        Action::MoveTo(String::from("reports")),
+                    ],
+                ),
+                Rule::new(
+                    "tagged work",
+                    vec![Condition::HasTag(String::from("work"))],
+                    vec![Action::Tag(String::from("work")), Action::MarkRead],
+                ),
+                Rule::new("everything", Vec::new(), vec![Action::MarkRead]),
+                Rule::new(
+                    "bob",
+                    vec![Condition::FromContains(String::from("bob"))],
+                    vec![Action::MoveTo(String::from("trash"))],
+                ),
+            ],
+        };
+
+        let mut report = plain("alice", "Report", "", "inbox");
+        assert_eq!(rules.run(&mut report), 2);
+        assert_eq!(report.tags, vec![String::from("work")]);
+        assert_eq!(report.folder, "reports");
+        assert!(report.read);
+
+        // the subject fails the first rule, and the rule without conditions never matches
+        let mut other = plain("alice", "Lunch?", "", "inbox");
+        assert_eq!(rules.run(&mut other), 0);
+        assert!(other.tags.is_empty());
+        assert_eq!(other.folder, "inbox");
+        assert!(!other.read);
+    }
+
+    #[test]
+    fn folders_are_run_on_their_own_messages() {
+        let rules = Rules {
+            rules: vec![Rule::new(
+                "alice",
+                vec![Condition::FromContains(String::from("alice"))],
+                vec![Action::MarkRead],
+            )],
+        };
+
+        let mut mailbox = Mailbox {
+            emails: vec![
+                plain("alice", "One", "", "inbox"),
+                plain("alice", "Two", "", "inbox"),
+                plain("bob", "Three", "", "inbox"),
+                plain("alice", "Four", "", "archive"),
+            ],
+        };
+
+        assert_eq!(rules.run_folder(&mut mailbox, "inbox"), 2);
+        let read: Vec<bool> = mailbox.emails.iter().map(|email| email.read).collect();
+        assert_eq!(read, vec![true, true, false, false]);
+
+        assert_eq!(rules.run_folder(&mut mailbox, "spam"), 0);
+    }
+}
@@ -0,0 +1,556 @@
+use super::bandwidth::Traffic;
+use super::catalog::{Catalog, CatalogError, Entry};
+use super::control::{Lookup, PeerInfo};
+use super::downloads::{Download, Downloads, State};
+use super::link::ShareLink;
+use super::node::{Node, NodeEvent};
+use super::sharing::SharingKey;
+use super::store::{is_cid, BlockStore, Manifest, StoreError};
+use std::collections::HashSet;
+use std::fmt;
+use std::path::PathBuf;
+
+// What a running node shares and fetches, driven by the window when it runs its own
+// node and by the daemon otherwise. Saving the downloads and writing out finished files
+// is left to them: see `take_changed` and `take_complete`
+#[derive(Default)]
+pub struct Session {
+    pub store: BlockStore,
+    pub node: Option<Node>,
+    pub sharing: Option<SharingKey>,
+    pub catalog: Catalog,
+    pub downloads: Downloads,
+    pub listening: Vec<String>,
+    pub peers: Vec<PeerInfo>,
+    pub lookups: Vec<Lookup>,
+    pub routing: usize,
+    pub traffic: Traffic,
+    // the downloads changed since they were last saved
+    changed: bool,
+    // downloads with every block, waiting to be written out, and those being written
+    complete: Vec<(String, PathBuf)>,
+    assembling: HashSet<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SessionError {
+    Stopped,
+    NotFound,
+    NotACid,
+    AlreadyDownloading,
+    PinError,
+}
+
+impl fmt::Display for SessionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SessionError::Stopped => write!(f, "The network node is stopped"),
+            SessionError::NotFound => write!(f, "This file is not in the downloads"),
+            SessionError::NotACid => write!(f, "This is not a valid content ID"),
+            SessionError::AlreadyDownloading => write!(f, "This file is already in the downloads"),
+            SessionError::PinError => write!(f, "The pins could not be saved"),
+        }
+    }
+}
+
+impl Session {
+    pub fn new(store: BlockStore, node: Option<Node>) -> Session {
+        Session {
+            store,
+            node,
+            ..Session::default()
+        }
+    }
+
+    pub fn node(&self) -> Result<&Node, SessionError> {
+        self.node.as_ref().ok_or(SessionError::Stopped)
+    }
+
+    // Whether the downloads changed since this was last asked, they are to be saved then
+    pub fn take_changed(&mut self) -> bool {
+        std::mem::replace(&mut self.changed, false)
+    }
+
+    // The downloads to write out with `assemble`, each given once; `assembled` is
+    // expected for every one of them
+    pub fn take_complete(&mut self) -> Vec<(String, PathBuf)> {
+        std::mem::replace(&mut self.complete, Vec::new())
+    }
+
+    // Every file of the catalog is announced as provided by this node
+    pub fn announce(&self) {
+        if let Some(node) = &self.node {
+            let roots = self
+                .catalog
+                .entries
+                .iter()
+                .filter(|entry| !entry.cid.is_empty())
+                .map(|entry| entry.cid.clone())
+                .collect();
+
+            let _ = node.provide(roots);
+        }
+    }
+
+    pub fn add(&mut self, entry: Entry) -> Result<(), CatalogError> {
+        self.catalog.add(entry, &self.store)?;
+        self.announce();
+
+        Ok(())
+    }
+
+    // The entry is no longer provided, its blocks are left to `unshare`
+    pub fn remove(&mut self, cid: &str) -> Option<Entry> {
+        let i = self
+            .catalog
+            .entries
+            .iter()
+            .position(|entry| entry.cid == cid)?;
+        let entry = self.catalog.remove(i);
+        self.announce();
+
+        entry
+    }
+
+    pub fn lookup(&mut self, cid: String) -> Result<(), SessionError> {
+        if !is_cid(&cid) {
+            return Err(SessionError::NotACid);
+        }
+        self.node()?
+            .find_providers(&cid)
+            .map_err(|_| SessionError::Stopped)?;
+
+        self.lookups.retain(|lookup| lookup.cid != cid);
+        self.lookups.push(Lookup {
+            cid,
+            providers: None,
+        });
+
+        Ok(())
+    }
+
+    // Unfinished downloads carry on from the chunks already verified
+    pub fn resume_downloads(&mut self) {
+        for i in 0..self.downloads.downloads.len() {
+            self.downloads.downloads[i].refresh(&self.store);
+
+            if self.downloads.downloads[i].is_active() {
+                self.locate(i);
+            }
+        }
+    }
+
+    // Peers given by a link are asked directly, otherwise the providers are found on the DHT
+    pub fn start_download(&mut self, link: ShareLink) -> Result<Download, SessionError> {
+        if self.downloads.find(&link.cid).is_some() {
+            return Err(SessionError::AlreadyDownloading);
+        }
+
+        let mut download = Download::new(link.cid.clone());
+        if let Some(name) = link.name {
+            download.name = name;
+        }
+        download.refresh(&self.store);
+
+        if let Some(node) = &self.node {
+            download.providers = link
+                .via
+                .iter()
+                .filter_map(|address| node.add_address(address).ok())
+                .collect();
+        }
+
+        let direct = !download.providers.is_empty() && !download.is_complete();
+        if direct {
+            download.state = State::Fetching;
+        }
+
+        let root = download.root.clone();
+        self.downloads.downloads.push(download);
+        self.changed = true;
+
+        if direct {
+            self.fetch(&root);
+        } else {
+            self.locate(self.downloads.downloads.len() - 1);
+        }
+
+        self.downloads
+            .find(&root)
+            .cloned()
+            .ok_or(SessionError::NotFound)
+    }
+
+    pub fn pause(&mut self, root: &str) -> Result<(), SessionError> {
+        let download = self.downloads.find(root).ok_or(SessionError::NotFound)?;
+        download.state = State::Paused;
+
+        if let Some(node) = &self.node {
+            let _ = node.cancel(download.wanted(&self.store));
+        }
+        self.changed = true;
+
+        Ok(())
+    }
+
+    pub fn resume(&mut self, root: &str) -> Result<(), SessionError> {
+        let i = self.position(root)?;
+        self.downloads.downloads[i].refresh(&self.store);
+        self.locate(i);
+        self.changed = true;
+
+        Ok(())
+    }
+
+    // What was fetched stays in the cache until it is evicted
+    pub fn cancel(&mut self, root: &str) -> Result<Download, SessionError> {
+        let i = self.position(root)?;
+        let download = self.downloads.downloads.remove(i);
+
+        if let Some(node) = &self.node {
+            let _ = node.cancel(download.wanted(&self.store));
+        }
+        let _ = self.store.unpin(root);
+        self.changed = true;
+
+        Ok(download)
+    }
+
+    pub fn pin(&mut self, root: &str, pinned: bool) -> Result<(), SessionError> {
+        let i = self.position(root)?;
+        let result = if pinned {
+            self.store.pin(root)
+        } else {
+            self.store.unpin(root)
+        };
+        result.map_err(|_| SessionError::PinError)?;
+
+        self.downloads.downloads[i].pinned = pinned;
+        self.changed = true;
+
+        Ok(())
+    }
+
+    // Returns what the user should hear about, if anything
+    pub fn node_event(&mut self, event: NodeEvent) -> Option<String> {
+        match event {
+            NodeEvent::Listening(addresses) => {
+                self.listening = addresses;
+            }
+            NodeEvent::Connected { peer, address } => {
+                self.peer(peer, address).connected = true;
+            }
+            NodeEvent::Disconnected(peer) => {
+                if let Some(known) = self.peers.iter_mut().find(|known| known.id == peer) {
+                    known.connected = false;
+                    known.rtt = None;
+                }
+                self.peers.retain(|known| known.connected || known.nearby);
+            }
+            NodeEvent::Discovered { peer, address } => {
+                self.peer(peer, address).nearby = true;
+            }
+            NodeEvent::Expired(peer) => {
+                if let Some(known) = self.peers.iter_mut().find(|known| known.id == peer) {
+                    known.nearby = false;
+                }
+                self.peers.retain(|known| known.connected || known.nearby);
+            }
+            NodeEvent::Ping { peer, rtt } => {
+                if let Some(known) = self.peers.iter_mut().find(|known| known.id == peer) {
+                    known.rtt = Some(rtt.as_millis() as u64);
+                }
+            }
+            NodeEvent::Routing { peers } => {
+                self.routing = peers;
+            }
+            NodeEvent::Providers { cid, providers } => {
+                if let Some(download) = self.downloads.find(&cid) {
+                    if download.state == State::Locating {
+                        if providers.is_empty() {
+                            download.state = State::Failed(String::from("no provider found"));
+                        } else {
+                            download.state = State::Fetching;
+                            download.providers = providers.clone();
+                        }
+                        self.changed = true;
+                    }
+                }
+                if let Some(lookup) = self.lookups.iter_mut().find(|lookup| lookup.cid == cid) {
+                    lookup.providers = Some(providers);
+                }
+
+                self.fetch(&cid);
+            }
+            NodeEvent::Block { cid, peer, size } => self.received(&cid, peer, size),
+            NodeEvent::BlockCorrupted { cid, peer } => {
+                return Some(format!("{} sent a corrupted copy of {}", peer, cid));
+            }
+            NodeEvent::BlockMissing(cid) => {
+                let mut failed = false;
+
+                for download in &mut self.downloads.downloads {
+                    if download.state == State::Fetching
+                        && (download.pending.contains(&cid) || download.chunks.contains(&cid))
+                    {
+                        download.state = State::Failed(format!("no provider could send {}", cid));
+                        failed = true;
+                    }
+                }
+
+                if !failed {
+                    return Some(format!("No provider could send {}", cid));
+                }
+                self.changed = true;
+            }
+            NodeEvent::Ledger { peer, ledger } => {
+                if let Some(known) = self.peers.iter_mut().find(|known| known.id == peer) {
+                    known.ledger = ledger;
+                }
+                self.traffic.record(peer, ledger);
+            }
+            NodeEvent::Error(error) => return Some(error),
+        }
+
+        None
+    }
+
+    // Returns what the user should hear about, nothing when it was cancelled meanwhile
+    pub fn assembled(&mut self, root: &str, result: Result<PathBuf, StoreError>) -> Option<String> {
+        self.assembling.remove(root);
+
+        let download = self.downloads.find(root)?;
+        let mut saved = None;
+
+        download.state = match result {
+            Ok(path) => {
+                saved = Some(format!("{} saved", path.display()));
+                State::Complete(path)
+            }
+            Err(StoreError::NoAccess) => {
+                State::Failed(String::from("this private file is not shared with you"))
+            }
+            Err(StoreError::Exists) => {
+                State::Failed(String::from("something else already has this name"))
+            }
+            Err(_) => State::Failed(String::from("the file could not be written")),
+        };
+        self.changed = true;
+
+        saved
+    }
+
+    fn received(&mut self, cid: &str, peer: String, size: usize) {
+        let mut fetch = Vec::new();
+
+        for download in &mut self.downloads.downloads {
+            if download.state != State::Fetching {
+                continue;
+            }
+
+            if download.pending.iter().any(|pending| pending == cid) {
+                // no need to fetch the chunks of a private file we can't open
+                if let (
+                    true,
+                    Ok(Manifest {
+                        encryption: Some(encryption),
+                        ..
+                    }),
+                ) = (download.root == cid, self.store.manifest(cid))
+                {
+                    let sharing = self.sharing.as_ref();
+
+                    if sharing
+                        .and_then(|sharing| sharing.open(&encryption))
+                        .is_none()
+                    {
+                        download.state =
+                            State::Failed(String::from("this private file is not shared with you"));
+                        self.changed = true;
+                        continue;
+                    }
+                }
+
+                // downloads are pinned, their blocks can't be evicted until unpinned
+                if download.root == cid && self.store.pin(cid).is_ok() {
+                    download.pinned = true;
+                }
+                // a manifest or a directory, the tree can be followed further
+                download.refresh(&self.store);
+                download.received(cid, peer.clone(), size);
+
+                self.changed = true;
+                fetch.push(download.root.clone());
+            } else if download.chunks.iter().any(|chunk| chunk == cid) {
+                download.received(cid, peer.clone(), size);
+
+                if download.is_complete() {
+                    fetch.push(download.root.clone());
+                }
+            }
+        }
+
+        for root in fetch {
+            self.fetch(&root);
+        }
+    }
+
+    fn position(&self, root: &str) -> Result<usize, SessionError> {
+        self.downloads
+            .downloads
+            .iter()
+            .position(|download| download.root == root)
+            .ok_or(SessionError::NotFound)
+    }
+
+    fn peer(&mut self, id: String, address: String) -> &mut PeerInfo {
+        let i = match self.peers.iter().position(|known| known.id == id) {
+            Some(i) => i,
+            None => {
+                self.peers.push(PeerInfo {
+                    id,
+                    address,
+                    connected: false,
+                    nearby: false,
+                    rtt: None,
+                    ledger: Default::default(),
+                });
+                self.peers.len() - 1
+            }
+        };
+
+        &mut self.peers[i]
+    }
+
+    // Finds who provides a download, the blocks are asked once the answer comes back
+    fn locate(&mut self, i: usize) {
+        let download = &mut self.downloads.downloads[i];
+
+        // every block is already here, only the file is left to write
+        if download.is_complete() {
+            download.state = State::Fetching;
+
+            let root = download.root.clone();
+            return self.fetch(&root);
+        }
+
+        download.state = State::Locating;
+
+        let located = self
+            .node
+            .as_ref()
+            .map_or(false, |node| node.find_providers(&download.root).is_ok());
+        if !located {
+            download.state = State::Failed(String::from("the network node is stopped"));
+        }
+    }
+
+    // Asks the providers for every missing block, or queues the file to be written out
+    // once none is left
+    fn fetch(&mut self, root: &str) {
+        let store = self.store.clone();
+        let node = &self.node;
+
+        if let Some(download) = self.downloads.find(root) {
+            if download.state != State::Fetching {
+                return;
+            }
+
+            if !download.is_complete() {
+                if let Some(node) = node {
+                    let _ = node.fetch(download.wanted(&store), &download.providers);
+                }
+                return;
+            }
+
+            if self.assembling.insert(root.to_owned()) {
+                self.complete
+                    .push((root.to_owned(), download.destination()));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::store;
+
+    fn session(name: &str) -> (Session, PathBuf) {
+        let dir =
+            std::env::temp_dir().join(format!("fuzzynet-session-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        (Session::new(BlockStore::new(dir.join("store")), None), dir)
+    }
+
+    #[test]
+    fn downloads_already_stored_are_written_out_once() {
+        let (mut session, dir) = session("stored");
+        std::fs::write(dir.join("file.txt"), b"already here").unwrap();
+        let root = session
+            .store
+            .add_path(&dir.join("file.txt"), &[])
+            .unwrap()
+            .root;
+
+        let download = session
+            .start_download(ShareLink::new(root.clone()))
+            .unwrap();
+        assert_eq!(download.name, "file.txt");
+        assert_eq!(download.state, State::Fetching);
+        assert!(session.take_changed());
+        assert!(!session.take_changed());
+
+        let complete = session.take_complete();
+        assert_eq!(complete.len(), 1);
+        assert_eq!(complete[0].0, root);
+        assert!(session.take_complete().is_empty());
+
+        assert_eq!(
+            session.start_download(ShareLink::new(root.clone())).err(),
+            Some(SessionError::AlreadyDownloading)
+        );
+
+        let path = dir.join("copy.txt");
+        assert_eq!(
+            session.assembled(&root, Ok(path.clone())),
+            Some(format!("{} saved", path.display()))
+        );
+        assert_eq!(
+            session.downloads.find(&root).unwrap().state,
+            State::Complete(path)
+        );
+        assert!(session.take_changed());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn missing_downloads_fail_without_a_node() {
+        let (mut session, dir) = session("offline");
+        let root = store::cid(b"elsewhere");
+
+        let download = session
+            .start_download(ShareLink::new(root.clone()))
+            .unwrap();
+        assert_eq!(
+            download.state,
+            State::Failed(String::from("the network node is stopped"))
+        );
+        assert!(session.take_complete().is_empty());
+
+        assert_eq!(session.lookup(root.clone()), Err(SessionError::Stopped));
+        assert_eq!(
+            session.lookup(String::from("QmNotACid")),
+            Err(SessionError::NotACid)
+        );
+        assert_eq!(session.pause("QmNotACid"), Err(SessionError::NotFound));
+
+        assert_eq!(session.cancel(&root).unwrap().root, root);
+        assert!(session.downloads.downloads.is_empty());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,353 @@
+use super::crypto::{
+    decode, decode_array, encode, open, random_key, seal, unwrap_key, wrap_key, write_private,
+    CryptoError, WrappedKey,
+};
+use libp2p::identity::{Keypair, PublicKey as IdentityKey};
+use libp2p::PeerId;
+use ring::aead::NONCE_LEN;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use x25519_dalek::{PublicKey, StaticSecret};
+
+const EXPORT_PREFIX: &str = "fuzzyshare2";
+
+// Poly1305 tag added to every encrypted chunk
+pub const TAG_LEN: usize = 16;
+
+// X25519 key pair private files are wrapped for, kept next to the node identity;
+// the public key is signed with the identity so that others know whose it is
+#[derive(Clone)]
+pub struct SharingKey {
+    pub peer: String,
+    identity: String,
+    signature: String,
+    secret: StaticSecret,
+}
+
+// Somebody a private file can be shared with, as given by their exported key;
+// `peer` is only set once the signature of that peer is checked
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Recipient {
+    pub peer: String,
+    pub key: String,
+    identity: String,
+    signature: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Access {
+    pub peer: String,
+    pub key: WrappedKey,
+}
+
+// Recorded in the manifest of a private file: the chunks are sealed with one random key,
+// wrapped here for every recipient
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Encryption {
+    pub keys: Vec<Access>,
+}
+
+fn path() -> PathBuf {
+    let mut path =
+        if let Some(project_dirs) = directories::ProjectDirs::from("rs", "Iced", "FuzzyNet") {
+            project_dirs.data_dir().into()
+        } else {
+            std::env::current_dir().unwrap_or(PathBuf::new())
+        };
+
+    path.push("sharing.key");
+
+    path
+}
+
+// What the identity signs: the versioned public key
+fn signed(key: &str) -> Vec<u8> {
+    format!("{}:{}", EXPORT_PREFIX, key).into_bytes()
+}
+
+impl SharingKey {
+    pub fn load_or_generate(identity: &Keypair) -> Result<SharingKey, CryptoError> {
+        let path = path();
+
+        let secret = match std::fs::read(&path) {
+            Ok(bytes) => {
+                if bytes.len() != 32 {
+                    return Err(CryptoError::KeyError);
+                }
+
+                let mut key = [0u8; 32];
+                key.copy_from_slice(&bytes);
+                key
+            }
+            Err(_) => {
+                let key = random_key()?;
+
+                if let Some(dir) = path.parent() {
+                    std::fs::create_dir_all(dir).map_err(|_| CryptoError::KeyError)?;
+                }
+                write_private(&path, &key[..]).map_err(|_| CryptoError::KeyError)?;
+
+                key
+            }
+        };
+
+        SharingKey::new(identity, secret)
+    }
+
+    fn new(identity: &Keypair, secret: [u8; 32]) -> Result<SharingKey, CryptoError> {
+        let secret = StaticSecret::from(secret);
+        let key = encode(PublicKey::from(&secret).as_bytes());
+        let signature = identity
+            .sign(&signed(&key))
+            .map_err(|_| CryptoError::KeyError)?;
+
+        Ok(SharingKey {
+            peer: PeerId::from_public_key(identity.public()).to_base58(),
+            identity: encode(&identity.public().into_protobuf_encoding()),
+            signature: encode(&signature),
+            secret,
+        })
+    }
+
+    pub fn recipient(&self) -> Recipient {
+        Recipient {
+            peer: self.peer.clone(),
+            key: encode(PublicKey::from(&self.secret).as_bytes()),
+            identity: self.identity.clone(),
+            signature: self.signature.clone(),
+        }
+    }
+
+    // The file key, when one of the wrapped keys is ours
+    pub fn open(&self, encryption: &Encryption) -> Option<[u8; 32]> {
+        let own = self.recipient().key;
+
+        encryption
+            .keys
+            .iter()
+            .filter(|access| access.key.recipient == own)
+            .filter_map(|access| unwrap_key(&access.key, &self.secret).ok())
+            .next()
+    }
+}
+
+impl Recipient {
+    // fuzzyshare2:<peer>:<key>:<identity>:<signature>
+    pub fn export(&self) -> String {
+        format!(
+            "{}:{}:{}:{}:{}",
+            EXPORT_PREFIX, self.peer, self.key, self.identity, self.signature
+        )
+    }
+
+    // Keys not signed by the identity of their peer are refused
+    pub fn import(text: &str) -> Result<Recipient, CryptoError> {
+        let parts: Vec<&str> = text.trim().split(':').collect();
+
+        match parts.as_slice() {
+            [prefix, peer, key, identity, signature] if *prefix == EXPORT_PREFIX => {
+                decode_array(key)?;
+
+                let public = IdentityKey::from_protobuf_encoding(&decode(identity)?)
+                    .map_err(|_| CryptoError::FormatError)?;
+
+                if PeerId::from_public_key(public.clone()).to_base58() != *peer
+                    || !public.verify(&signed(key), &decode(signature)?)
+                {
+                    return Err(CryptoError::KeyError);
+                }
+
+                Ok(Recipient {
+                    peer: (*peer).to_owned(),
+                    key: (*key).to_owned(),
+                    identity: (*identity).to_owned(),
+                    signature: (*signature).to_owned(),
+                })
+            }
+            _ => Err(CryptoError::FormatError),
+        }
+    }
+}
+
+impl Encryption {
+    pub fn new(key: &[u8; 32], recipients: &[Recipient]) -> Result<Encryption, CryptoError> {
+        let mut keys = Vec::new();
+
+        for recipient in recipients {
+            let public = PublicKey::from(decode_array(&recipient.key)?);
+
+            keys.push(Access {
+                peer: recipient.peer.clone(),
+                key: wrap_key(key, &public)?,
+            });
+        }
+
+        Ok(Encryption { keys })
+    }
+
+    pub fn peers(&self) -> Vec<String> {
+        self.keys.iter().map(|access| access.peer.clone()).collect()
+    }
+}
+
+// Exported keys the next file is encrypted for, separated by commas or spaces; ours is added
+// so the file stays readable here. No key at all shares the file publicly, the key that
+// can't be imported is returned otherwise
+pub fn recipients<'a>(
+    keys: impl Iterator<Item = &'a str>,
+    sharing: Option<&SharingKey>,
+) -> Result<Vec<Recipient>, String> {
+    let mut recipients = Vec::new();
+
+    for key in keys
+        .flat_map(|keys| keys.split(|c: char| c == ',' || c.is_whitespace()))
+        .filter(|key| !key.is_empty())
+    {
+        recipients.push(Recipient::import(key).map_err(|_| key.to_owned())?);
+    }
+
+    if let (Some(sharing), false) = (sharing, recipients.is_empty()) {
+        let own = sharing.recipient();
+
+        if !recipients.contains(&own) {
+            recipients.push(own);
+        }
+    }
+
+    Ok(recipients)
+}
+
+// Every chunk is sealed on its own, its index in the file is the nonce;
+// the key is never reused across files so the nonces never repeat
+fn chunk_nonce(index: u64) -> [u8; NONCE_LEN] {
+    let mut nonce = [0u8; NONCE_LEN];
+    nonce[NONCE_LEN - 8..].copy_from_slice(&index.to_be_bytes());
+    nonce
+}
+
+pub fn seal_chunk(key: &[u8; 32], index: u64, data: &[u8]) -> Result<Vec<u8>, CryptoError> {
+    seal(key, chunk_nonce(index), data)
+}
+
+pub fn open_chunk(key: &[u8; 32], index: u64, data: &[u8]) -> Result<Vec<u8>, CryptoError> {
+    open(key, chunk_nonce(index), data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sharing_key() -> SharingKey {
+        SharingKey::new(&Keypair::generate_ed25519(), random_key().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn only_recipients_open_sealed_chunks() {
+        let (alice, bob, carol) = (sharing_key(), sharing_key(), sharing_key());
+        let key = random_key().unwrap();
+
+        let encryption = Encryption::new(&key, &[alice.recipient(), bob.recipient()]).unwrap();
+        assert_eq!(
+            encryption.peers(),
+            vec![alice.peer.clone(), bob.peer.clone()]
+        );
+
+        let sealed = seal_chunk(&key, 3, b"private").unwrap();
+        assert_eq!(sealed.len(), b"private".len() + TAG_LEN);
+
+        let opened = bob.open(&encryption).unwrap();
+        assert_eq!(open_chunk(&opened, 3, &sealed).unwrap(), b"private");
+        assert_eq!(alice.open(&encryption), Some(key));
+        assert_eq!(carol.open(&encryption), None);
+
+        // every chunk has its own nonce
+        assert!(open_chunk(&key, 4, &sealed).is_err());
+    }
+
+    #[test]
+    fn tampered_chunks_are_rejected() {
+        let key = random_key().unwrap();
+        let sealed = seal_chunk(&key, 0, b"private").unwrap();
+
+        let mut tampered = sealed.clone();
+        tampered[0] ^= 1;
+        assert!(open_chunk(&key, 0, &tampered).is_err());
+
+        let mut tag = sealed.clone();
+        let last = tag.len() - 1;
+        tag[last] ^= 1;
+        assert!(open_chunk(&key, 0, &tag).is_err());
+
+        assert!(open_chunk(&key, 0, &sealed[..sealed.len() - 1]).is_err());
+        assert!(open_chunk(&random_key().unwrap(), 0, &sealed).is_err());
+    }
+
+    #[test]
+    fn exported_keys_are_imported_back() {
+        let alice = sharing_key();
+        let exported = alice.recipient().export();
+
+        assert_eq!(
+            Recipient::import(&format!("  {}\n", exported)).unwrap(),
+            alice.recipient()
+        );
+    }
+
+    #[test]
+    fn malformed_or_forged_keys_are_refused() {
+        let (alice, bob) = (sharing_key(), sharing_key());
+        let own = sharing_key();
+        let exported = alice.recipient().export();
+        let parts: Vec<&str> = exported.split(':').collect();
+        let bob_exported = bob.recipient().export();
+        let bob_parts: Vec<&str> = bob_exported.split(':').collect();
+
+        let malformed = vec![
+            String::from("fuzzyshare2"),
+            // the unsigned keys of the first version
+            format!("fuzzyshare1:{}:{}", parts[1], parts[2]),
+            format!("fuzzyshare3:{}", parts[1..].join(":")),
+            parts[..4].join(":"),
+            format!("{}:extra", exported),
+            // not base58, or not a key
+            format!("{}:{}:0OIl:{}:{}", parts[0], parts[1], parts[3], parts[4]),
+            format!(
+                "{}:{}:{}:{}:{}",
+                parts[0], parts[1], parts[1], parts[3], parts[4]
+            ),
+            format!(
+                "{}:{}:{}:{}:{}",
+                parts[0], parts[1], parts[2], parts[2], parts[4]
+            ),
+            // somebody else's name on alice's key
+            format!("{}:{}:{}", parts[0], bob_parts[1], parts[2..].join(":")),
+            // alice's name and identity on bob's key
+            format!(
+                "{}:{}:{}:{}:{}",
+                parts[0], parts[1], bob_parts[2], parts[3], parts[4]
+            ),
+            // bob's key with alice's signature
+            format!("{}:{}", bob_parts[..4].join(":"), parts[4]),
+        ];
+
+        for key in &malformed {
+            assert!(Recipient::import(key).is_err(), "{} was imported", key);
+            assert_eq!(
+                recipients(
+                    vec![exported.as_str(), key.as_str()].into_iter(),
+                    Some(&own)
+                ),
+                Err(key.clone())
+            );
+        }
+
+        assert_eq!(
+            recipients(vec!["", " , "].into_iter(), Some(&own)),
+            Ok(vec![])
+        );
+        assert_eq!(
+            recipients(vec![exported.as_str()].into_iter(), Some(&own)),
+            Ok(vec![alice.recipient(), own.recipient()])
+        );
+    }
+}
@@ -0,0 +1,981 @@
+use super::sharing::{open_chunk, seal_chunk, Encryption, Recipient, SharingKey, TAG_LEN};
+use ring::digest::{digest, Context, SHA256};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::UNIX_EPOCH;
+
+pub const CHUNK_SIZE: usize = 256 * 1024;
+
+pub const DEFAULT_QUOTA: u64 = 1024 * 1024 * 1024;
+
+// multihash prefix: sha2-256, 32 bytes digest (a CIDv0 once base58 encoded)
+const MULTIHASH_SHA2_256: [u8; 2] = [0x12, 0x20];
+
+#[derive(Debug, Clone)]
+pub enum StoreError {
+    IoError,
+    NotFound(String),
+    Corrupted(String),
+    FormatError,
+    KeyError,
+    NoAccess,
+    // the destination of a file is already taken
+    Exists,
+}
+
+pub fn cid(data: &[u8]) -> String {
+    let hash = digest(&SHA256, data);
+
+    let mut multihash = MULTIHASH_SHA2_256.to_vec();
+    multihash.extend_from_slice(hash.as_ref());
+
+    bs58::encode(multihash).into_string()
+}
+
+pub fn is_cid(text: &str) -> bool {
+    match bs58::decode(text).into_vec() {
+        Ok(bytes) => bytes.len() == 34 && bytes[..2] == MULTIHASH_SHA2_256,
+        Err(_) => false,
+    }
+}
+
+// A file is stored as its chunks plus this manifest, itself stored as a block
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Manifest {
+    pub name: String,
+    pub size: u64,
+    pub chunk_size: usize,
+    pub chunks: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encryption: Option<Encryption>,
+}
+
+impl Manifest {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        serde_json::to_vec(self).unwrap_or_default()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Manifest, StoreError> {
+        let manifest: Manifest =
+            serde_json::from_slice(bytes).map_err(|_| StoreError::FormatError)?;
+
+        // manifests come from other peers too
+        if !manifest.chunks.iter().all(|chunk| is_cid(chunk)) {
+            return Err(StoreError::FormatError);
+        }
+
+        Ok(manifest)
+    }
+}
+
+// A directory lists its entries sorted by name, so that the same tree always gets the same cid
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Directory {
+    pub name: String,
+    pub entries: Vec<DirEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DirEntry {
+    pub name: String,
+    pub directory: bool,
+    pub cid: String,
+    pub size: u64,
+}
+
+impl Directory {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        serde_json::to_vec(self).unwrap_or_default()
+    }
+
+    pub fn size(&self) -> u64 {
+        self.entries.iter().map(|entry| entry.size).sum()
+    }
+}
+
+// What a root block describes; a directory has `entries` and a file has `chunks`,
+// which tells them apart
+#[derive(Debug, Clone)]
+pub enum Root {
+    File(Manifest),
+    Directory(Directory),
+}
+
+impl Root {
+    pub fn from_bytes(bytes: &[u8]) -> Result<Root, StoreError> {
+        if let Ok(directory) = serde_json::from_slice::<Directory>(bytes) {
+            // so do directories, an entry that isn't a cid makes the whole one invalid
+            if !directory.entries.iter().all(|entry| is_cid(&entry.cid)) {
+                return Err(StoreError::FormatError);
+            }
+
+            return Ok(Root::Directory(directory));
+        }
+
+        Manifest::from_bytes(bytes).map(Root::File)
+    }
+}
+
+// What is known of a tree being downloaded: the blocks still needed to know its shape,
+// and the chunks of every file found so far
+#[derive(Debug, Clone, Default)]
+pub struct Tree {
+    pub name: Option<String>,
+    pub size: u64,
+    pub directory: bool,
+    pub missing: Vec<String>,
+    pub chunks: Vec<String>,
+}
+
+// What `add_path` stored: the root block, the size of the content and its SHA-256,
+// a directory's being its root
+#[derive(Debug, Clone)]
+pub struct Added {
+    pub root: String,
+    pub size: u64,
+    pub hash: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GcStats {
+    pub removed: usize,
+    pub freed: u64,
+}
+
+// Disk used by the store: blocks of our own files, of pinned files, and everything else
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Usage {
+    pub own: u64,
+    pub pinned: u64,
+    pub cached: u64,
+    pub quota: u64,
+}
+
+impl Usage {
+    pub fn total(&self) -> u64 {
+        self.own + self.pinned + self.cached
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Config {
+    quota: u64,
+}
+
+// Blocks live in `<root>/blocks`; the manifests of our own files are listed in
+// `<root>/roots.json` and the pinned ones in `<root>/pins.json`, nothing else is
+// safe from eviction. `used` remembers when each block was last read or written
+#[derive(Debug, Clone)]
+pub struct BlockStore {
+    root: PathBuf,
+    used: Arc<Mutex<HashMap<String, u64>>>,
+}
+
+impl Default for BlockStore {
+    fn default() -> Self {
+        BlockStore::open_default()
+    }
+}
+
+impl BlockStore {
+    pub fn new(root: PathBuf) -> BlockStore {
+        let used = std::fs::read(root.join("used.json"))
+            .ok()
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .unwrap_or_default();
+
+        BlockStore {
+            root,
+            used: Arc::new(Mutex::new(used)),
+        }
+    }
+
+    pub fn open_default() -> BlockStore {
+        let root =
+            if let Some(project_dirs) = directories::ProjectDirs::from("rs", "Iced", "FuzzyNet") {
+                project_dirs.data_dir().join("store")
+            } else {
+                std::env::current_dir()
+                    .unwrap_or(PathBuf::new())
+                    .join("store")
+            };
+
+        BlockStore::new(root)
+    }
+
+    // Cids come from other peers too, anything else never gets a path
+    fn block_path(&self, cid: &str) -> Option<PathBuf> {
+        if !is_cid(cid) {
+            return None;
+        }
+
+        // shard on the next-to-last characters, the prefix is the same for every cid;
+        // a cid is plain base58, slicing it is safe
+        let shard = &cid[cid.len() - 3..cid.len() - 1];
+
+        Some(self.root.join("blocks").join(shard).join(cid))
+    }
+
+    fn write_atomic(path: &Path, data: &[u8]) -> Result<(), StoreError> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).map_err(|_| StoreError::IoError)?;
+        }
+
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, data).map_err(|_| StoreError::IoError)?;
+        std::fs::rename(&tmp, path).map_err(|_| StoreError::IoError)
+    }
+
+    pub fn has(&self, cid: &str) -> bool {
+        self.block_path(cid).map_or(false, |path| path.is_file())
+    }
+
+    fn touch(&self, cid: &str) {
+        if let Ok(mut used) = self.used.lock() {
+            used.insert(cid.to_owned(), super::catalog::now());
+        }
+    }
+
+    pub fn put(&self, data: &[u8]) -> Result<String, StoreError> {
+        let cid = cid(data);
+        let path = self.block_path(&cid).ok_or(StoreError::FormatError)?;
+
+        if !path.is_file() {
+            Self::write_atomic(&path, data)?;
+        }
+        self.touch(&cid);
+
+        Ok(cid)
+    }
+
+    // Stores a block received from somewhere else, refusing it if it doesn't match its cid
+    pub fn put_verified(&self, expected: &str, data: &[u8]) -> Result<(), StoreError> {
+        if cid(data) != expected {
+            return Err(StoreError::Corrupted(expected.to_owned()));
+        }
+
+        self.put(data).map(|_| ())
+    }
+
+    pub fn get(&self, cid: &str) -> Result<Vec<u8>, StoreError> {
+        let data = self
+            .block_path(cid)
+            .and_then(|path| std::fs::read(path).ok())
+            .ok_or_else(|| StoreError::NotFound(cid.to_owned()))?;
+
+        if self::cid(&data) != cid {
+            return Err(StoreError::Corrupted(cid.to_owned()));
+        }
+        self.touch(cid);
+
+        Ok(data)
+    }
+
+    pub fn manifest(&self, cid: &str) -> Result<Manifest, StoreError> {
+        Manifest::from_bytes(&self.get(cid)?)
+    }
+
+    pub fn root(&self, cid: &str) -> Result<Root, StoreError> {
+        Root::from_bytes(&self.get(cid)?)
+    }
+
+    pub fn directory(&self, cid: &str) -> Result<Directory, StoreError> {
+        match self.root(cid)? {
+            Root::Directory(directory) => Ok(directory),
+            Root::File(_) => Err(StoreError::FormatError),
+        }
+    }
+
+    // Adds a file or a whole directory and keeps it as one of our own roots;
+    // with recipients, every file is encrypted for them
+    pub fn add_path(&self, path: &Path, recipients: &[Recipient]) -> Result<Added, StoreError> {
+        let added = self.add_node(path, recipients)?;
+        self.add_root(&added.root)?;
+
+        Ok(added)
+    }
+
+    fn add_node(&self, path: &Path, recipients: &[Recipient]) -> Result<Added, StoreError> {
+        let metadata = std::fs::symlink_metadata(path).map_err(|_| StoreError::IoError)?;
+
+        if metadata.is_dir() {
+            self.add_directory(path, recipients)
+        } else if recipients.is_empty() {
+            self.add_file(path, None)
+        } else {
+            // every file gets its own key
+            let key = super::crypto::random_key().map_err(|_| StoreError::KeyError)?;
+            let encryption = Encryption::new(&key, recipients).map_err(|_| StoreError::KeyError)?;
+
+            self.add_file(path, Some((&key, encryption)))
+        }
+    }
+
+    fn add_directory(&self, path: &Path, recipients: &[Recipient]) -> Result<Added, StoreError> {
+        let mut entries = Vec::new();
+
+        for child in std::fs::read_dir(path).map_err(|_| StoreError::IoError)? {
+            let child = child.map_err(|_| StoreError::IoError)?;
+            let metadata =
+                std::fs::symlink_metadata(child.path()).map_err(|_| StoreError::IoError)?;
+
+            // symlinks and special files are left out
+            if !metadata.is_dir() && !metadata.is_file() {
+                continue;
+            }
+
+            let added = self.add_node(&child.path(), recipients)?;
+            entries.push(DirEntry {
+                name: child.file_name().to_string_lossy().into_owned(),
+                directory: metadata.is_dir(),
+                cid: added.root,
+                size: added.size,
+            });
+        }
+
+        // the filesystem lists entries in any order, the manifest must not
+        entries.sort_by(|a, b| a.name.cmp(&b.name));
+
+        let directory = Directory {
+            name: file_name(path),
+            entries,
+        };
+
+        let root = self.put(&directory.to_bytes())?;
+
+        Ok(Added {
+            hash: root.clone(),
+            root,
+            size: directory.size(),
+        })
+    }
+
+    fn add_file(
+        &self,
+        path: &Path,
+        encryption: Option<(&[u8; 32], Encryption)>,
+    ) -> Result<Added, StoreError> {
+        let mut file = std::fs::File::open(path).map_err(|_| StoreError::IoError)?;
+        let mut chunks = Vec::new();
+        let mut size = 0;
+        let mut hash = Context::new(&SHA256);
+
+        // sealed chunks grow by their tag, they still fit in a block
+        let mut buffer = match encryption {
+            Some(_) => vec![0u8; CHUNK_SIZE - TAG_LEN],
+            None => vec![0u8; CHUNK_SIZE],
+        };
+
+        loop {
+            let read = read_chunk(&mut file, &mut buffer)?;
+            if read == 0 {
+                break;
+            }
+            hash.update(&buffer[..read]);
+
+            let cid = match &encryption {
+                Some((key, _)) => {
+                    let sealed = seal_chunk(key, chunks.len() as u64, &buffer[..read])
+                        .map_err(|_| StoreError::KeyError)?;
+                    self.put(&sealed)?
+                }
+                None => self.put(&buffer[..read])?,
+            };
+
+            chunks.push(cid);
+            size += read as u64;
+        }
+
+        let manifest = Manifest {
+            name: file_name(path),
+            size,
+            chunk_size: buffer.len(),
+            chunks,
+            encryption: encryption.map(|(_, encryption)| encryption),
+        };
+
+        Ok(Added {
+            root: self.put(&manifest.to_bytes())?,
+            size,
+            hash: super::catalog::to_hex(hash.finish().as_ref()),
+        })
+    }
+
+    // Follows a tree as far as the store allows
+    pub fn walk(&self, root: &str) -> Tree {
+        let mut tree = Tree::default();
+        let mut stack = vec![root.to_owned()];
+
+        while let Some(cid) = stack.pop() {
+            let top = cid == root;
+
+            match self.root(&cid) {
+                Ok(Root::File(manifest)) => {
+                    if top {
+                        tree.name = Some(manifest.name);
+                        tree.size = manifest.size;
+                    }
+                    tree.chunks.extend(manifest.chunks);
+                }
+                Ok(Root::Directory(directory)) => {
+                    if top {
+                        tree.size = directory.size();
+                        tree.name = Some(directory.name);
+                        tree.directory = true;
+                    }
+                    stack.extend(directory.entries.into_iter().map(|entry| entry.cid));
+                }
+                Err(_) => tree.missing.push(cid),
+            }
+        }
+
+        tree
+    }
+
+    // Recreates a file or a directory tree at `destination`, which must not exist yet
+    pub fn read_tree(
+        &self,
+        root: &str,
+        destination: &Path,
+        sharing: Option<&SharingKey>,
+    ) -> Result<(), StoreError> {
+        match self.root(root)? {
+            Root::File(_) => self.read_file(root, destination, sharing),
+            Root::Directory(directory) => {
+                if let Some(dir) = destination.parent() {
+                    std::fs::create_dir_all(dir).map_err(|_| StoreError::IoError)?;
+                }
+                // never merged into what is there, nor a name given twice
+                std::fs::create_dir(destination).map_err(|error| match error.kind() {
+                    std::io::ErrorKind::AlreadyExists => StoreError::Exists,
+                    _ => StoreError::IoError,
+                })?;
+
+                for entry in &directory.entries {
+                    // the names come from another peer, none may lead out of the directory
+                    if !is_plain_name(&entry.name) {
+                        return Err(StoreError::FormatError);
+                    }
+
+                    self.read_tree(&entry.cid, &destination.join(&entry.name), sharing)?;
+                }
+
+                Ok(())
+            }
+        }
+    }
+
+    // Reassembles a file, every chunk is checked against its cid on the way;
+    // private files are decrypted when the sharing key is one of their recipients.
+    // An existing file is never replaced
+    pub fn read_file(
+        &self,
+        root: &str,
+        destination: &Path,
+        sharing: Option<&SharingKey>,
+    ) -> Result<(), StoreError> {
+        let manifest = self.manifest(root)?;
+
+        let key = match &manifest.encryption {
+            Some(encryption) => Some(
+                sharing
+                    .and_then(|sharing| sharing.open(encryption))
+                    .ok_or(StoreError::NoAccess)?,
+            ),
+            None => None,
+        };
+
+        if exists(destination) {
+            return Err(StoreError::Exists);
+        }
+        if let Some(dir) = destination.parent() {
+            std::fs::create_dir_all(dir).map_err(|_| StoreError::IoError)?;
+        }
+
+        let tmp = part_path(destination);
+        {
+            let mut file = std::fs::File::create(&tmp).map_err(|_| StoreError::IoError)?;
+
+            for (index, chunk) in manifest.chunks.iter().enumerate() {
+                let data = match &key {
+                    Some(key) => open_chunk(key, index as u64, &self.get(chunk)?)
+                        .map_err(|_| StoreError::Corrupted(chunk.clone()))?,
+                    None => self.get(chunk)?,
+                };
+
+                file.write_all(&data).map_err(|_| StoreError::IoError)?;
+            }
+        }
+
+        // it may have been created meanwhile
+        if exists(destination) {
+            let _ = std::fs::remove_file(&tmp);
+            return Err(StoreError::Exists);
+        }
+
+        std::fs::rename(&tmp, destination).map_err(|_| StoreError::IoError)
+    }
+
+    fn read_list(&self, file: &str) -> Vec<String> {
+        std::fs::read(self.root.join(file))
+            .ok()
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .unwrap_or_default()
+    }
+
+    fn write_list(&self, file: &str, list: &[String]) -> Result<(), StoreError> {
+        let json = serde_json::to_vec_pretty(list).map_err(|_| StoreError::FormatError)?;
+
+        Self::write_atomic(&self.root.join(file), &json)
+    }
+
+    fn add_to_list(&self, file: &str, cid: &str) -> Result<(), StoreError> {
+        let mut list = self.read_list(file);
+
+        if !list.iter().any(|known| known == cid) {
+            list.push(cid.to_owned());
+            self.write_list(file, &list)?;
+        }
+
+        Ok(())
+    }
+
+    fn remove_from_list(&self, file: &str, cid: &str) -> Result<(), StoreError> {
+        let mut list = self.read_list(file);
+        list.retain(|known| known != cid);
+
+        self.write_list(file, &list)
+    }
+
+    pub fn roots(&self) -> Vec<String> {
+        self.read_list("roots.json")
+    }
+
+    pub fn add_root(&self, cid: &str) -> Result<(), StoreError> {
+        self.add_to_list("roots.json", cid)
+    }
+
+    pub fn remove_root(&self, cid: &str) -> Result<(), StoreError> {
+        self.remove_from_list("roots.json", cid)
+    }
+
+    pub fn pins(&self) -> Vec<String> {
+        self.read_list("pins.json")
+    }
+
+    pub fn pin(&self, cid: &str) -> Result<(), StoreError> {
+        self.add_to_list("pins.json", cid)
+    }
+
+    pub fn unpin(&self, cid: &str) -> Result<(), StoreError> {
+        self.remove_from_list("pins.json", cid)
+    }
+
+    pub fn quota(&self) -> u64 {
+        std::fs::read(self.root.join("config.json"))
+            .ok()
+            .and_then(|bytes| serde_json::from_slice::<Config>(&bytes).ok())
+            .map(|config| config.quota)
+            .unwrap_or(DEFAULT_QUOTA)
+    }
+
+    pub fn set_quota(&self, quota: u64) -> Result<(), StoreError> {
+        let json =
+            serde_json::to_vec_pretty(&Config { quota }).map_err(|_| StoreError::FormatError)?;
+
+        Self::write_atomic(&self.root.join("config.json"), &json)
+    }
+
+    // Every block with its size and the time it was last used
+    fn blocks(&self) -> Vec<(String, PathBuf, u64, u64)> {
+        let mut blocks = Vec::new();
+
+        let shards = match std::fs::read_dir(self.root.join("blocks")) {
+            Ok(shards) => shards,
+            Err(_) => return blocks,
+        };
+        let used = self
+            .used
+            .lock()
+            .map(|used| used.clone())
+            .unwrap_or_default();
+
+        for shard in shards.filter_map(Result::ok) {
+            if let Ok(files) = std::fs::read_dir(shard.path()) {
+                for file in files.filter_map(Result::ok) {
+                    let name = file.file_name().to_string_lossy().into_owned();
+
+                    if !is_cid(&name) {
+                        continue;
+                    }
+
+                    let metadata = file.metadata().ok();
+                    let size = metadata.as_ref().map(|m| m.len()).unwrap_or(0);
+                    // blocks never used since the app started count from when they were written
+                    let last_used = used.get(&name).cloned().unwrap_or_else(|| {
+                        metadata
+                            .and_then(|m| m.modified().ok())
+                            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                            .map(|duration| duration.as_secs())
+                            .unwrap_or(0)
+                    });
+
+                    blocks.push((name, file.path(), size, last_used));
+                }
+            }
+        }
+
+        blocks
+    }
+
+    // Every block reachable from the given manifests; manifests that can't be read keep
+    // nothing but themselves alive
+    fn reachable(&self, roots: Vec<String>) -> HashSet<String> {
+        let mut reachable = HashSet::new();
+        let mut stack = roots;
+
+        while let Some(cid) = stack.pop() {
+            if !reachable.insert(cid.clone()) {
+                continue;
+            }
+
+            match self.root(&cid) {
+                Ok(Root::File(manifest)) => reachable.extend(manifest.chunks),
+                Ok(Root::Directory(directory)) => {
+                    stack.extend(directory.entries.into_iter().map(|entry| entry.cid))
+                }
+                Err(_) => {}
+            }
+        }
+
+        reachable
+    }
+
+    fn protected(&self) -> HashSet<String> {
+        let mut protected = self.reachable(self.roots());
+        protected.extend(self.reachable(self.pins()));
+
+        protected
+    }
+
+    pub fn usage(&self) -> Usage {
+        let own = self.reachable(self.roots());
+        let pinned = self.reachable(self.pins());
+        let mut usage = Usage {
+            quota: self.quota(),
+            ..Usage::default()
+        };
+
+        for (cid, _, size, _) in self.blocks() {
+            if own.contains(&cid) {
+                usage.own += size;
+            } else if pinned.contains(&cid) {
+                usage.pinned += size;
+            } else {
+                usage.cached += size;
+            }
+        }
+
+        usage
+    }
+
+    fn remove_block(&self, path: &Path, cid: &str) -> Result<(), StoreError> {
+        std::fs::remove_file(path).map_err(|_| StoreError::IoError)?;
+
+        if let Ok(mut used) = self.used.lock() {
+            used.remove(cid);
+        }
+
+        Ok(())
+    }
+
+    fn save_used(&self) -> Result<(), StoreError> {
+        let used = self
+            .used
+            .lock()
+            .map(|used| used.clone())
+            .unwrap_or_default();
+        let json = serde_json::to_vec(&used).map_err(|_| StoreError::FormatError)?;
+
+        Self::write_atomic(&self.root.join("used.json"), &json)
+    }
+
+    // Drops the whole cache, only own and pinned files are left
+    pub fn gc(&self) -> Result<GcStats, StoreError> {
+        let protected = self.protected();
+        let mut stats = GcStats::default();
+
+        for (cid, path, size, _) in self.blocks() {
+            if !protected.contains(&cid) {
+                self.remove_block(&path, &cid)?;
+                stats.removed += 1;
+                stats.freed += size;
+            }
+        }
+
+        self.save_used()?;
+
+        Ok(stats)
+    }
+
+    // Evicts cached blocks, least recently used first, until the store fits in its quota;
+    // blocks of own and pinned files are never evicted, even above the quota
+    pub fn evict(&self) -> Result<GcStats, StoreError> {
+        let quota = self.quota();
+        let protected = self.protected();
+        let mut blocks = self.blocks();
+        let mut total: u64 = blocks.iter().map(|(_, _, size, _)| size).sum();
+        let mut stats = GcStats::default();
+
+        blocks.retain(|(cid, _, _, _)| !protected.contains(cid));
+        blocks.sort_by_key(|(_, _, _, last_used)| *last_used);
+
+        for (cid, path, size, _) in blocks {
+            if total <= quota {
+                break;
+            }
+
+            self.remove_block(&path, &cid)?;
+            total -= size;
+            stats.removed += 1;
+            stats.freed += size;
+        }
+
+        self.save_used()?;
+
+        Ok(stats)
+    }
+}
+
+pub async fn usage(store: BlockStore) -> Usage {
+    store.usage()
+}
+
+pub async fn evict(store: BlockStore) -> Result<GcStats, StoreError> {
+    store.evict()
+}
+
+pub async fn clear_cache(store: BlockStore) -> Result<GcStats, StoreError> {
+    store.gc()
+}
+
+// Where a file is written before it is complete
+pub fn part_path(destination: &Path) -> PathBuf {
+    let mut name = destination.as_os_str().to_owned();
+    name.push(".part");
+
+    PathBuf::from(name)
+}
+
+// Broken links count, they would be followed
+pub fn exists(path: &Path) -> bool {
+    std::fs::symlink_metadata(path).is_ok()
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+fn is_plain_name(name: &str) -> bool {
+    !name.is_empty() && name != "." && name != ".." && !name.contains('/') && !name.contains('\\')
+}
+
+// Fills the buffer unless the end of the file comes first
+fn read_chunk(file: &mut std::fs::File, buffer: &mut [u8]) -> Result<usize, StoreError> {
+    let mut filled = 0;
+
+    while filled < buffer.len() {
+        let read = file
+            .read(&mut buffer[filled..])
+            .map_err(|_| StoreError::IoError)?;
+        if read == 0 {
+            break;
+        }
+        filled += read;
+    }
+
+    Ok(filled)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::catalog::to_hex;
+    use super::*;
+
+    // An empty directory of its own for every test
+    fn scratch(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("fuzzynet-store-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        dir
+    }
+
+    fn content(size: usize) -> Vec<u8> {
+        (0..size).map(|i| (i * 31 % 251) as u8).collect()
+    }
+
+    #[test]
+    fn files_are_read_back_as_added() {
+        let dir = scratch("round-trip");
+        let store = BlockStore::new(dir.join("store"));
+        let data = content(CHUNK_SIZE * 2 + CHUNK_SIZE / 2);
+        std::fs::write(dir.join("file.bin"), &data).unwrap();
+
+        let added = store.add_path(&dir.join("file.bin"), &[]).unwrap();
+        assert_eq!(added.size, data.len() as u64);
+        assert_eq!(added.hash, to_hex(digest(&SHA256, &data).as_ref()));
+        assert_eq!(store.roots(), vec![added.root.clone()]);
+
+        let manifest = store.manifest(&added.root).unwrap();
+        assert_eq!(manifest.name, "file.bin");
+        assert_eq!(manifest.chunks.len(), 3);
+
+        store
+            .read_file(&added.root, &dir.join("copy.bin"), None)
+            .unwrap();
+        assert_eq!(std::fs::read(dir.join("copy.bin")).unwrap(), data);
+
+        // nothing is replaced
+        std::fs::write(dir.join("copy.bin"), b"mine").unwrap();
+        match store.read_file(&added.root, &dir.join("copy.bin"), None) {
+            Err(StoreError::Exists) => {}
+            result => panic!("replaced a file: {:?}", result),
+        }
+        assert_eq!(std::fs::read(dir.join("copy.bin")).unwrap(), b"mine");
+        assert!(!dir.join("copy.bin.part").exists());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn corrupted_blocks_are_not_read() {
+        let dir = scratch("corrupted");
+        let store = BlockStore::new(dir.join("store"));
+        std::fs::write(dir.join("file.bin"), content(CHUNK_SIZE + 1)).unwrap();
+
+        let added = store.add_path(&dir.join("file.bin"), &[]).unwrap();
+        let chunk = store.manifest(&added.root).unwrap().chunks[1].clone();
+        std::fs::write(store.block_path(&chunk).unwrap(), b"something else").unwrap();
+
+        match store.get(&chunk) {
+            Err(StoreError::Corrupted(cid)) => assert_eq!(cid, chunk),
+            result => panic!("read a corrupted block: {:?}", result),
+        }
+        match store.read_file(&added.root, &dir.join("copy.bin"), None) {
+            Err(StoreError::Corrupted(cid)) => assert_eq!(cid, chunk),
+            result => panic!("read a corrupted file: {:?}", result),
+        }
+        assert!(!dir.join("copy.bin").exists());
+
+        match store.put_verified(&chunk, b"something else") {
+            Err(StoreError::Corrupted(_)) => {}
+            result => panic!("stored a corrupted block: {:?}", result),
+        }
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn gc_keeps_only_own_and_pinned_files() {
+        let dir = scratch("gc");
+        let store = BlockStore::new(dir.join("store"));
+        std::fs::write(dir.join("own.bin"), content(CHUNK_SIZE + 1)).unwrap();
+        std::fs::create_dir_all(dir.join("pinned/inner")).unwrap();
+        std::fs::write(dir.join("pinned/inner/file.bin"), b"pinned").unwrap();
+
+        let own = store.add_path(&dir.join("own.bin"), &[]).unwrap();
+        let pinned = store.add_path(&dir.join("pinned"), &[]).unwrap();
+        store.remove_root(&pinned.root).unwrap();
+        store.pin(&pinned.root).unwrap();
+        let cached = store.put(b"cached").unwrap();
+
+        let stats = store.gc().unwrap();
+        assert_eq!(stats.removed, 1);
+        assert_eq!(stats.freed, b"cached".len() as u64);
+        assert!(!store.has(&cached));
+
+        for root in &[own.root, pinned.root] {
+            let tree = store.walk(root);
+            assert!(tree.missing.is_empty());
+            assert!(tree.chunks.iter().all(|chunk| store.has(chunk)));
+        }
+
+        let usage = store.usage();
+        assert_eq!(usage.cached, 0);
+        assert!(usage.own > 0 && usage.pinned > 0);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn eviction_keeps_pinned_trees_over_the_quota() {
+        let dir = scratch("evict");
+        let store = BlockStore::new(dir.join("store"));
+        std::fs::create_dir_all(dir.join("pinned/inner")).unwrap();
+        std::fs::write(dir.join("pinned/inner/file.bin"), content(CHUNK_SIZE + 1)).unwrap();
+        std::fs::write(dir.join("pinned/file.bin"), b"pinned").unwrap();
+        std::fs::write(dir.join("own.bin"), b"own").unwrap();
+
+        let pinned = store.add_path(&dir.join("pinned"), &[]).unwrap();
+        store.remove_root(&pinned.root).unwrap();
+        store.pin(&pinned.root).unwrap();
+        let own = store.add_path(&dir.join("own.bin"), &[]).unwrap();
+
+        let older = store.put(b"older").unwrap();
+        let newer = store.put(b"newer").unwrap();
+        {
+            let mut used = store.used.lock().unwrap();
+            used.insert(older.clone(), 1);
+            used.insert(newer.clone(), 2);
+        }
+
+        // room for one cached block, the least recently used one goes
+        let usage = store.usage();
+        store
+            .set_quota(usage.own + usage.pinned + b"newer".len() as u64)
+            .unwrap();
+
+        let stats = store.evict().unwrap();
+        assert_eq!(stats.removed, 1);
+        assert!(!store.has(&older));
+        assert!(store.has(&newer));
+
+        // no room at all, only the cache goes
+        store.set_quota(1).unwrap();
+
+        let stats = store.evict().unwrap();
+        assert_eq!(stats.removed, 1);
+        assert!(!store.has(&newer));
+
+        let tree = store.walk(&pinned.root);
+        assert!(tree.directory);
+        assert!(tree.missing.is_empty());
+        assert_eq!(tree.chunks.len(), 3);
+        assert!(tree.chunks.iter().all(|chunk| store.has(chunk)));
+        assert!(store.has(&pinned.root));
+        assert!(store.has(&own.root));
+
+        store
+            .read_tree(&pinned.root, &dir.join("copy"), None)
+            .unwrap();
+        assert_eq!(
+            std::fs::read(dir.join("copy/inner/file.bin")).unwrap(),
+            content(CHUNK_SIZE + 1)
+        );
+
+        // nor merged into an existing directory
+        match store.read_tree(&pinned.root, &dir.join("copy"), None) {
+            Err(StoreError::Exists) => {}
+            result => panic!("merged a tree: {:?}", result),
+        }
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -1,12 +1,11 @@
 use iced::{button, Background, Color, Font, HorizontalAlignment, Length, Text, Vector};
-use serde::{Deserialize, Serialize};
 // Fonts - Icons
 const ICONS: Font = Font::External {
     name: "Icons",
     bytes: include_bytes!("../../resources/fuzzynet.png"),
 };
 
-fn icon(unicode: char) -> Text {
+pub fn icon(unicode: char) -> Text {
     Text::new(&unicode.to_string())
         .font(ICONS)
         .width(Length::Units(20))
@@ -22,19 +21,6 @@ fn icon(unicode: char) -> Text {
 //     icon('\u{F1F8}')
 // }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
-pub enum Filter {
-    All,
-    Active,
-    Completed,
-}
-
-impl Default for Filter {
-    fn default() -> Self {
-        Filter::All
-    }
-}
-
 pub enum Button {
     Filter { selected: bool },
     Icon,
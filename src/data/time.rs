@@ -0,0 +1,43 @@
+use std::time::{Duration, Instant};
+
+// Fires every `duration`, for as long as the subscription is kept
+pub fn every(duration: Duration) -> iced::Subscription<Instant> {
+    iced::Subscription::from_recipe(Every(duration))
+}
+
+struct Every(Duration);
+
+impl<H, I> iced_native::subscription::Recipe<H, I> for Every
+where
+    H: std::hash::Hasher,
+{
+    type Output = Instant;
+
+    fn hash(&self, state: &mut H) {
+        use std::hash::Hash;
+
+        std::any::TypeId::of::<Self>().hash(state);
+        self.0.hash(state);
+    }
+
+    fn stream(
+        self: Box<Self>,
+        _input: futures::stream::BoxStream<'static, I>,
+    ) -> futures::stream::BoxStream<'static, Self::Output> {
+        use futures::stream::StreamExt;
+
+        let (sender, receiver) = futures::channel::mpsc::unbounded();
+        let duration = self.0;
+
+        // the thread stops once the subscription is dropped and the receiver with it
+        std::thread::spawn(move || loop {
+            std::thread::sleep(duration);
+
+            if sender.unbounded_send(Instant::now()).is_err() {
+                break;
+            }
+        });
+
+        receiver.boxed()
+    }
+}
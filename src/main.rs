@@ -1,9 +1,13 @@
 use iced::{
-    button, scrollable, Button, Checkbox, Color, Column, Container, Element, HorizontalAlignment,
-    Length, Row, Sandbox, Scrollable, Settings, Space, Text, VerticalAlignment,
+    button, scrollable, text_input, Button, Checkbox, Color, Column, Container, Element,
+    HorizontalAlignment, Length, Row, Sandbox, Scrollable, Settings, Space, Text, TextInput,
+    VerticalAlignment,
 };
 
-mod data;
+use calculator::data::crypto::{is_valid_name, PublicKeys, SecretKeys};
+use calculator::data::mail::{Body, Email, Envelope, Keyring, Mailbox, Verification};
+use calculator::data::rules::{Action, Condition, Rule, Rules};
+use calculator::data::styles;
 //use styles;
 
 //use styles;
@@ -127,6 +131,10 @@ impl Steps {
                 sent_button: button::State::new(),
                 spam_button: button::State::new(),
                 trash_button: button::State::new(),
+                compose_button: button::State::new(),
+                keys_button: button::State::new(),
+                rules_button: button::State::new(),
+                mail: Mail::new(),
             }],
             current: 0,
         }
@@ -178,6 +186,10 @@ enum Step {
         sent_button: button::State,
         spam_button: button::State,
         trash_button: button::State,
+        compose_button: button::State,
+        keys_button: button::State,
+        rules_button: button::State,
+        mail: Mail,
     },
 }
 
@@ -188,6 +200,32 @@ pub enum StepMessage {
     LayoutChanged(Layout),
     SpacingChanged(f32),
     NavButtonPressed(String),
+    ComposeToChanged(String),
+    ComposeSubjectChanged(String),
+    ComposeBodyChanged(String),
+    SendPressed,
+    OpenEmail(usize),
+    CloseEmail,
+    ExportEmail,
+    CopyFieldEdited(String),
+    PasteChanged(String),
+    ImportEmail,
+    KeyNameChanged(String),
+    GenerateKeys,
+    KeyImportChanged(String),
+    ImportKey,
+    ToggleSecretKey,
+    SecretImportChanged(String),
+    RestoreKey,
+    RuleNameChanged(String),
+    RuleFromChanged(String),
+    RuleSubjectChanged(String),
+    RuleTagChanged(String),
+    RuleFolderChanged(String),
+    RuleMarkReadToggled(bool),
+    AddRule,
+    DeleteRule(usize),
+    ApplyRules,
 }
 
 impl<'a> Step {
@@ -209,9 +247,12 @@ impl<'a> Step {
                 }
             }
             StepMessage::NavButtonPressed(button) => {
-                if let Step::RowsAndColumns { value, .. } = self {
+                if let Step::RowsAndColumns { value, mail, .. } = self {
                     *value = format!("{}", button);
                     println!("value  LOAD CONTENT ? {} ", value);
+                    mail.reading = None;
+                    // the secret key is shown again only on request
+                    mail.secret_shown = false;
                 }
             }
             StepMessage::InputChanged(new_value) => {
@@ -219,6 +260,19 @@ impl<'a> Step {
                     *value = new_value;
                 }
             }
+            StepMessage::SendPressed => {
+                if let Step::RowsAndColumns {
+                    mail, is_secure, ..
+                } = self
+                {
+                    mail.send(*is_secure);
+                }
+            }
+            mail_msg => {
+                if let Step::RowsAndColumns { mail, .. } = self {
+                    mail.update(mail_msg);
+                }
+            }
         };
     }
 
@@ -247,6 +301,10 @@ impl<'a> Step {
                 sent_button,
                 spam_button,
                 trash_button,
+                compose_button,
+                keys_button,
+                rules_button,
+                mail,
             } => Self::rows_and_columns(
                 *is_secure,
                 *layout,
@@ -258,6 +316,10 @@ impl<'a> Step {
                 sent_button,
                 spam_button,
                 trash_button,
+                compose_button,
+                keys_button,
+                rules_button,
+                mail,
             ),
         }
         .into()
@@ -278,6 +340,10 @@ impl<'a> Step {
         sent_button: &'a mut button::State,
         spam_button: &'a mut button::State,
         trash_button: &'a mut button::State,
+        compose_button: &'a mut button::State,
+        keys_button: &'a mut button::State,
+        rules_button: &'a mut button::State,
+        mail: &'a mut Mail,
     ) -> Column<'a, StepMessage> {
         fn new_button<'a>(state: &'a mut button::State, label: &str) -> Button<'a, StepMessage> {
             Button::new(
@@ -290,73 +356,37 @@ impl<'a> Step {
             .on_press(StepMessage::NavButtonPressed(label.to_owned()))
             .padding(3)
             .min_width(50)
-            .style(data::styles::Button::Icon)
+            .style(styles::Button::Icon)
         }
 
         let nav_list = Column::new()
             .padding(5)
             .spacing(spacing)
+            .push(new_button(compose_button, "compose"))
             .push(new_button(inbox_button, "inbox"))
             .push(new_button(folders_button, "folders"))
             .push(new_button(tags_button, "tags"))
             .push(new_button(sent_button, "sent"))
             .push(new_button(spam_button, "spam"))
-            .push(new_button(trash_button, "trash"));
-
-        // reduced to newColumn
-        fn new_column(data: Vec<&str>, is_secure: bool) -> Column<StepMessage> {
-            Column::new().padding(10).spacing(5).push(data.iter().fold(
-                Column::new().padding(5).spacing(10),
-                |choices, language| {
-                    choices.push(Checkbox::new(
-                        is_secure,
-                        language,
-                        StepMessage::ToggleSecureInput,
-                    ))
-                },
-            ))
-        }
-
-        let email_list = vec![
-            "This is an email message you need to click and read",
-            "This is an email message you need to click and read",
-            "This is an email message you need to click and read",
-            "This is an email message you need to click and read",
-            "This is an email message you need to click and read",
-            "This is an email message you need to click and read",
-        ];
-        let email_row = new_column(email_list, is_secure);
+            .push(new_button(trash_button, "trash"))
+            .push(new_button(keys_button, "keys"))
+            .push(new_button(rules_button, "rules"));
 
-        let folders = vec!["my folder", "your folder", "his folder"];
-        let folder_row = new_column(folders, is_secure);
-
-        let tags = vec!["my tag", "your tag", "his tag"];
-        let tag_row = new_column(tags, is_secure);
-
-        let sents = vec!["my sent", "your sent", "his sent"];
-        let sent_row = new_column(sents, is_secure);
-
-        let spams = vec!["my spams", "your spams", "his spams"];
-        let spam_row = new_column(spams, is_secure);
-
-        let trash = vec!["my trash", "your trash", "his trash"];
-        let trash_row = new_column(trash, is_secure);
+        let section = match layout {
+            Layout::Row => value.as_str(),
+            Layout::Column => "inbox",
+        };
 
         let layout_section: Element<_> = match layout {
             Layout::Row => Row::new()
                 .spacing(5)
                 .push(nav_list)
-                .push(match value.as_str() {
-                    "inbox" => email_row,
-                    "folders" => folder_row,
-                    "tags" => tag_row,
-                    "sent" => sent_row,
-                    "spam" => spam_row,
-                    "trash" => trash_row,
-                    _ => email_row, // default is email row
-                })
+                .push(mail.view(section, is_secure))
+                .into(),
+            Layout::Column => Column::new()
+                .spacing(100)
+                .push(mail.view(section, is_secure))
                 .into(),
-            Layout::Column => Column::new().spacing(100).push(email_row).into(),
         };
 
         Self::container("Inbox View")
@@ -365,6 +395,820 @@ impl<'a> Step {
     }
 }
 
+// Section of a custom folder, followed by the folder name
+const FOLDER_SECTION: &str = "folders/";
+
+// reduced to newColumn
+fn new_column<'a>(data: Vec<String>) -> Column<'a, StepMessage> {
+    Column::new().padding(10).spacing(5).push(
+        data.iter()
+            .fold(Column::new().padding(5).spacing(10), |choices, label| {
+                choices.push(Text::new(label).color(Color::BLACK))
+            }),
+    )
+}
+
+struct Reading {
+    index: usize,
+    text: String,
+    verification: Option<Verification>,
+    // the name on the key that signed the message
+    signer: String,
+}
+
+struct Mail {
+    mailbox: Mailbox,
+    keyring: Keyring,
+    rules: Rules,
+    rule_editor: RuleEditor,
+    reading: Option<Reading>,
+    status: String,
+    to: String,
+    subject: String,
+    body: String,
+    exported: String,
+    paste: String,
+    key_name: String,
+    key_import: String,
+    secret_shown: bool,
+    secret_import: String,
+    to_input: text_input::State,
+    subject_input: text_input::State,
+    body_input: text_input::State,
+    exported_input: text_input::State,
+    paste_input: text_input::State,
+    key_name_input: text_input::State,
+    key_import_input: text_input::State,
+    own_key_input: text_input::State,
+    secret_key_input: text_input::State,
+    secret_import_input: text_input::State,
+    send_button: button::State,
+    close_button: button::State,
+    export_button: button::State,
+    import_email_button: button::State,
+    generate_button: button::State,
+    import_key_button: button::State,
+    secret_button: button::State,
+    restore_button: button::State,
+    // one per custom folder, resized when the folders are listed
+    folder_buttons: Vec<button::State>,
+}
+
+impl Mail {
+    fn new() -> Mail {
+        Mail {
+            mailbox: Mailbox::load(),
+            keyring: Keyring::load(),
+            rules: Rules::load(),
+            rule_editor: RuleEditor::default(),
+            reading: None,
+            status: String::new(),
+            to: String::new(),
+            subject: String::new(),
+            body: String::new(),
+            exported: String::new(),
+            paste: String::new(),
+            key_name: String::new(),
+            key_import: String::new(),
+            secret_shown: false,
+            secret_import: String::new(),
+            to_input: text_input::State::new(),
+            subject_input: text_input::State::new(),
+            body_input: text_input::State::new(),
+            exported_input: text_input::State::new(),
+            paste_input: text_input::State::new(),
+            key_name_input: text_input::State::new(),
+            key_import_input: text_input::State::new(),
+            own_key_input: text_input::State::new(),
+            secret_key_input: text_input::State::new(),
+            secret_import_input: text_input::State::new(),
+            send_button: button::State::new(),
+            close_button: button::State::new(),
+            export_button: button::State::new(),
+            import_email_button: button::State::new(),
+            generate_button: button::State::new(),
+            import_key_button: button::State::new(),
+            secret_button: button::State::new(),
+            restore_button: button::State::new(),
+            folder_buttons: Vec::new(),
+        }
+    }
+
+    fn update(&mut self, msg: StepMessage) {
+        match msg {
+            StepMessage::ComposeToChanged(to) => self.to = to,
+            StepMessage::ComposeSubjectChanged(subject) => self.subject = subject,
+            StepMessage::ComposeBodyChanged(body) => self.body = body,
+            StepMessage::OpenEmail(index) => self.open(index),
+            StepMessage::CloseEmail => {
+                self.reading = None;
+                self.exported.clear();
+            }
+            StepMessage::ExportEmail => {
+                let mailbox = &self.mailbox;
+
+                if let Some(email) = self
+                    .reading
+                    .as_ref()
+                    .and_then(|reading| mailbox.emails.get(reading.index))
+                {
+                    self.exported = serde_json::to_string(email).unwrap_or_default();
+                }
+            }
+            // read-only field, kept editable so the text can be selected and copied
+            StepMessage::CopyFieldEdited(_) => {}
+            StepMessage::PasteChanged(paste) => self.paste = paste,
+            StepMessage::ImportEmail => match serde_json::from_str::<Email>(&self.paste) {
+                Ok(mut email) => {
+                    email.folder = String::from("inbox");
+                    email.read = false;
+                    self.rules.run(&mut email);
+                    self.status = format!("Message imported to {}", email.folder);
+                    self.mailbox.emails.push(email);
+                    self.paste.clear();
+                    self.save_mailbox();
+                }
+                Err(_) => self.status = String::from("This is not an exported message"),
+            },
+            StepMessage::KeyNameChanged(name) => self.key_name = name,
+            StepMessage::GenerateKeys => {
+                if self.key_name.is_empty() {
+                    self.status = String::from("Pick a name for your key first");
+                } else if !is_valid_name(&self.key_name) {
+                    self.status = String::from("A key name can not contain ':'");
+                } else {
+                    match SecretKeys::generate(&self.key_name) {
+                        Ok(keys) => {
+                            self.keyring.own = Some(keys);
+                            self.status = String::from("New key generated");
+                            self.save_keyring();
+                        }
+                        Err(_) => self.status = String::from("Could not generate a key"),
+                    }
+                }
+            }
+            StepMessage::KeyImportChanged(key) => self.key_import = key,
+            StepMessage::ImportKey => match PublicKeys::import(&self.key_import) {
+                Ok(keys) => {
+                    self.status = format!("Imported key of {}", keys.name);
+                    self.keyring.import(keys);
+                    self.key_import.clear();
+                    self.save_keyring();
+                }
+                Err(_) => self.status = String::from("This is not an exported key"),
+            },
+            StepMessage::ToggleSecretKey => self.secret_shown = !self.secret_shown,
+            StepMessage::SecretImportChanged(key) => self.secret_import = key,
+            StepMessage::RestoreKey => match SecretKeys::import(&self.secret_import) {
+                Ok(keys) => {
+                    self.status = format!("Restored the key of {}", keys.name);
+                    self.keyring.own = Some(keys);
+                    self.secret_import.clear();
+                    self.save_keyring();
+                }
+                Err(_) => self.status = String::from("This is not an exported secret key"),
+            },
+            StepMessage::AddRule => match self.rule_editor.rule() {
+                Some(rule) => {
+                    self.rules.rules.push(rule);
+                    self.rule_editor = RuleEditor::default();
+                    self.status = String::from("Rule added");
+                    self.save_rules();
+                }
+                None => {
+                    self.status = String::from("A rule needs a name, a condition and an action")
+                }
+            },
+            StepMessage::DeleteRule(index) => {
+                if index < self.rules.rules.len() {
+                    self.rules.rules.remove(index);
+                    self.save_rules();
+                }
+            }
+            StepMessage::ApplyRules => {
+                let changed = self.rules.run_folder(&mut self.mailbox, "inbox");
+                self.status = format!("Rules matched {} messages in inbox", changed);
+                self.save_mailbox();
+            }
+            rule_msg => self.rule_editor.update(rule_msg),
+        }
+    }
+
+    fn open(&mut self, index: usize) {
+        let email = match self.mailbox.emails.get_mut(index) {
+            Some(email) => email,
+            None => return,
+        };
+        email.read = true;
+
+        let (text, signer) = match &email.body {
+            Body::Plain(text) => (text.clone(), String::new()),
+            Body::Sealed(envelope) => {
+                let text = match &self.keyring.own {
+                    Some(own) => envelope.open(own).unwrap_or_else(|_| {
+                        String::from("This message was not encrypted for you.")
+                    }),
+                    None => String::from("Generate or restore your key to read this message."),
+                };
+
+                (text, envelope.sender.name.clone())
+            }
+        };
+
+        self.reading = Some(Reading {
+            index,
+            text,
+            verification: self.keyring.verify(email),
+            signer,
+        });
+        self.exported.clear();
+        self.save_mailbox();
+    }
+
+    fn send(&mut self, secure: bool) {
+        if self.to.is_empty() {
+            self.status = String::from("Who is this message for?");
+            return;
+        }
+
+        let from = match &self.keyring.own {
+            Some(own) => own.name.clone(),
+            None => String::from("me"),
+        };
+
+        let body = if secure {
+            let own = match &self.keyring.own {
+                Some(own) => own,
+                None => {
+                    self.status = String::from("Generate your key before sending encrypted mail");
+                    return;
+                }
+            };
+
+            let recipient = if own.name == self.to {
+                own.public().ok()
+            } else {
+                self.keyring.contact(&self.to).cloned()
+            };
+
+            let recipient = match recipient {
+                Some(recipient) => recipient,
+                None => {
+                    self.status = format!("No key for {}, import it in keys first", self.to);
+                    return;
+                }
+            };
+
+            match Envelope::seal(own, &[&recipient], &self.to, &self.subject, &self.body) {
+                Ok(envelope) => Body::Sealed(envelope),
+                Err(_) => {
+                    self.status = String::from("Could not encrypt the message");
+                    return;
+                }
+            }
+        } else {
+            Body::Plain(self.body.clone())
+        };
+
+        self.mailbox
+            .emails
+            .push(Email::new(&from, &self.to, &self.subject, body, "sent"));
+        self.to.clear();
+        self.subject.clear();
+        self.body.clear();
+        self.status = String::from("Sent, open it from sent to export it");
+        self.save_mailbox();
+    }
+
+    fn save_mailbox(&mut self) {
+        if self.mailbox.save().is_err() {
+            self.status = String::from("Could not save the mailbox");
+        }
+    }
+
+    fn save_keyring(&mut self) {
+        if self.keyring.save().is_err() {
+            self.status = String::from("Could not save the keyring");
+        }
+    }
+
+    fn save_rules(&mut self) {
+        if self.rules.save().is_err() {
+            self.status = String::from("Could not save the rules");
+        }
+    }
+
+    fn view(&mut self, section: &str, is_secure: bool) -> Column<StepMessage> {
+        let folders = self.mailbox.folders();
+        let tags = self.mailbox.tags();
+
+        let Mail {
+            mailbox,
+            keyring,
+            rules,
+            rule_editor,
+            reading,
+            status,
+            to,
+            subject,
+            body,
+            exported,
+            paste,
+            key_name,
+            key_import,
+            secret_shown,
+            secret_import,
+            to_input,
+            subject_input,
+            body_input,
+            exported_input,
+            paste_input,
+            key_name_input,
+            key_import_input,
+            own_key_input,
+            secret_key_input,
+            secret_import_input,
+            send_button,
+            close_button,
+            export_button,
+            import_email_button,
+            generate_button,
+            import_key_button,
+            secret_button,
+            restore_button,
+            folder_buttons,
+        } = self;
+
+        let content = match section {
+            "compose" => Column::new()
+                .padding(10)
+                .spacing(10)
+                .push(TextInput::new(to_input, "To", to, StepMessage::ComposeToChanged).padding(5))
+                .push(
+                    TextInput::new(
+                        subject_input,
+                        "Subject",
+                        subject,
+                        StepMessage::ComposeSubjectChanged,
+                    )
+                    .padding(5),
+                )
+                .push(
+                    TextInput::new(body_input, "Message", body, StepMessage::ComposeBodyChanged)
+                        .padding(5),
+                )
+                .push(Checkbox::new(
+                    is_secure,
+                    "Encrypt",
+                    StepMessage::ToggleSecureInput,
+                ))
+                .push(action_button(send_button, "Send", StepMessage::SendPressed)),
+            "keys" => {
+                let own = keyring
+                    .own
+                    .as_ref()
+                    .and_then(|own| own.public().ok().map(|public| (own, public)));
+
+                let own: Element<_> = match own {
+                    Some((own, public)) => {
+                        let mut column = Column::new()
+                            .spacing(5)
+                            .push(
+                                Text::new(format!(
+                                    "Your key: {} ({})",
+                                    public.name,
+                                    public.fingerprint()
+                                ))
+                                .color(Color::BLACK),
+                            )
+                            .push(
+                                TextInput::new(
+                                    own_key_input,
+                                    "",
+                                    &public.export(),
+                                    StepMessage::CopyFieldEdited,
+                                )
+                                .padding(5),
+                            )
+                            .push(action_button(
+                                secret_button,
+                                if *secret_shown {
+                                    "Hide secret key"
+                                } else {
+                                    "Show secret key"
+                                },
+                                StepMessage::ToggleSecretKey,
+                            ));
+
+                        if *secret_shown {
+                            column = column
+                                .push(
+                                    Text::new(
+                                        "Anyone with this key can read your mail and sign as you, \
+                                         only paste it on your own devices",
+                                    )
+                                    .color(Color::from_rgb(0.8, 0.1, 0.1)),
+                                )
+                                .push(
+                                    TextInput::new(
+                                        secret_key_input,
+                                        "",
+                                        &own.export(),
+                                        StepMessage::CopyFieldEdited,
+                                    )
+                                    .padding(5),
+                                );
+                        }
+
+                        column.into()
+                    }
+                    None => Column::new()
+                        .spacing(5)
+                        .push(
+                            Row::new()
+                                .spacing(5)
+                                .push(
+                                    TextInput::new(
+                                        key_name_input,
+                                        "Your name",
+                                        key_name,
+                                        StepMessage::KeyNameChanged,
+                                    )
+                                    .padding(5),
+                                )
+                                .push(action_button(
+                                    generate_button,
+                                    "Generate",
+                                    StepMessage::GenerateKeys,
+                                )),
+                        )
+                        .push(
+                            Row::new()
+                                .spacing(5)
+                                .push(
+                                    TextInput::new(
+                                        secret_import_input,
+                                        "Or paste your secret key to restore it",
+                                        secret_import,
+                                        StepMessage::SecretImportChanged,
+                                    )
+                                    .padding(5),
+                                )
+                                .push(action_button(
+                                    restore_button,
+                                    "Restore",
+                                    StepMessage::RestoreKey,
+                                )),
+                        )
+                        .into(),
+                };
+
+                let contacts = keyring
+                    .contacts
+                    .iter()
+                    .map(|keys| format!("{} ({})", keys.name, keys.fingerprint()))
+                    .collect();
+
+                Column::new()
+                    .padding(10)
+                    .spacing(10)
+                    .push(own)
+                    .push(
+                        Row::new()
+                            .spacing(5)
+                            .push(
+                                TextInput::new(
+                                    key_import_input,
+                                    "Paste a key to import",
+                                    key_import,
+                                    StepMessage::KeyImportChanged,
+                                )
+                                .padding(5),
+                            )
+                            .push(action_button(
+                                import_key_button,
+                                "Import",
+                                StepMessage::ImportKey,
+                            )),
+                    )
+                    .push(new_column(contacts))
+            }
+            "folders" => {
+                let is_empty = folders.is_empty();
+                folder_buttons.resize_with(folders.len(), Default::default);
+
+                let mut column = folder_buttons.iter_mut().zip(folders).fold(
+                    Column::new().padding(10).spacing(5),
+                    |column, (state, folder)| {
+                        column.push(
+                            Button::new(state, Text::new(folder.as_str()).color(Color::BLACK))
+                                .on_press(StepMessage::NavButtonPressed(format!(
+                                    "{}{}",
+                                    FOLDER_SECTION, folder
+                                )))
+                                .padding(3)
+                                .style(styles::Button::Icon),
+                        )
+                    },
+                );
+
+                if is_empty {
+                    column = column.push(Text::new("No folders").color(Color::BLACK));
+                }
+
+                column
+            }
+            "tags" => new_column(tags),
+            "rules" => rule_editor.view(rules),
+            folder => match reading {
+                Some(reading) => {
+                    let email = &mailbox.emails[reading.index];
+
+                    let mut column = Column::new()
+                        .padding(10)
+                        .spacing(10)
+                        .push(Text::new(format!("From: {}", email.from)).color(Color::BLACK))
+                        .push(Text::new(format!("To: {}", email.to)).color(Color::BLACK))
+                        .push(Text::new(&email.subject).size(30).color(Color::BLACK));
+
+                    if let Some(verification) = reading.verification {
+                        let color = match verification {
+                            Verification::Verified => Color::from_rgb(0.1, 0.6, 0.1),
+                            Verification::UnknownKey => Color::from_rgb(0.8, 0.5, 0.0),
+                            Verification::WrongSender | Verification::BadSignature => {
+                                Color::from_rgb(0.8, 0.1, 0.1)
+                            }
+                        };
+
+                        column = column
+                            .push(Text::new(verification.badge(&reading.signer)).color(color));
+                    }
+
+                    column = column
+                        .push(Text::new(&reading.text).color(Color::BLACK))
+                        .push(
+                            Row::new()
+                                .spacing(10)
+                                .push(action_button(close_button, "Back", StepMessage::CloseEmail))
+                                .push(action_button(
+                                    export_button,
+                                    "Export",
+                                    StepMessage::ExportEmail,
+                                )),
+                        );
+
+                    if !exported.is_empty() {
+                        column = column.push(
+                            TextInput::new(
+                                exported_input,
+                                "",
+                                exported,
+                                StepMessage::CopyFieldEdited,
+                            )
+                            .padding(5),
+                        );
+                    }
+
+                    column
+                }
+                None => {
+                    // custom folders are prefixed so that one named like a section stays a folder
+                    let folder = if folder.starts_with(FOLDER_SECTION) {
+                        &folder[FOLDER_SECTION.len()..]
+                    } else if folder.is_empty() {
+                        "inbox"
+                    } else {
+                        folder
+                    };
+                    let is_empty = mailbox.in_folder(folder).is_empty();
+
+                    let mut column = mailbox
+                        .emails
+                        .iter_mut()
+                        .enumerate()
+                        .filter(|(_, email)| email.folder == folder)
+                        .fold(
+                            Column::new().padding(10).spacing(5),
+                            |column, (i, email)| {
+                                let label = format!(
+                                    "{}{} - {}{}",
+                                    if email.read { "" } else { "* " },
+                                    email.from,
+                                    email.subject,
+                                    if email.is_secure() { " [secure]" } else { "" }
+                                );
+
+                                column.push(
+                                    Button::new(
+                                        &mut email.open_button,
+                                        Text::new(label).color(Color::BLACK),
+                                    )
+                                    .on_press(StepMessage::OpenEmail(i))
+                                    .padding(3)
+                                    .style(styles::Button::Icon),
+                                )
+                            },
+                        );
+
+                    if is_empty {
+                        column = column.push(Text::new("No messages").color(Color::BLACK));
+                    }
+
+                    if folder == "inbox" {
+                        column = column.push(
+                            Row::new()
+                                .spacing(5)
+                                .push(
+                                    TextInput::new(
+                                        paste_input,
+                                        "Paste an exported message",
+                                        paste,
+                                        StepMessage::PasteChanged,
+                                    )
+                                    .padding(5),
+                                )
+                                .push(action_button(
+                                    import_email_button,
+                                    "Import",
+                                    StepMessage::ImportEmail,
+                                )),
+                        );
+                    }
+
+                    column
+                }
+            },
+        };
+
+        content.push(Text::new(status.as_str()).color(Color::BLACK))
+    }
+}
+
+// Form to build a rule: filled fields become conditions and actions
+#[derive(Default)]
+struct RuleEditor {
+    name: String,
+    from: String,
+    subject: String,
+    tag: String,
+    folder: String,
+    mark_read: bool,
+    name_input: text_input::State,
+    from_input: text_input::State,
+    subject_input: text_input::State,
+    tag_input: text_input::State,
+    folder_input: text_input::State,
+    add_button: button::State,
+    apply_button: button::State,
+    delete_buttons: Vec<button::State>,
+}
+
+impl RuleEditor {
+    fn update(&mut self, msg: StepMessage) {
+        match msg {
+            StepMessage::RuleNameChanged(name) => self.name = name,
+            StepMessage::RuleFromChanged(from) => self.from = from,
+            StepMessage::RuleSubjectChanged(subject) => self.subject = subject,
+            StepMessage::RuleTagChanged(tag) => self.tag = tag,
+            StepMessage::RuleFolderChanged(folder) => self.folder = folder,
+            StepMessage::RuleMarkReadToggled(mark_read) => self.mark_read = mark_read,
+            _ => {}
+        }
+    }
+
+    fn rule(&self) -> Option<Rule> {
+        let mut conditions = Vec::new();
+        if !self.from.is_empty() {
+            conditions.push(Condition::FromContains(self.from.clone()));
+        }
+        if !self.subject.is_empty() {
+            conditions.push(Condition::SubjectContains(self.subject.clone()));
+        }
+
+        let mut actions = Vec::new();
+        if !self.tag.is_empty() {
+            actions.push(Action::Tag(self.tag.clone()));
+        }
+        if !self.folder.is_empty() {
+            actions.push(Action::MoveTo(self.folder.clone()));
+        }
+        if self.mark_read {
+            actions.push(Action::MarkRead);
+        }
+
+        if self.name.is_empty() || conditions.is_empty() || actions.is_empty() {
+            None
+        } else {
+            Some(Rule::new(&self.name, conditions, actions))
+        }
+    }
+
+    fn view<'a>(&'a mut self, rules: &'a Rules) -> Column<'a, StepMessage> {
+        let RuleEditor {
+            name,
+            from,
+            subject,
+            tag,
+            folder,
+            mark_read,
+            name_input,
+            from_input,
+            subject_input,
+            tag_input,
+            folder_input,
+            add_button,
+            apply_button,
+            delete_buttons,
+        } = self;
+
+        delete_buttons.resize_with(rules.rules.len(), Default::default);
+
+        let list = rules
+            .rules
+            .iter()
+            .zip(delete_buttons.iter_mut())
+            .enumerate()
+            .fold(Column::new().spacing(5), |column, (i, (rule, state))| {
+                column.push(
+                    Row::new()
+                        .spacing(10)
+                        .push(Text::new(rule.describe()).color(Color::BLACK))
+                        .push(
+                            Button::new(state, Text::new("Delete"))
+                                .on_press(StepMessage::DeleteRule(i))
+                                .padding(5)
+                                .style(styles::Button::Destructive),
+                        ),
+                )
+            });
+
+        Column::new()
+            .padding(10)
+            .spacing(10)
+            .push(list)
+            .push(
+                TextInput::new(name_input, "Rule name", name, StepMessage::RuleNameChanged)
+                    .padding(5),
+            )
+            .push(
+                TextInput::new(
+                    from_input,
+                    "If from contains",
+                    from,
+                    StepMessage::RuleFromChanged,
+                )
+                .padding(5),
+            )
+            .push(
+                TextInput::new(
+                    subject_input,
+                    "and subject contains",
+                    subject,
+                    StepMessage::RuleSubjectChanged,
+                )
+                .padding(5),
+            )
+            .push(
+                TextInput::new(tag_input, "then tag", tag, StepMessage::RuleTagChanged).padding(5),
+            )
+            .push(
+                TextInput::new(
+                    folder_input,
+                    "and move to folder",
+                    folder,
+                    StepMessage::RuleFolderChanged,
+                )
+                .padding(5),
+            )
+            .push(Checkbox::new(
+                *mark_read,
+                "and mark read",
+                StepMessage::RuleMarkReadToggled,
+            ))
+            .push(
+                Row::new()
+                    .spacing(10)
+                    .push(action_button(add_button, "Add rule", StepMessage::AddRule))
+                    .push(action_button(
+                        apply_button,
+                        "Apply to inbox",
+                        StepMessage::ApplyRules,
+                    )),
+            )
+    }
+}
+
+fn action_button<'a>(
+    state: &'a mut button::State,
+    label: &str,
+    message: StepMessage,
+) -> Button<'a, StepMessage> {
+    Button::new(state, Text::new(label).color(Color::BLACK))
+        .on_press(message)
+        .padding(5)
+        .style(styles::Button::Filter { selected: false })
+}
+
 fn button<'a, Message>(state: &'a mut button::State, label: &str) -> Button<'a, Message> {
     Button::new(
         state,